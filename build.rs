@@ -29,6 +29,8 @@ fn main() -> SdResult<()> {
 }
 
 fn append_write_const(mut file: &File) -> SdResult<()> {
+    // shadow-rs leaves a blank line after its "generated by" doc comment
+    writeln!(file, "#[allow(clippy::empty_line_after_doc_comments)]")?;
     if git_clean() {
         writeln!(file, r#"pub const DIRTY_SUFFIX: &str = "";"#)?;
         writeln!(file, r#"pub const DIRTY_LINE: &str = "";"#)?;
@@ -0,0 +1,145 @@
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+/// An expectation of how many records are selected, for '--assert', like
+/// `count >= 1`, checked after the search.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct Assertion {
+    scope: Scope,
+    op: Op,
+    n: usize,
+}
+
+/// What's counted.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Scope {
+    /// All files' selected records, together.
+    Total,
+    /// Each file's selected records, on its own.
+    EachFile,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Op {
+    const ALL: [(&'static str, Op); 6] = [
+        ("==", Op::Eq),
+        ("!=", Op::Ne),
+        ("<=", Op::Le),
+        (">=", Op::Ge),
+        ("<", Op::Lt),
+        (">", Op::Gt),
+    ];
+
+    fn symbol(self) -> &'static str {
+        Op::ALL.iter().find(|(_, op)| *op == self).unwrap().0
+    }
+}
+
+impl Assertion {
+    /// Check the assertion against files' counts of selected records,
+    /// returning a description of each failure.
+    pub(crate) fn check<'a>(
+        &self,
+        counts: impl IntoIterator<Item = (&'a str, usize)>,
+    ) -> Vec<String> {
+        match self.scope {
+            Scope::Total => {
+                let total = counts.into_iter().map(|(_, n)| n).sum();
+                if self.holds(total) {
+                    vec![]
+                } else {
+                    vec![format!("{self}, but it was {total}")]
+                }
+            }
+            Scope::EachFile => counts
+                .into_iter()
+                .filter(|&(_, n)| !self.holds(n))
+                .map(|(f, n)| format!("{self}, but it was {n} for {f}"))
+                .collect(),
+        }
+    }
+
+    fn holds(&self, count: usize) -> bool {
+        match self.op {
+            Op::Eq => count == self.n,
+            Op::Ne => count != self.n,
+            Op::Lt => count < self.n,
+            Op::Le => count <= self.n,
+            Op::Gt => count > self.n,
+            Op::Ge => count >= self.n,
+        }
+    }
+}
+
+impl FromStr for Assertion {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid =
+            || format!("'{s}' is not an assertion, like 'count >= 1' or 'file-count == 0'");
+        let s = s.trim();
+        let (scope, rest) = if let Some(rest) = s.strip_prefix("file-count") {
+            (Scope::EachFile, rest)
+        } else if let Some(rest) = s.strip_prefix("count") {
+            (Scope::Total, rest)
+        } else {
+            return Err(invalid());
+        };
+        let rest = rest.trim_start();
+        let (op, rest) = Op::ALL
+            .iter()
+            .find_map(|&(sym, op)| rest.strip_prefix(sym).map(|r| (op, r)))
+            .ok_or_else(invalid)?;
+        let n = rest.trim().parse().map_err(|_| invalid())?;
+        Ok(Assertion { scope, op, n })
+    }
+}
+
+impl Display for Assertion {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let scope = match self.scope {
+            Scope::Total => "count",
+            Scope::EachFile => "file-count",
+        };
+        write!(f, "{scope} {} {}", self.op.symbol(), self.n)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let a: Assertion = "count>=1".parse().unwrap();
+        assert_eq!("count >= 1", a.to_string());
+        let a: Assertion = " file-count  ==  0 ".parse().unwrap();
+        assert_eq!("file-count == 0", a.to_string());
+        assert!("count => 1".parse::<Assertion>().is_err());
+        assert!("records > 1".parse::<Assertion>().is_err());
+        assert!("count > lots".parse::<Assertion>().is_err());
+    }
+
+    #[test]
+    fn check() {
+        let counts = [("a.log", 0), ("b.log", 3)];
+        let total: Assertion = "count < 3".parse().unwrap();
+        assert_eq!(vec!["count < 3, but it was 3"], total.check(counts));
+        let total: Assertion = "count != 0".parse().unwrap();
+        assert!(total.check(counts).is_empty());
+        let each: Assertion = "file-count > 0".parse().unwrap();
+        assert_eq!(
+            vec!["file-count > 0, but it was 0 for a.log"],
+            each.check(counts)
+        );
+    }
+}
@@ -1,22 +1,45 @@
+use std::fmt::{self, Display, Formatter};
+use std::io::{self, Write};
+use std::num::NonZeroUsize;
+use std::time::Duration;
+
 use anyhow::{Context, Result};
 use clap::{ColorChoice, CommandFactory, Parser};
+use jiff::civil::Date;
+use jiff::SignedDuration;
 
+use crate::assertion::Assertion;
+use crate::level::Level;
+use crate::pair::PairReport;
+use crate::read::encoding::Encoding;
+use crate::read::index::IndexMode;
+use crate::read::traverse::FileOrder;
+use crate::read::Directories;
+use crate::write::compress::OutputCompression;
+use crate::write::json;
+use crate::write::Wrap;
 use crate::Exit;
 use crate::Exit::Help;
 
 shadow_rs::shadow!(build);
 
-#[cfg(not(target_os = "windows"))]
 const COMPRESSED_FILES: &str = "COMPRESSED FILES:
 \n\
-                       Files (and STDIN) will be automatically decompressed, assuming appropriate \
-                       utilities are available on your `$PATH`. That is, `gzcat log.gz | lgrep ERROR` \
-                       is unneeded; just do `lgrep ERROR log.gz` (but don't do `zlgrep ERROR log.gz`). \
-                       This feature is not available on Windows.
+                       Files (and STDIN) will be automatically decompressed, recognized by their \
+                       extension, or else their content. That is, `gzcat log.gz | lgrep ERROR` is \
+                       unneeded; just do `lgrep ERROR log.gz` (but don't do `zlgrep ERROR log.gz`). \
+                       gzip, zstd, bzip2, xz, and lz4 are decompressed in-process; only compress \
+                       needs its utility (`gzip`) on your `$PATH`.
 \n\
                        ";
 
-const BASE_LONG_HELP: &str = "ENVIRONMENT:
+const BASE_LONG_HELP: &str = "OUTPUT ORDER:
+\n\
+                       Output is deterministic: files are searched and reported in the order \
+                       given, and each file's records are output in the order they appear in it. \
+                       So the output of two runs over the same inputs can be reliably diffed.
+\n\
+                       ENVIRONMENT:
 \n\
                        The `LGREP_LOG_PATTERN` environment variable may be used to default the \
                        '--log-pattern' option, if you consistently need a different start-of-record \
@@ -24,7 +47,8 @@ const BASE_LONG_HELP: &str = "ENVIRONMENT:
 \n\
                        The `GREP_COLORS` environment variable will be used to color output, in \
                        similar manner as `grep`. All `grep` capabilities are accepted, but not all \
-                       affect output. For example, `lgrep` doesn't have context lines.
+                       affect output. As `lgrep` doesn't have context lines, `sl` and `cx` style \
+                       the lines of a selected record which do and don't contain a match.
 \n\
                        There is no support for a `GREP_OPTIONS` equivalent. Use a shell function.";
 
@@ -42,13 +66,95 @@ pub(crate) struct Cli {
     /// Pattern to search.
     ///
     /// Like `grep`, if any PATTERN are passed with '-e', all positional params
-    /// are considered filenames.
+    /// are considered filenames. An empty PATTERN selects every record without
+    /// any searching, which with '--start' and/or '--end' makes a fast slicer.
     #[arg()]
     pub pattern: Option<String>,
 
     /// File(s) to search. If omitted or '-', search STDIN.
+    ///
+    /// Each file in a tar archive (e.g., `bundle.tar.gz` or `bundle.tgz`) is searched as its own
+    /// file, named like `bundle.tar.gz!logs/app.log`.
+    ///
+    /// If built with the 's3' feature, `s3://bucket/key` objects may be searched too. They're
+    /// streamed with the AWS CLI, using its usual credentials and configuration.
     pub files: Vec<String>,
 
+    /// What to do with a directory FILE: read, skip, or recurse.
+    ///
+    /// Like `grep`, 'read' tries to read it as a file (which fails), and 'recurse' is the same as
+    /// '-r'. By default, directories are skipped with a warning.
+    #[arg(short = 'd', long, value_name = "ACTION", default_value = "skip")]
+    pub directories: Directories,
+
+    /// Search the files in directories, recursively.
+    ///
+    /// Files are searched in name order within each directory, and symbolic links found while
+    /// recursing are not followed. If no FILE is given, the current directory is searched.
+    #[arg(short = 'r', long)]
+    pub recursive: bool,
+
+    /// When recursing, search a file reached by several paths each time.
+    ///
+    /// By default, a file reached more than once (e.g., via a symlinked directory given as a FILE,
+    /// overlapping directories, or a hard link) is only searched the first time, so its matches
+    /// aren't counted twice.
+    #[arg(long)]
+    pub no_dedupe_files: bool,
+
+    /// When recursing, only search files whose name matches glob.
+    ///
+    /// May be repeated, and a file matching any of them is searched. Globs support '*', '?', and
+    /// '[...]', and one with a '/' is matched against the file's path rather than just its name.
+    /// Files given on the command line are always searched.
+    #[arg(long, value_name = "GLOB")]
+    pub include: Vec<String>,
+
+    /// When recursing, skip files whose name matches glob, even if included.
+    ///
+    /// May be repeated, e.g., `-r --include '*.log*' --exclude '*.gz'`. Globs are as for
+    /// '--include'.
+    #[arg(long, value_name = "GLOB")]
+    pub exclude: Vec<String>,
+
+    /// When recursing, skip directories whose name matches glob, without descending into them.
+    ///
+    /// May be repeated, e.g., `-r --exclude-dir .git --exclude-dir archive`. Globs are as for
+    /// '--include', and a trailing '/' is ignored. Directories given on the command line are
+    /// always searched.
+    #[arg(long, value_name = "GLOB")]
+    pub exclude_dir: Vec<String>,
+
+    /// Only search files modified since WHEN, an age (like '2d' or '36h') or a time.
+    ///
+    /// A time is like '2024-07-01 01:25:47', or just a date, meaning its start, in the local time
+    /// zone (see '--assume-tz'). Applies to every file, whether given on the command line or found
+    /// while recursing, and files skipped this way aren't opened at all. Handy with a glob matching
+    /// many rotated logs, e.g., `lgrep --newer-than 2d ERROR logs/*`.
+    #[arg(long, value_name = "WHEN")]
+    pub newer_than: Option<String>,
+
+    /// Only search files last modified before WHEN, an age (like '2d') or a time.
+    ///
+    /// As for '--newer-than', which it may be combined with, to search files modified in a window.
+    #[arg(long, value_name = "WHEN")]
+    pub older_than: Option<String>,
+
+    /// Search (and report) files in this order: name, mtime, or size, with ':desc' to reverse it.
+    ///
+    /// By default, files are searched in the order given (and found, when recursing). E.g.,
+    /// `--sort-files mtime:desc` searches the most recently modified logs first. Files which tie
+    /// stay in the order given, and STDIN is last.
+    #[arg(long, value_name = "ORDER")]
+    pub sort_files: Option<FileOrder>,
+
+    /// Skip files larger than this, like '2G', with a notice to STDERR.
+    ///
+    /// The size is on disk, so before any decompression. Handy for broad recursive searches, so
+    /// one giant file (e.g., a core dump) doesn't dominate. Skipped files are listed in '--stats'.
+    #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+    pub max_filesize: Option<usize>,
+
     /// Additional patterns to search.
     ///
     /// Unlike `grep`, a syntax error in PATTERN will exit with a helpful message and a non-zero
@@ -56,6 +162,102 @@ pub(crate) struct Cli {
     #[arg(short = 'e', long = "regexp", value_name = "PATTERN")]
     pub patterns: Vec<String>,
 
+    /// Read patterns from PATTERNFILE, one per line.
+    ///
+    /// They're searched for along with any '-e' patterns, so a curated list of known signatures can
+    /// be applied in one pass. Like `grep`, an empty line matches every record, and a file with no
+    /// lines matches nothing.
+    ///
+    /// A few directives give a shared pattern library its meaning: '# flags: i' sets inline flags
+    /// (e.g., 'i' to ignore case, or 'x' for verbose patterns) for the lines after it, until the
+    /// next ('# flags:' clears them); any other line starting with '#' is a comment; '! PATTERN'
+    /// is a negative pattern, so records it matches aren't selected (e.g., known-benign errors);
+    /// and 'label: NAME PATTERN' labels the pattern, as '--pattern-label' does. A file of only
+    /// negative patterns selects every record they don't match. Escape a leading '#' or '!' with a
+    /// '\' to search for it.
+    #[arg(short = 'f', long = "file", value_name = "PATTERNFILE")]
+    pub pattern_files: Vec<String>,
+
+    /// Skip records which take longer than this to match, like '50ms'.
+    ///
+    /// A pathological pattern and record combination can take a long time to match. Records are
+    /// matched on another thread, which is given up on at the deadline, so an over-budget record is
+    /// skipped at once, with a warning, rather than being highlighted, counted, or printed. Its
+    /// matching can't be interrupted, so finishes in the background. Skipped records are counted
+    /// in '--stats'.
+    #[arg(long, value_name = "DURATION", value_parser = parse_timeout)]
+    pub match_timeout: Option<Duration>,
+
+    /// Interpret PATTERNs as fixed strings, not regular expressions.
+    ///
+    /// Handy for pasted exception messages, full of '(', '[', and '+'. Only applies to the main
+    /// pattern(s), not the log/start/end patterns.
+    #[arg(short = 'F', long)]
+    pub fixed_strings: bool,
+
+    /// A label for a '-e' pattern, in the same order.
+    ///
+    /// The first label names the first '-e' pattern, the second the second, and so on, so
+    /// `-e timeout --pattern-label TO -e refused --pattern-label REFUSED` reads naturally. With
+    /// '--json', each record lists the labels of its matching patterns, so saved results carry
+    /// categories instead of regex text.
+    #[arg(long, value_name = "LABEL", requires = "patterns")]
+    pub pattern_label: Vec<String>,
+
+    /// Read patterns of known, benign records to leave out from FILE, one per line.
+    ///
+    /// Records which are otherwise selected, but match any of them, aren't, like `grep -v -f
+    /// known.txt` on whole records rather than lines. FILE is read like a '-f' PATTERNFILE, so
+    /// comments and '# flags:' may be used, but not negative patterns, and blank lines are
+    /// skipped. May be repeated.
+    #[arg(long, value_name = "FILE")]
+    pub suppress_file: Vec<String>,
+
+    /// Write how many records were left out by '--suppress-file' to standard error.
+    #[arg(long, requires = "suppress_file")]
+    pub show_suppressed_count: bool,
+
+    /// Pair up records matching START with later ones matching END, by a shared key.
+    ///
+    /// Only START and END are searched for, instead of any PATTERN, so all positional params are
+    /// considered filenames. When an END record follows a START record with the same
+    /// '--pair-key' (e.g., a request ID), both are written, the END annotated with the time
+    /// between them, if both have timestamps. So `--pair 'begin (\w+)' 'done (\w+)'` shows each
+    /// request's begin and end together. An END without a START isn't written, and a START
+    /// replaces any still waiting with the same key.
+    #[arg(
+        long,
+        num_args = 2,
+        value_names = ["START", "END"],
+        conflicts_with_all = [
+            "patterns",
+            "pattern_files",
+            "invert_match",
+            "count",
+            "count_by",
+            "count_distinct",
+            "cluster",
+            "files_with_matches",
+            "files_without_match",
+            "context_pattern",
+        ]
+    )]
+    pub pair: Vec<String>,
+
+    /// The capture group of the '--pair' patterns which is a record's key, by number or name.
+    ///
+    /// A name may instead be a named group of the log pattern, like a thread.
+    #[arg(long, value_name = "GROUP", default_value = "1")]
+    pub pair_key: String,
+
+    /// Instead of writing each '--pair', report on them once all FILEs are searched.
+    ///
+    /// With 'unmatched', each START record no END followed, and each END record which followed no
+    /// START, is written, annotated with what it lacks. Those are the requests which hung, or
+    /// began before the logs do.
+    #[arg(long, value_name = "REPORT", requires = "pair")]
+    pub pair_report: Option<PairReport>,
+
     /// Perform case-insensitive matching.
     ///
     /// By default, `lgrep` is case-sensitive. Note that this flag applies to ALL patterns,
@@ -67,16 +269,64 @@ pub(crate) struct Cli {
     #[arg(short, long)]
     pub ignore_case: bool,
 
+    /// Disable Unicode in all patterns, for speed on ASCII-only logs.
+    ///
+    /// Classes like `\w`, `\d`, and `\s`, case-insensitivity, and word boundaries become
+    /// ASCII-only, and '.' matches any single byte. On large logs this can be significantly
+    /// faster. Unicode may be re-enabled within a pattern via `(?u)`.
+    #[arg(long)]
+    pub no_unicode: bool,
+
+    /// Keep word boundaries Unicode-aware, when '--no-unicode' is given.
+    ///
+    /// Ignored without '--no-unicode'.
+    #[arg(long)]
+    pub unicode_word_boundaries: bool,
+
     /// Stop reading the file after num matches.
     #[arg(short, long, value_name = "NUM")]
     pub max_count: Option<usize>,
 
+    /// Exit with status 3 if '--max-count' left selected records unprinted.
+    ///
+    /// Instead of stopping at num matches, the rest of the file is searched for one more selected
+    /// record. If there is one, a note is printed to STDERR, and the exit status is 3 instead of
+    /// 0, so scripts can tell "there were more" from "that was all of them".
+    #[arg(long, requires = "max_count")]
+    pub truncation_status: bool,
+
+    /// Only select records with at least num matches of the pattern(s).
+    ///
+    /// Matches of all patterns are counted together, and counting stops once num is reached. For
+    /// example, `--min-matches 5 retry` finds records mentioning 'retry' five or more times.
+    #[arg(long, value_name = "NUM")]
+    pub min_matches: Option<usize>,
+
+    /// Only select records of at least this size, like '64K'.
+    ///
+    /// A record's size is the bytes of its text, including the newlines between its lines. Size
+    /// limits apply regardless of '-v'. For example, `--min-bytes 1M ''` finds giant payload dumps.
+    #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+    pub min_bytes: Option<usize>,
+
+    /// Only select records of at most this size, like '4K', to leave out giant payload dumps.
+    #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+    pub max_bytes_record: Option<usize>,
+
     /// Each output line is preceded by its relative line number in the file, starting at line 1.
     ///
     /// The line number counter is reset for each file processed.  This option is ignored if -c  is specified.
     #[arg(short = 'n', long)]
     pub line_number: bool,
 
+    /// Each output line is preceded by its record's number in the file, starting at record 1.
+    ///
+    /// Like line numbers, the record number counter is reset for each file, and it comes before
+    /// any line number. It's colored by the 'rn' capability of `GREP_COLORS`. This option is
+    /// ignored if -c is specified.
+    #[arg(long)]
+    pub record_number: bool,
+
     /// Selected lines are those NOT matching any of the specified patterns.
     ///
     /// Does not impact log/start/end patterns, only the main matching pattern(s).
@@ -84,9 +334,128 @@ pub(crate) struct Cli {
     pub invert_match: bool,
 
     /// Only a count of selected records is written to standard output.
+    ///
+    /// With '--quiet', nothing is written to standard output, but every file is searched, and the
+    /// total count across all files is written to standard error. So "is it bad, and how bad?"
+    /// needs only one pass.
     #[arg(short, long)]
     pub count: bool,
 
+    /// Count each occurrence of the patterns, not each selected record, like '--count'.
+    ///
+    /// One record can contain many hits (e.g., a stack trace naming a class on every frame), so
+    /// this counts the non-overlapping matches in each file's selected records. Output is the
+    /// same as '--count', and so is '--quiet''s total to standard error.
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "count",
+            "count_by",
+            "count_distinct",
+            "cluster",
+            "invert_match",
+            "files_with_matches",
+            "files_without_match",
+            "context_pattern",
+            "pair",
+            "follow",
+        ]
+    )]
+    pub count_matches: bool,
+
+    /// Count selected records by the text of a capture group, by number or name.
+    ///
+    /// Writes a `value<TAB>count` line for each distinct value across all files, most frequent
+    /// first, in place of the records. Records where the group didn't participate in the match
+    /// aren't counted. For example, `--count-by user 'login user=(?<user>\w+)'`. A name may also
+    /// be a field of the log pattern (see '--log-pattern').
+    #[arg(long, value_name = "GROUP", conflicts_with_all = ["count", "invert_match"])]
+    pub count_by: Option<String>,
+
+    /// Count how many distinct values a capture group took, by number or name.
+    ///
+    /// Writes the number of distinct values across all files' selected records, in place of the
+    /// records. For example, "how many users hit this error?" is `--count-distinct user
+    /// 'NullPointerException.*user=(?<user>\w+)'`. Records where the group didn't participate in
+    /// the match aren't counted. A name may also be a field of the log pattern.
+    #[arg(
+        long,
+        value_name = "GROUP",
+        conflicts_with_all = [
+            "count",
+            "count_by",
+            "invert_match",
+            "files_with_matches",
+            "files_without_match",
+        ]
+    )]
+    pub count_distinct: Option<String>,
+
+    /// Estimate '--count-distinct' in a fixed, small amount of memory.
+    ///
+    /// Instead of remembering every value, which can exhaust memory on huge scans with many
+    /// values, a HyperLogLog sketch estimates the count to within about 0.8% (one standard error).
+    /// The margin is written after the count.
+    #[arg(long, requires = "count_distinct")]
+    pub approx: bool,
+
+    /// Group selected records which are alike but for their numbers, writing the first of each.
+    ///
+    /// Numbers, and hexadecimal IDs, are wildcarded, so records differing only in timestamps,
+    /// IDs, counts, and such are one cluster. Each cluster's first record is written, after its
+    /// size (e.g., "×37"), largest first, in place of the records. Handy for triaging a log with
+    /// failures you don't know to look for yet.
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "count",
+            "count_by",
+            "count_distinct",
+            "files_with_matches",
+            "files_without_match",
+            "format",
+        ]
+    )]
+    pub cluster: bool,
+
+    /// Only select this fraction of the records which would otherwise be selected, like '0.1'.
+    ///
+    /// Sampling is deterministic, by a hash of each record's text, so repeated runs over the same
+    /// logs select the same records.
+    #[arg(long, value_name = "RATE", value_parser = parse_rate)]
+    pub sample: Option<f64>,
+
+    /// Sample consistently by the text of a capture group, by number or name.
+    ///
+    /// All records with the same value are kept or dropped together, so `--sample 0.1 --sample-by
+    /// user 'user=(?<user>\w+)'` keeps every record for a tenth of the users, rather than a tenth of
+    /// every user's records. Records where the group didn't participate in the match are dropped.
+    /// A name may also be a field of the log pattern (see '--log-pattern').
+    #[arg(
+        long,
+        value_name = "GROUP",
+        requires = "sample",
+        conflicts_with = "invert_match"
+    )]
+    pub sample_by: Option<String>,
+
+    /// Only the names of files containing selected records are written to standard output.
+    ///
+    /// Scanning will stop on the first selected record of each file.
+    #[arg(short = 'l', long, conflicts_with_all = ["count", "count_by"])]
+    pub files_with_matches: bool,
+
+    /// Only the names of files containing no selected records are written to standard output.
+    ///
+    /// Scanning will stop on the first selected record of each file. Handy for finding which of a
+    /// log's rotated files never saw some error.
+    #[arg(
+        short = 'L',
+        long,
+        conflicts_with_all = ["count", "count_by", "files_with_matches"]
+    )]
+    pub files_without_match: bool,
+
     /// Label to use in place of “(standard input)” for a file name where a file name would normally
     /// be printed.
     #[arg(long)]
@@ -108,14 +477,282 @@ pub(crate) struct Cli {
     )]
     pub color: ColorChoice,
 
+    /// Tint each selected record by its level: dim for DEBUG, yellow for WARN, red for ERROR.
+    ///
+    /// The level is the log pattern's `level` field, if it has one (see '--log-pattern'), or else
+    /// the first level-like word on the record's first line. Matches are still highlighted, and the
+    /// tint is used in place of the `sl` and `cx` styles. Has no effect unless output is colored.
+    #[arg(long)]
+    pub color_by_level: bool,
+
+    /// Highlight each pattern's matches in its own color.
+    ///
+    /// Colors are cycled through in the order patterns are given: red, green, yellow, blue,
+    /// magenta, and cyan, all bold. Handy for telling which of several '-e' patterns hit in a dense
+    /// record. Used in place of the `mt` style. Has no effect unless output is colored.
+    #[arg(long)]
+    pub color_by_pattern: bool,
+
+    /// Map nonstandard level names to levels, like 'SEVERE=ERROR,FINE=DEBUG'.
+    ///
+    /// For logging frameworks with their own severities, so level-aware options (like
+    /// '--color-by-level') understand them. Names are matched ignoring case, and the levels are
+    /// TRACE, DEBUG, INFO, WARN, ERROR, and FATAL (or an alias, like WARNING). A mapped name
+    /// overrides its usual meaning, if it has one. May be given more than once.
+    #[arg(
+        long,
+        value_name = "NAME=LEVEL,...",
+        value_delimiter = ',',
+        value_parser = parse_level_mapping
+    )]
+    pub level_map: Vec<(String, Level)>,
+
+    /// Hard-wrap long lines: never, at the terminal's width, or at N columns.
+    ///
+    /// A wrapped line's later rows are indented past its prefix (file name, line number, etc.),
+    /// and start with a '↪' marker, so very wide log lines stay readable. 'terminal' only wraps
+    /// when writing to one. By default, long lines are left to the terminal.
+    #[arg(long, value_name = "never|terminal|N", default_value = "never", value_parser = parse_wrap)]
+    pub wrap: Wrap,
+
+    /// Leave lines matching this pattern out of written records, like a stack trace's frames.
+    ///
+    /// Only output is affected: records are selected as if every line were there, and the written
+    /// lines keep their line numbers. A record's first line is always written.
+    #[arg(long, value_name = "PATTERN", conflicts_with = "json")]
+    pub omit_lines: Option<String>,
+
+    /// Write each selected record by a template, like '{file}:{line}: {text}'.
+    ///
+    /// The fields are `file`, `record` (number), `line` (number of the first line), `offset` (of
+    /// the record in its file), and `text` (the whole record), along with the pattern's capture
+    /// groups, by name or number, and the log pattern's fields (e.g. '{user}' or '{1}'). A group
+    /// which didn't participate in the match is left empty. Braces are doubled for literal ones.
+    /// The output is never colored.
+    #[arg(long, value_name = "TEMPLATE", conflicts_with = "json")]
+    pub format: Option<String>,
+
+    /// Only write lines of records matching this pattern, plus each record's first line.
+    ///
+    /// The complement of '--omit-lines', to pick just the interesting lines out of huge records.
+    /// Only output is affected, and the written lines keep their line numbers. With both, a line
+    /// is written if it's kept and not omitted.
+    #[arg(long, value_name = "PATTERN", conflicts_with = "json")]
+    pub keep_lines: Option<String>,
+
+    /// Write records much like the last one written in full as just their differences from it.
+    ///
+    /// A record is similar if it has as many lines, and most are the same, but for numbers (like
+    /// timestamps and IDs). Its first line is written, along with any lines which differ, and each
+    /// run of identical lines is replaced by a marker. So a loop's near-identical errors, stack
+    /// traces and all, take a few lines apiece. Only records of the same file are compared.
+    #[arg(long, conflicts_with_all = ["json", "format"])]
+    pub diff_similar: bool,
+
+    /// Write a run of selected records which are the same but for their timestamps only once.
+    ///
+    /// The first record of the run is written, and the rest replaced by a '... repeated N times'
+    /// marker, so a retry loop's thousands of identical errors take two lines. Timestamps are
+    /// ignored wherever they are in a record's first line. Only records of the same file are
+    /// compared, and the rest are still counted, e.g., by '--max-count'.
+    #[arg(long, conflicts_with_all = ["json", "context_pattern"])]
+    pub squeeze_repeats: bool,
+
+    /// Also write records matching this pattern near a selected record, as context.
+    ///
+    /// A record is near if it's within 10 records before or after one which is selected, e.g., to
+    /// always see the 'Started request' records around matched errors. Context records are styled
+    /// as context lines, without their matches highlighted, and all their lines are prefixed by a
+    /// '-' separator, rather than a ':'.
+    #[arg(
+        long,
+        value_name = "PATTERN",
+        conflicts_with_all = [
+            "count",
+            "count_by",
+            "count_distinct",
+            "cluster",
+            "files_with_matches",
+            "files_without_match",
+            "json",
+            "format",
+            "diff_similar",
+        ]
+    )]
+    pub context_pattern: Option<String>,
+
+    /// Only highlight matches on the first matching line of each record.
+    ///
+    /// Finding every match in a very large record can be costly. This trades fidelity for speed:
+    /// the rest of the record is printed unstyled. Has no effect unless output is colored.
+    #[arg(long)]
+    pub fast_highlight: bool,
+
+    /// Write each selected record as a line of JSON (i.e., NDJSON), instead of text.
+    ///
+    /// Each object has the record's `file`, `record` number, first `line` number, byte `offset` in
+    /// the (decompressed) file, and `text` (as it would be printed, less color), plus a `spans`
+    /// array of `offset`/`length`/`kind` objects marking the byte ranges of each `match`,
+    /// `filename`, `record` (number), and `line` (number) within the text, so highlighting can be
+    /// re-rendered without parsing escape sequences. For a rotation set, `file` is the member the
+    /// record starts in, and numbers and offsets are within it. Counts are written as
+    /// `file`/`count` objects, '--files-with-matches' names as `file` objects, and a
+    /// '--count-distinct' total as a `distinct` object, with an `error` if it's '--approx'. With
+    /// '--pattern-label', records also have a `patterns` array of their matching labels. Each line
+    /// is stamped with the `schema` version it follows; see '--json-schema'.
+    #[arg(long, conflicts_with = "annotate")]
+    pub json: bool,
+
+    /// Print the JSON Schema of the lines '--json' writes, and exit.
+    ///
+    /// Every line carries a `schema` field with the version it follows, which only changes if a
+    /// field is removed or changes meaning; new fields may be added within a version. Build
+    /// parsers against this, rather than against sample output.
+    #[arg(long)]
+    pub json_schema: bool,
+
+    /// After searching, print statistics about each file to STDERR.
+    ///
+    /// Each file's line has how many records were scanned and selected, how many lines and bytes
+    /// were read, and how long that took (with its throughput), where the start pattern
+    /// matched (or that it never did), and where the end pattern stopped the search early, if it
+    /// did. An active log which grew while it was searched is noted too, as only what it held
+    /// when opened is searched. A total line follows, if there was more than one file. Handy for
+    /// figuring out why a file produced nothing, or where the time went.
+    #[arg(long)]
+    pub stats: bool,
+
+    /// Print how each pattern fared on its own to STDERR, once all files are searched.
+    ///
+    /// For each pattern, how many records it matched of those searched, how long it took to
+    /// search one on average, and whether the regex engine could skip ahead to a literal it
+    /// starts with (a prefilter). Patterns without one are tried at every position, so are
+    /// usually the slow ones in a long '-e' list. Each pattern is searched for separately, as well
+    /// as all together, so this slows the search itself.
+    #[arg(long)]
+    pub pattern_stats: bool,
+
+    /// After searching, print a one-line verdict to STDERR.
+    ///
+    /// Like `matched 42 records across 3/5 files in 1.8s`, where a file counts if it had a
+    /// selected record (a rotation set searched with '--continue-records' is one file). If reading a
+    /// file fails partway, the search stops there, and the summary says so.
+    #[arg(long)]
+    pub summary: bool,
+
+    /// Log where the time goes to STDERR, at this level or filter (e.g., 'debug').
+    ///
+    /// Spans for opening and searching each file are at 'debug'; those for decompressing, splitting
+    /// records, matching, and writing are at 'trace', so are many. Each is logged with how long it
+    /// took, once it's done. The filter is like `RUST_LOG`'s, e.g., 'lgrep::read=trace'. Defaults
+    /// to `$LGREP_LOG`, if set. Needs a build with the 'trace' feature.
+    #[arg(long, value_name = "LEVEL")]
+    pub log_level: Option<String>,
+
+    /// After searching, check how many records were selected, like 'count >= 1'.
+    ///
+    /// `count` is the total across all files, and `file-count` is each file's own (a rotation set
+    /// searched with '--continue-records' is one file), compared with '==', '!=', '<', '<=', '>',
+    /// or '>='. Each failure is described on STDERR, and the exit status is 0 if every assertion
+    /// holds, or 1 if any doesn't, regardless of what matched. So lgrep can be a step of a test,
+    /// checking its logs. Counts are limited by '--max-count'. May be given more than once.
+    #[arg(
+        long,
+        value_name = "EXPR",
+        conflicts_with_all = ["files_with_matches", "files_without_match"]
+    )]
+    pub assert: Vec<Assertion>,
+
+    /// Write a JSON manifest of the run to FILE, for reproducible (and auditable) investigations.
+    ///
+    /// It has the exact invocation, every option's resolved value (and whether it came from the
+    /// command line, the environment, or a default), each input file's size and SHA-256, and each
+    /// file's results, as '--stats' would report them. Hashing reads every file a second time.
+    #[arg(long, value_name = "FILE")]
+    pub manifest: Option<String>,
+
+    /// Save this invocation as NAME, to re-run later with '--replay'.
+    ///
+    /// Invocations are saved in `lgrep/saved` in your config directory (`$XDG_CONFIG_HOME`, or
+    /// else `~/.config`; `%APPDATA%` on Windows), replacing any already saved as NAME. The search is
+    /// run as usual.
+    #[arg(long, value_name = "NAME")]
+    pub save: Option<String>,
+
+    /// Re-run the invocation saved as NAME (see '--save').
+    ///
+    /// Any other arguments are added to the saved ones, so more files can be searched, or options
+    /// given again to override their saved values. E.g., `lgrep --replay npe --since 2024-07-02
+    /// app.log.1`. Handy for recalling a triage command built up during an incident.
+    #[arg(long, value_name = "NAME")]
+    pub replay: Option<String>,
+
+    /// Also write output to FILE, without color.
+    ///
+    /// Standard output is unaffected (and colored per '--color'), so there's no need to choose
+    /// between watching the results and saving them. FILE is truncated if it exists, and is
+    /// compressed if it ends with `.gz` or `.zst`.
+    #[arg(long, value_name = "FILE")]
+    pub tee: Option<String>,
+
+    /// Write output to FILE, instead of standard output.
+    ///
+    /// FILE is truncated if it exists. If it ends with `.gz` or `.zst`, output is compressed
+    /// accordingly, as if '--compress-output' were specified.
+    #[arg(long, value_name = "FILE")]
+    pub output: Option<String>,
+
+    /// Compress output, with gzip (the default) or zstd.
+    ///
+    /// Large extractions can be compressed as they're written, without a separate step. Applies to
+    /// '--output' or standard output (e.g., `lgrep --compress-output ERROR app.log > errors.gz`).
+    /// Compressed output isn't colored, unless explicitly requested. A compressed stream is only
+    /// complete once ended, so it can't be combined with '--follow'.
+    #[arg(
+        long,
+        value_name = "FORMAT",
+        default_missing_value = "gzip",
+        num_args = 0..=1,
+        require_equals = true,
+        conflicts_with = "follow",
+    )]
+    pub compress_output: Option<OutputCompression>,
+
     /// Quiet; do not write anything to standard output.
     ///
-    /// Exit immediately with zero status if any match is found, making searches potentially less
-    /// expensive. Using --quiet is preferable to redirecting to `/dev/null` for this reason, even
-    /// though their behavior is basically equivalent.
+    /// Exit immediately with zero status if any match is found (unless '--count' is also given),
+    /// making searches potentially less expensive. Using --quiet is preferable to redirecting to
+    /// `/dev/null` for this reason, even though their behavior is basically equivalent.
     #[arg(short, long, visible_alias = "silent")]
     pub quiet: bool,
 
+    /// Maximum size of a compiled pattern (e.g., 20M).
+    ///
+    /// Giant patterns, such as generated alternations of thousands of IDs, may exceed the default
+    /// limit of 10M. Raising it allows them to compile, at the cost of memory.
+    #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+    pub regex_size_limit: Option<usize>,
+
+    /// Maximum memory used by each pattern's DFAs (e.g., 16M).
+    ///
+    /// This bounds both the ahead-of-time DFA (which is skipped if it would be larger) and the
+    /// lazily built DFA's cache. Larger values can speed up complex patterns.
+    #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+    pub dfa_size_limit: Option<usize>,
+
+    /// Treat leading lines of each FILE as a header, and print it before the file's first record.
+    ///
+    /// The header is either the first N lines, or the leading lines matching PATTERN, but never
+    /// extends past the first log record. Header lines aren't searched; they're printed once, just
+    /// before the first selected record of their file, so the output keeps the context of, for
+    /// example, a CSV header or startup banner.
+    #[arg(
+        long,
+        value_name = "N|PATTERN",
+        conflicts_with_all = ["record_delimiter_bytes", "null_data"]
+    )]
+    pub file_header: Option<String>,
+
     /// Pattern identifying the start of a log record.
     ///
     /// By default, assumes log records start with an ISO-8601-ish datetime with sub-second
@@ -129,21 +766,240 @@ pub(crate) struct Cli {
     /// Be careful if you pipe a multi-file `lgrep` into another `lgrep`! By default, the second
     /// `lgrep` will receive filename-prefixed lines, which your log pattern must gracefully handle.
     /// The default pattern accounts for this.
+    ///
+    /// Named capture groups (e.g., `(?<level>[A-Z]+)`) are fields of every record, taken from its
+    /// first line, and may be used by name wherever a group is expected, such as '--count-by'.
     #[arg(long, value_name = "PATTERN")]
     pub log_pattern: Option<String>,
 
+    /// Split records on a raw byte sequence, instead of log pattern and lines.
+    ///
+    /// For binary-ish framed streams. Use `\xHH` to specify arbitrary bytes, as well as `\0`,
+    /// `\n`, `\r`, `\t`, and `\\`; other characters stand for themselves. For example,
+    /// `--record-delimiter-bytes='\x1e'` splits on ASCII record separators. Invalid UTF-8 within
+    /// a record is replaced, and a newline just before a delimiter is dropped. Supersedes
+    /// '--log-pattern'.
+    #[arg(long, value_name = "BYTES")]
+    pub record_delimiter_bytes: Option<String>,
+
+    /// Treat input as a sequence of records, each terminated by a zero byte (NUL).
+    ///
+    /// Like `grep -z`, for exporters which write NUL-delimited blobs. It's the same as
+    /// `--record-delimiter-bytes='\0'`; records are still written out newline-terminated, so
+    /// multi-line records read as they would otherwise.
+    #[arg(short = 'z', long, conflicts_with = "record_delimiter_bytes")]
+    pub null_data: bool,
+
     /// Ignore records until this pattern is found in a file.
     ///
     /// The record containing the pattern WILL be searched, and if it matches, printed.
     #[arg(short = 'S', long, value_name = "PATTERN")]
     pub start: Option<String>,
 
+    /// Ignore records until one is timestamped at or after this time.
+    ///
+    /// The time is like a record's timestamp (e.g., `2024-07-01 01:25:47`), or just a date,
+    /// meaning its start. Like '--start', every record after the first such one is searched, with
+    /// or without a timestamp. Logs are assumed to be in order, so in a plain (uncompressed) file,
+    /// the first such record is found by binary search, instead of reading up to it, unless line
+    /// numbers or the like are needed, which can only be had by reading (but see '--index').
+    #[arg(long, value_name = "TIME", conflicts_with = "start")]
+    pub since: Option<String>,
+
+    /// Build a sidecar index of each FILE, or use it to seek to '--since'.
+    ///
+    /// `lgrep --index build FILE...` writes `FILE.lgrep-idx` beside each plain (uncompressed)
+    /// FILE, instead of searching it, with where records start, and when they were, about every
+    /// MiB. Then, `lgrep --index use --since TIME PATTERN FILE` starts reading at the last record
+    /// indexed before TIME, with the record and line numbers it'd have had reading from the start,
+    /// so numbered and JSON output can skip ahead too. An index stays good while its FILE only
+    /// grows; one which isn't (or was built with another log pattern) is ignored, with a warning.
+    #[arg(long, value_name = "MODE", conflicts_with_all = ["follow", "merge"])]
+    pub index: Option<IndexMode>,
+
+    /// Give up on a FILE if the start pattern isn't found within a budget.
+    ///
+    /// The budget is a size of (decompressed) record text, like '64M' or '1G', or a count of
+    /// records, like '10000-records' or '10m-of-records' (decimal 'k', 'm', and 'g' suffixes are
+    /// allowed). When it's exhausted, a notice is printed to STDERR and the rest of the FILE is
+    /// skipped, rather than silently scanning all of it for a marker which isn't there.
+    #[arg(long, value_name = "BUDGET", value_parser = parse_budget, requires = "start")]
+    pub start_within: Option<Budget>,
+
     /// Ignore remaining records once this pattern is found in a file.
     ///
     /// The record containing the pattern WILL NOT be searched.
     #[arg(short = 'E', long, value_name = "PATTERN")]
     pub end: Option<String>,
 
+    /// Prefix each record with its offset from the file's first timestamp.
+    ///
+    /// The offset is formatted like `[+00:14:03.2]`, and is measured from the first record in the
+    /// file with a parsable timestamp, whether or not it matched. Handy for correlating behavior
+    /// with "minutes after startup" instead of absolute clock times. Records without a timestamp
+    /// are not prefixed.
+    #[arg(long)]
+    pub since_start: bool,
+
+    /// Prefix each record without a parsable timestamp with the time it was read.
+    ///
+    /// The stamp is like `2024-07-01 01:25:47.755`, in the '--assume-tz' zone, so time-based
+    /// tooling (lgrep included) can still order and slice logs where only some lines are
+    /// timestamped, like a process's raw STDOUT. Most useful with '--follow', where the time read
+    /// is close to the time written. Records with a timestamp are left as they are.
+    #[arg(long)]
+    pub stamp: bool,
+
+    /// Precede each record with a comment line describing it.
+    ///
+    /// The comment is like `# file=app.log record=12 lines=8 ts=2024-07-01T01:25:47.755`, so a
+    /// saved capture stays self-describing. The record number counts from the start of the file,
+    /// and `ts` (in the '--assume-tz' zone) is omitted if the record has no parsable timestamp.
+    #[arg(long)]
+    pub annotate: bool,
+
+    /// Time zone for interpreting timestamps without zone info.
+    ///
+    /// Log timestamps frequently lack an offset. By default they're interpreted in the local time
+    /// zone, but an IANA name (e.g., `Europe/Berlin` or `UTC`) may be supplied so time-based
+    /// features are correct across DST boundaries. Timestamps with an explicit offset (`Z` or
+    /// `+02:00`) are never reinterpreted.
+    #[arg(long, value_name = "ZONE")]
+    pub assume_tz: Option<String>,
+
+    /// Infer dates for records whose timestamp is only a time of day.
+    ///
+    /// Some formats log only `HH:MM:SS`. With this flag, the first such record in a file is
+    /// assumed to be within the day before the file was last modified (or now, for STDIN), and
+    /// each time the clock goes backward by more than 12 hours, the next day is assumed. Records
+    /// with a full datetime are unaffected.
+    #[arg(long)]
+    pub time_only: bool,
+
+    /// Date of the first time-only record in each file (e.g. `2024-07-01`).
+    ///
+    /// Implies '--time-only', but anchors the inferred dates explicitly, instead of using the
+    /// file's modification time.
+    #[arg(long, value_name = "DATE")]
+    pub start_date: Option<Date>,
+
+    /// Join records split across rotated files.
+    ///
+    /// When consecutive FILEs are part of the same rotation set (e.g., `app.log.1` and `app.log`,
+    /// or `app.log-20240701.gz` and `app.log`), they are searched as one continuous log, so a
+    /// record cut off at the end of one file is completed by the leading lines of the next. List
+    /// the files oldest first. The start/end patterns and '--max-count' apply to the whole set. A
+    /// joined record is printed with the filename and line numbers of the file it started in.
+    #[arg(long)]
+    pub continue_records: bool,
+
+    /// Keep reading FILE as it's written, printing selected records as they're appended.
+    ///
+    /// Like `tail -f`, it never ends on its own. A record is printed once the next record starts,
+    /// so its continuation lines are included, or once nothing more has been written for a
+    /// second. Like `tail -F`, if FILE is rotated (renamed and replaced) or truncated, it's
+    /// reopened by name and followed from its start, with a notice on STDERR. Only a single,
+    /// uncompressed FILE can be followed.
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "count",
+            "count_by",
+            "files_with_matches",
+            "files_without_match",
+            "record_delimiter_bytes",
+            "null_data",
+        ]
+    )]
+    pub follow: bool,
+
+    /// POST selected records to a webhook at URL, when following a file, as a zero-infrastructure
+    /// alert.
+    ///
+    /// The body is a JSON summary, `{"file":..., "count":..., "records":[...]}`, with the first 10
+    /// records (each with its "record", "line", and "text"), and a count of all of them. POSTs are
+    /// sent with `curl`, in the background, and one which fails is warned about, but following
+    /// goes on.
+    #[arg(long, value_name = "URL", requires = "follow")]
+    pub notify_webhook: Option<String>,
+
+    /// The body to POST to '--notify-webhook', in place of the JSON summary.
+    ///
+    /// Its fields are '{file}', '{count}', and the text of the '{first}' and '{last}' records, and
+    /// their values are escaped as in a JSON string, so `{{"text": "{count} errors: {first}"}}`
+    /// suits a chat webhook. A literal brace is doubled.
+    #[arg(long, value_name = "TEMPLATE", requires = "notify_webhook")]
+    pub notify_template: Option<String>,
+
+    /// Batch '--notify-webhook' POSTs to at most one per DURATION, like '5m'.
+    ///
+    /// Records selected before then are sent together. Without it, each is sent as soon as it's
+    /// selected, along with any others already waiting.
+    #[arg(
+        long,
+        value_name = "DURATION",
+        value_parser = parse_timeout,
+        requires = "notify_webhook"
+    )]
+    pub notify_interval: Option<Duration>,
+
+    /// Interleave the records of all FILEs in timestamp order, as a single chronological log.
+    ///
+    /// For an incident spread across several nodes, e.g., `lgrep --merge ERROR node*.log`. Each
+    /// file's records are read in step, and the earliest record of any file is searched next. A
+    /// record without a timestamp stays after the record before it in its file. Timestamps are
+    /// found as for '--since', so need a date; consider '--tag' to tell the files apart. Every FILE
+    /// is open at once, so thousands of them can need a higher open files limit ('ulimit -n').
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "follow",
+            "continue_records",
+            "time_only",
+            "record_delimiter_bytes",
+            "null_data",
+        ]
+    )]
+    pub merge: bool,
+
+    /// Read each compressed FILE on its own thread, overlapping decompression with matching.
+    ///
+    /// Chunks of the decompressed stream are handed over through a small bounded buffer, so
+    /// memory use stays flat. This helps most when matching is expensive and inputs are
+    /// gzip-heavy; uncompressed files and STDIN are read as usual.
+    #[arg(long)]
+    pub decompress_thread: bool,
+
+    /// The encoding of each FILE, which is transcoded to UTF-8 before it's split into records.
+    ///
+    /// Without it, a FILE starting with a byte order mark (e.g., a UTF-16LE log written on
+    /// Windows) is transcoded from the encoding it marks, and any other is read as UTF-8. Invalid
+    /// characters in a transcoded FILE are replaced with '�'.
+    #[arg(long, value_name = "ENCODING")]
+    pub encoding: Option<Encoding>,
+
+    /// Split records longer than NUM lines, so a runaway one isn't read whole.
+    ///
+    /// A record's line after its first NUM starts another record, as if it matched the log
+    /// pattern, and so on. So a huge dump without timestamps (e.g., a heap histogram) is searched,
+    /// and written, NUM lines at a time, rather than held in memory as a single record.
+    #[arg(
+        long,
+        value_name = "NUM",
+        conflicts_with_all = ["record_delimiter_bytes", "null_data"]
+    )]
+    pub max_record_lines: Option<NonZeroUsize>,
+
+    /// Search each large, uncompressed FILE in up to NUM chunks at once, each on its own thread.
+    ///
+    /// A FILE is split at records, into chunks of at least 8 MiB, and the records selected from
+    /// each are written in order, as if it were read start to end. Only searches where each
+    /// record is selected on its own are split, so not with '--start', '--end', '--since',
+    /// '--file-header', '--sample', '--annotate', '--follow', and the like; those FILEs are read
+    /// as usual.
+    #[arg(short = 'j', long, value_name = "NUM", default_value_t = 1)]
+    pub threads: usize,
+
     /// Always print filename headers with output lines.
     ///
     /// The first line of a record will follow the filename with a ':' (colon) and subsequent lines
@@ -156,14 +1012,129 @@ pub(crate) struct Cli {
     #[arg(short = 'h', long)]
     pub no_filename: bool,
 
+    /// Output a zero byte (NUL) instead of the character that normally follows a file name.
+    ///
+    /// For example, `lgrep -lZ` outputs a zero byte after each file name instead of the usual
+    /// newline, so names with unusual characters are safe for `xargs -0`. Ignored with '--json'.
+    #[arg(short = 'Z', long)]
+    pub null: bool,
+
+    /// Prefix output lines with a short, colored tag for their file, instead of its name.
+    ///
+    /// Like `docker compose`, each file's tag is its name less directories, extension, and
+    /// rotation suffix (e.g., `api` for `/var/log/api.log.1`), padded to line up, and followed by
+    /// ' | '. Each tag gets its own color, so interleaved records from several services are easy
+    /// to tell apart. A tag's color is derived from its name, so a service keeps its color from
+    /// run to run.
+    #[arg(long)]
+    pub tag: bool,
+
     /// Print comprehensive help.
     #[arg(long)]
     pub help: bool,
 }
 
+/// How much of a file may be read looking for something.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum Budget {
+    Bytes(usize),
+    Records(usize),
+}
+
+impl Display for Budget {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Budget::Bytes(n) => write!(f, "{n} bytes"),
+            Budget::Records(n) => write!(f, "{n} records"),
+        }
+    }
+}
+
+/// Parse a [Budget], either a size (see [parse_size]) or a count of records,
+/// like `5000-records` or `10m-of-records`.
+pub(crate) fn parse_budget(s: &str) -> Result<Budget, String> {
+    let Some(count) = s
+        .strip_suffix("-of-records")
+        .or_else(|| s.strip_suffix("-records"))
+    else {
+        return parse_size(s).map(Budget::Bytes);
+    };
+    let (digits, multiplier) = match count.char_indices().last() {
+        Some((i, 'k' | 'K')) => (&count[..i], 1_000),
+        Some((i, 'm' | 'M')) => (&count[..i], 1_000_000),
+        Some((i, 'g' | 'G')) => (&count[..i], 1_000_000_000),
+        _ => (count, 1),
+    };
+    digits
+        .parse::<usize>()
+        .ok()
+        .and_then(|n| n.checked_mul(multiplier))
+        .map(Budget::Records)
+        .ok_or_else(|| format!("'{s}' is not a record count, like 5000-records or 10m-of-records"))
+}
+
+/// Parse a size in bytes, with an optional (binary) `K`, `M`, or `G` suffix.
+pub(crate) fn parse_size(s: &str) -> Result<usize, String> {
+    let (digits, multiplier) = match s.char_indices().last() {
+        Some((i, 'k' | 'K')) => (&s[..i], 1 << 10),
+        Some((i, 'm' | 'M')) => (&s[..i], 1 << 20),
+        Some((i, 'g' | 'G')) => (&s[..i], 1 << 30),
+        _ => (s, 1),
+    };
+    digits
+        .parse::<usize>()
+        .ok()
+        .and_then(|n| n.checked_mul(multiplier))
+        .ok_or_else(|| format!("'{s}' is not a size, like 512, 64K, 10M, or 2G"))
+}
+
+/// Parse a (positive) duration, like '50ms' or '2s'.
+fn parse_timeout(s: &str) -> Result<Duration, String> {
+    s.parse::<SignedDuration>()
+        .ok()
+        .filter(|d| d.is_positive())
+        .and_then(|d| Duration::try_from(d).ok())
+        .ok_or_else(|| format!("'{s}' is not a duration, like 50ms or 2s"))
+}
+
+/// Parse a sampling rate, a fraction from zero to one.
+fn parse_rate(s: &str) -> Result<f64, String> {
+    s.parse::<f64>()
+        .ok()
+        .filter(|r| (0.0..=1.0).contains(r))
+        .ok_or_else(|| format!("'{s}' is not a rate, like 0.1, from 0 to 1"))
+}
+
+fn parse_level_mapping(s: &str) -> Result<(String, Level), String> {
+    let (name, level) = s
+        .split_once('=')
+        .filter(|(n, _)| !n.is_empty())
+        .ok_or_else(|| format!("'{s}' is not a mapping, like SEVERE=ERROR"))?;
+    let level = Level::parse(level)
+        .ok_or_else(|| format!("'{level}' is not a level, like ERROR or DEBUG"))?;
+    Ok((name.to_owned(), level))
+}
+
+fn parse_wrap(s: &str) -> Result<Wrap, String> {
+    match s {
+        "never" => Ok(Wrap::Never),
+        "terminal" => Ok(Wrap::Terminal),
+        _ => s
+            .parse()
+            .ok()
+            .filter(|&n| n > 0)
+            .map(Wrap::Width)
+            .ok_or_else(|| format!("'{s}' is not 'never', 'terminal', or a number of columns")),
+    }
+}
+
 impl Cli {
     pub(crate) fn like_grep(mut self) -> Self {
-        if !self.patterns.is_empty() {
+        if !self.patterns.is_empty()
+            || !self.pattern_files.is_empty()
+            || !self.pair.is_empty()
+            || self.index == Some(IndexMode::Build)
+        {
             if let Some(p) = self.pattern {
                 // p is a file, since there are explict patterns
                 self.pattern = None;
@@ -174,7 +1145,10 @@ impl Cli {
     }
 
     pub fn has_patterns(&self) -> bool {
-        self.pattern.is_some() || !self.patterns.is_empty()
+        self.pattern.is_some()
+            || !self.patterns.is_empty()
+            || !self.pattern_files.is_empty()
+            || !self.pair.is_empty()
     }
 
     pub(crate) fn print_help(&self) -> Result<Exit> {
@@ -184,18 +1158,17 @@ impl Cli {
         Ok(Help)
     }
 
-    #[cfg(not(target_os = "windows"))]
-    pub(crate) fn print_long_help(&self) -> Result<Exit> {
-        Cli::command_for_update()
-            .after_long_help(COMPRESSED_FILES.to_owned() + BASE_LONG_HELP)
-            .print_long_help()
-            .context("failed to print long help")?;
-        Ok(Help)
+    pub(crate) fn print_json_schema(&self) -> Result<Exit> {
+        io::stdout()
+            .write_all(json::SCHEMA.as_bytes())
+            .context("failed to print JSON schema")?;
+        // unlike help, it's what was asked for
+        Ok(Exit::Match)
     }
 
-    #[cfg(target_os = "windows")]
     pub(crate) fn print_long_help(&self) -> Result<Exit> {
-        Cli::command()
+        Cli::command_for_update()
+            .after_long_help(COMPRESSED_FILES.to_owned() + BASE_LONG_HELP)
             .print_long_help()
             .context("failed to print long help")?;
         Ok(Help)
@@ -208,20 +1181,105 @@ impl Cli {
         Cli {
             pattern: None,
             files: vec![],
+            directories: Directories::Skip,
+            recursive: false,
+            no_dedupe_files: false,
+            include: vec![],
+            exclude: vec![],
+            exclude_dir: vec![],
+            newer_than: None,
+            older_than: None,
+            sort_files: None,
+            max_filesize: None,
             patterns: vec![],
+            pattern_files: vec![],
+            match_timeout: None,
+            fixed_strings: false,
+            pattern_label: vec![],
+            suppress_file: vec![],
+            show_suppressed_count: false,
             ignore_case: false,
+            no_unicode: false,
+            unicode_word_boundaries: false,
             max_count: None,
+            truncation_status: false,
+            min_matches: None,
+            min_bytes: None,
+            max_bytes_record: None,
             line_number: false,
+            record_number: false,
             invert_match: false,
             count: false,
+            count_matches: false,
+            count_by: None,
+            count_distinct: None,
+            cluster: false,
+            approx: false,
+            sample: None,
+            sample_by: None,
+            files_with_matches: false,
+            files_without_match: false,
             label: None,
             color: ColorChoice::Auto,
+            wrap: Wrap::Never,
+            omit_lines: None,
+            format: None,
+            keep_lines: None,
+            diff_similar: false,
+            squeeze_repeats: false,
+            color_by_level: false,
+            color_by_pattern: false,
+            level_map: Vec::new(),
+            fast_highlight: false,
+            context_pattern: None,
+            pair: Vec::new(),
+            pair_key: "1".to_owned(),
+            pair_report: None,
+            json: false,
+            json_schema: false,
+            stats: false,
+            pattern_stats: false,
+            summary: false,
+            log_level: None,
+            assert: Vec::new(),
+            manifest: None,
+            save: None,
+            replay: None,
+            tee: None,
+            output: None,
+            compress_output: None,
             quiet: false,
+            regex_size_limit: None,
+            dfa_size_limit: None,
+            file_header: None,
             log_pattern: None,
+            record_delimiter_bytes: None,
+            null_data: false,
             start: None,
+            start_within: None,
+            since: None,
+            index: None,
             end: None,
+            since_start: false,
+            stamp: false,
+            annotate: false,
+            assume_tz: None,
+            time_only: false,
+            start_date: None,
+            continue_records: false,
+            follow: false,
+            notify_webhook: None,
+            notify_template: None,
+            notify_interval: None,
+            merge: false,
+            decompress_thread: false,
+            encoding: None,
+            max_record_lines: None,
+            threads: 1,
             filename: false,
             no_filename: false,
+            null: false,
+            tag: false,
             help: false,
         }
     }
@@ -268,6 +1326,78 @@ mod tests {
         assert!(cli.has_patterns());
     }
 
+    #[test]
+    fn budgets() {
+        assert_eq!(Ok(Budget::Bytes(1 << 30)), parse_budget("1G"));
+        assert_eq!(Ok(Budget::Records(5000)), parse_budget("5000-records"));
+        assert_eq!(
+            Ok(Budget::Records(10_000_000)),
+            parse_budget("10m-of-records")
+        );
+        assert!(parse_budget("-records").is_err());
+        assert!(parse_budget("lots-of-records").is_err());
+    }
+
+    #[test]
+    fn sizes() {
+        assert_eq!(Ok(512), parse_size("512"));
+        assert_eq!(Ok(64 * 1024), parse_size("64k"));
+        assert_eq!(Ok(10 * 1024 * 1024), parse_size("10M"));
+        assert_eq!(Ok(2 * 1024 * 1024 * 1024), parse_size("2G"));
+        assert!(parse_size("").is_err());
+        assert!(parse_size("ten").is_err());
+        assert!(parse_size("1T").is_err());
+    }
+
+    #[test]
+    fn timeouts() {
+        assert_eq!(Ok(Duration::from_millis(50)), parse_timeout("50ms"));
+        assert_eq!(Ok(Duration::from_secs(2)), parse_timeout("2s"));
+        assert!(parse_timeout("0s").is_err());
+        assert!(parse_timeout("50").is_err());
+    }
+
+    #[test]
+    fn rates() {
+        assert_eq!(Ok(0.1), parse_rate("0.1"));
+        assert_eq!(Ok(1.0), parse_rate("1"));
+        assert!(parse_rate("1.5").is_err());
+        assert!(parse_rate("-0.1").is_err());
+        assert!(parse_rate("tenth").is_err());
+    }
+
+    #[test]
+    fn level_maps() {
+        let cli = Cli::try_parse_from([
+            "lgrep",
+            "--level-map",
+            "SEVERE=error,fine=DEBUG",
+            "--level-map=A=warn",
+            "ERROR",
+        ])
+        .unwrap();
+        assert_eq!(
+            vec![
+                ("SEVERE".to_owned(), Level::Error),
+                ("fine".to_owned(), Level::Debug),
+                ("A".to_owned(), Level::Warn),
+            ],
+            cli.level_map
+        );
+        assert!(parse_level_mapping("SEVERE").is_err());
+        assert!(parse_level_mapping("=ERROR").is_err());
+        assert!(parse_level_mapping("SEVERE=LOUD").is_err());
+    }
+
+    #[test]
+    fn wraps() {
+        assert_eq!(Ok(Wrap::Never), parse_wrap("never"));
+        assert_eq!(Ok(Wrap::Terminal), parse_wrap("terminal"));
+        assert_eq!(Ok(Wrap::Width(120)), parse_wrap("120"));
+        assert!(parse_wrap("0").is_err());
+        assert!(parse_wrap("wide").is_err());
+    }
+
     #[test]
     fn no_match_no_patterns() {
         let cli = Cli::empty();
@@ -331,6 +1461,23 @@ mod tests {
             assert_files(vec![], &cli.files);
         }
 
+        #[test]
+        fn like_grep_pair() {
+            let cli = Cli::from("lgrep --pair begin done app.log");
+            assert_eq!(None, cli.pattern);
+            assert_eq!(vec!["begin", "done"], cli.pair);
+            assert!(cli.has_patterns());
+            assert_files(vec!["app.log"], &cli.files);
+        }
+
+        #[test]
+        fn like_grep_index_build() {
+            let cli = Cli::from("lgrep --index build app.log");
+            assert_eq!(None, cli.pattern);
+            assert!(!cli.has_patterns());
+            assert_files(vec!["app.log"], &cli.files);
+        }
+
         #[test]
         fn like_grep_5() {
             let cli = Cli::from("lgrep -e Cli -e H src/handler.rs");
@@ -402,5 +1549,14 @@ mod tests {
             assert!(cli.has_patterns());
             assert_files(vec!["src/handler.rs"], &cli.files);
         }
+
+        #[test]
+        fn like_grep_12() {
+            let cli = Cli::from("lgrep -f bad.txt Cli src/handler.rs");
+            assert_eq!(None, cli.pattern);
+            assert_eq!(vec!["bad.txt"], cli.pattern_files);
+            assert!(cli.has_patterns());
+            assert_files(vec!["Cli", "src/handler.rs"], &cli.files);
+        }
     }
 }
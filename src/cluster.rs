@@ -0,0 +1,80 @@
+use std::cmp::Reverse;
+use std::collections::HashMap;
+
+use crate::read::records::Record;
+use crate::write::similar::mask_numbers;
+
+/// Selected records grouped by their fingerprint, for '--cluster'.
+#[derive(Debug, Default)]
+pub(crate) struct Clusters {
+    by_fingerprint: HashMap<String, usize>,
+    /// In order of their first records.
+    clusters: Vec<Cluster>,
+}
+
+/// Records alike but for their numbers (timestamps, IDs, etc.), with the
+/// first of them to stand for the rest.
+#[derive(Debug, Eq, PartialEq)]
+pub(crate) struct Cluster {
+    pub(crate) filename: String,
+    pub(crate) record: Record,
+    pub(crate) count: usize,
+}
+
+impl Clusters {
+    pub(crate) fn insert(&mut self, filename: &str, record: &Record) {
+        let fingerprint = mask_numbers(&record.text);
+        if let Some(&i) = self.by_fingerprint.get(&fingerprint) {
+            self.clusters[i].count += 1;
+            return;
+        }
+        self.by_fingerprint.insert(fingerprint, self.clusters.len());
+        self.clusters.push(Cluster {
+            filename: filename.to_owned(),
+            record: record.clone(),
+            count: 1,
+        });
+    }
+
+    /// The clusters, largest first. Those the same size stay in order of
+    /// their first records.
+    pub(crate) fn take_largest_first(&mut self) -> Vec<Cluster> {
+        self.by_fingerprint.clear();
+        let mut clusters = std::mem::take(&mut self.clusters);
+        clusters.sort_by_key(|c| Reverse(c.count));
+        clusters
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn clusters() {
+        let mut clusters = Clusters::default();
+        let texts = [
+            "12:00 timeout for 1\n  at a",
+            "12:00 started",
+            "12:01 timeout for 42\n  at a",
+            "12:01 timeout for 3\n  at b",
+            "12:02 timeout for 7\n  at a",
+        ];
+        for (i, t) in texts.iter().enumerate() {
+            clusters.insert("app.log", &Record::new(t, i + 1, i + 1));
+        }
+        let clusters: Vec<_> = clusters
+            .take_largest_first()
+            .into_iter()
+            .map(|c| (c.record.text, c.count))
+            .collect();
+        assert_eq!(
+            vec![
+                (texts[0].to_owned(), 3),
+                (texts[1].to_owned(), 1),
+                (texts[3].to_owned(), 1),
+            ],
+            clusters
+        );
+    }
+}
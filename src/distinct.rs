@@ -0,0 +1,142 @@
+use std::collections::HashSet;
+
+use crate::stable_hash;
+
+/// Bits of a value's hash which pick its register, so there are 2^14
+/// registers, in 16K of memory, for about a 0.8% standard error.
+const PRECISION: u32 = 14;
+const REGISTERS: usize = 1 << PRECISION;
+
+/// The distinct values seen, for '--count-distinct'. Either exactly, in a set
+/// which grows with the values, or approximately (for '--approx'), in a
+/// fixed-size sketch.
+#[derive(Debug)]
+pub(crate) enum Distinct {
+    Exact(HashSet<String>),
+    Approx(HyperLogLog),
+}
+
+impl Default for Distinct {
+    fn default() -> Self {
+        Distinct::Exact(HashSet::new())
+    }
+}
+
+impl Distinct {
+    pub(crate) fn approx() -> Distinct {
+        Distinct::Approx(HyperLogLog::default())
+    }
+
+    pub(crate) fn insert(&mut self, value: &str) {
+        match self {
+            Distinct::Exact(set) => {
+                if !set.contains(value) {
+                    set.insert(value.to_owned());
+                }
+            }
+            Distinct::Approx(hll) => hll.insert(value),
+        }
+    }
+
+    pub(crate) fn count(&self) -> usize {
+        match self {
+            Distinct::Exact(set) => set.len(),
+            Distinct::Approx(hll) => hll.estimate(),
+        }
+    }
+
+    /// The count's relative standard error, if it's approximate.
+    pub(crate) fn error(&self) -> Option<f64> {
+        match self {
+            Distinct::Exact(_) => None,
+            Distinct::Approx(_) => Some(HyperLogLog::STANDARD_ERROR),
+        }
+    }
+}
+
+/// A HyperLogLog sketch: an estimate of how many distinct values were
+/// inserted, in constant memory. See Flajolet et al., "HyperLogLog: the
+/// analysis of a near-optimal cardinality estimation algorithm" (2007).
+#[derive(Debug)]
+pub(crate) struct HyperLogLog {
+    /// The most leading zeros (plus one) seen among the hashes of each
+    /// register's values.
+    registers: Box<[u8; REGISTERS]>,
+}
+
+impl Default for HyperLogLog {
+    fn default() -> Self {
+        HyperLogLog {
+            registers: Box::new([0; REGISTERS]),
+        }
+    }
+}
+
+impl HyperLogLog {
+    const STANDARD_ERROR: f64 = 1.04 / 128.0; // 1.04 / sqrt(REGISTERS)
+
+    pub(crate) fn insert(&mut self, value: &str) {
+        let hash = mix(stable_hash(value));
+        let register = (hash >> (64 - PRECISION)) as usize;
+        // a sentinel bit bounds the count, should the rest be all zeros
+        let rest = (hash << PRECISION) | (1 << (PRECISION - 1));
+        let rank = rest.leading_zeros() as u8 + 1;
+        let r = &mut self.registers[register];
+        *r = (*r).max(rank);
+    }
+
+    pub(crate) fn estimate(&self) -> usize {
+        let m = REGISTERS as f64;
+        let alpha = 0.7213 / (1.0 + 1.079 / m);
+        let sum: f64 = self.registers.iter().map(|&r| 2f64.powi(-(r as i32))).sum();
+        let raw = alpha * m * m / sum;
+        let empty = self.registers.iter().filter(|&&r| r == 0).count();
+        if raw <= 2.5 * m && empty > 0 {
+            // few values, so linear counting is more accurate
+            (m * (m / empty as f64).ln()).round() as usize
+        } else {
+            raw.round() as usize
+        }
+    }
+}
+
+/// Spread a hash's entropy across all its bits (MurmurHash3's finalizer),
+/// as the sketch depends on the high bits being uniform.
+fn mix(mut h: u64) -> u64 {
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    h ^ (h >> 33)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn exact() {
+        let mut d = Distinct::default();
+        for v in ["a", "b", "a", "c", "b"] {
+            d.insert(v);
+        }
+        assert_eq!(3, d.count());
+        assert_eq!(None, d.error());
+    }
+
+    #[test]
+    fn approx() {
+        let mut d = Distinct::approx();
+        assert_eq!(0, d.count());
+        for n in [10, 1_000, 100_000] {
+            for i in 0..n {
+                // each value twice
+                d.insert(&format!("user-{i}"));
+                d.insert(&format!("user-{i}"));
+            }
+            let error = (d.count() as f64 - n as f64).abs() / n as f64;
+            // within three standard errors
+            assert!(error < 3.0 * d.error().unwrap(), "{} for {n}", d.count());
+        }
+    }
+}
@@ -1,17 +1,56 @@
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, VecDeque};
 use std::env;
-use std::io::BufWriter;
+use std::fs::File;
+use std::io::{BufRead, BufWriter, ErrorKind, Write};
+use std::num::NonZeroUsize;
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
 
-use anyhow::{Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use clap::ColorChoice;
 use is_terminal::is_terminal;
-use regex_automata::meta::Regex;
+use jiff::civil::Date;
+use jiff::Timestamp;
+use regex_automata::meta::{BuildError, Builder, FindMatches, Regex};
+use regex_automata::util::prefilter::Prefilter;
 use regex_automata::util::syntax;
+use regex_automata::{Input, MatchKind, PatternID};
 
 use read::STDIN_FILENAME;
 
-use crate::cli::Cli;
+use crate::assertion::Assertion;
+use crate::cli::{Budget, Cli};
+use crate::cluster::Clusters;
+use crate::distinct::Distinct;
+use crate::level::{Level, LevelDetector};
+use crate::manifest::Manifest;
+use crate::notify::{Notifier, NotifyField};
+use crate::pair::{PairReport, Pairs, Unmatched, Waiting};
+use crate::pattern_file;
+use crate::read::chunked::{self, ChunkedRecords, Selector};
+use crate::read::delimited::parse_delimiter;
+use crate::read::encoding::{self, Encoding};
+use crate::read::index::{Checkpoint, Index, IndexMode};
+use crate::read::lazy::LazyReader;
+use crate::read::records::Record;
+use crate::read::seek;
 use crate::read::source::Source;
-use crate::write::LgrepWrite;
+use crate::read::tar::{self, Archive};
+use crate::read::traverse::{FileFilter, Traversal};
+use crate::read::Directories;
+use crate::repeats::Repeats;
+use crate::stable_hash;
+use crate::stats::{FileStats, PatternStats, Position, Start, Summary};
+use crate::timestamp::{format_offset, Anchor, TimestampParser};
+use crate::trace::span;
+use crate::watchdog::Watchdog;
+use crate::write::compress::{Encoder, OutputCompression};
+use crate::write::tags::Tags;
+use crate::write::template::Template;
+use crate::write::{ceil_char_boundary, floor_char_boundary, LgrepWrite, Wrap};
 use crate::{read, Exit};
 
 const ENV_LOG_PATTERN: &str = "LGREP_LOG_PATTERN";
@@ -19,20 +58,135 @@ const ENV_LOG_PATTERN: &str = "LGREP_LOG_PATTERN";
 const DEFAULT_LOG_PATTERN: &str = r"(^|:)\d{4}-\d{2}-\d{2}[ T]\d{2}:\d{2}:\d{2}[.,]\d";
 const DEFAULT_STDIN_LABEL: &str = "(standard input)";
 
+/// How long a followed file must go unwritten before its last record, which
+/// may yet have more lines, is printed anyway.
+const FOLLOW_FLUSH_AFTER: Duration = Duration::from_secs(1);
+
+/// How many records before or after a selected record one matching
+/// '--context-pattern' can be, to be printed as its context.
+const CONTEXT_PATTERN_REACH: usize = 10;
+
 pub(crate) struct Handler {
     files: Vec<String>,
     pattern_set: Regex,
+    /// The negative patterns of pattern files, which a record mustn't match.
+    negative_set: Option<Regex>,
+    /// The patterns of '--suppress-file', which leave out selected records.
+    suppress_set: Option<Regex>,
+    show_suppressed_count: bool,
+    /// Selected records left out by `suppress_set`.
+    suppressed: Cell<usize>,
+    match_all: bool,
     max_count: Option<usize>,
+    truncation_status: bool,
+    min_matches: Option<usize>,
+    min_bytes: Option<usize>,
+    max_bytes: Option<usize>,
     invert_match: bool,
+    /// Labels for the patterns of `pattern_set`, by pattern ID; empty if
+    /// unlabeled.
+    pattern_labels: Vec<String>,
     counts: bool,
+    /// Whether counts are of pattern occurrences, rather than records.
+    count_matches: bool,
+    files_with_matches: bool,
+    files_without_match: bool,
+    count_by: Option<GroupKey>,
+    sample: Option<f64>,
+    match_timeout: Option<Watchdog>,
+    sample_by: Option<GroupKey>,
+    tallies: RefCell<HashMap<String, usize>>,
+    count_distinct: Option<GroupKey>,
+    cluster: bool,
+    clusters: RefCell<Clusters>,
+    pair_key: Option<GroupKey>,
+    pairs: RefCell<Pairs>,
+    pair_report: Option<PairReport>,
+    /// Each pattern on its own, for '--pattern-stats'.
+    each_pattern: Vec<Regex>,
+    pattern_stats: RefCell<Vec<PatternStats>>,
+    distinct: RefCell<Distinct>,
     color_mode: ColorChoice,
+    tee: Option<String>,
+    output: Option<String>,
+    output_compression: Option<OutputCompression>,
     quiet: bool,
+    /// Selected records across all files, for `--quiet --count`.
+    quiet_total: Cell<usize>,
     stdin_label: Option<String>,
+    file_header: Option<FileHeader>,
     log_pattern: Regex,
+    /// The log pattern as given, to tell if an index was built with it.
+    log_pattern_source: String,
+    record_delimiter: Option<Vec<u8>>,
     start: Option<Regex>,
+    start_within: Option<Budget>,
+    since: Option<Timestamp>,
+    index: Option<IndexMode>,
     end: Option<Regex>,
+    context_pattern: Option<Regex>,
+    since_start: bool,
+    stamp: bool,
+    annotate: bool,
+    timestamps: TimestampParser,
+    time_only: bool,
+    start_date: Option<Date>,
+    continue_records: bool,
+    follow: bool,
+    /// Where selected records are POSTed, with '--notify-webhook'.
+    notifier: Option<Notifier>,
+    merge: bool,
+    decompress_thread: bool,
+    encoding: Option<Encoding>,
+    max_record_lines: Option<usize>,
+    threads: usize,
+    fast_highlight: bool,
+    color_by_level: bool,
+    color_by_pattern: bool,
+    level_map: Vec<(String, Level)>,
+    wrap: Wrap,
+    omit_lines: Option<Regex>,
+    keep_lines: Option<Regex>,
+    diff_similar: bool,
+    squeeze_repeats: bool,
+    format: Option<Template<FormatField>>,
+    json: bool,
+    tag: bool,
+    stats: bool,
+    summary: bool,
+    manifest: Option<String>,
+    file_stats: RefCell<Vec<FileStats>>,
+    assertions: Vec<Assertion>,
     filenames: bool,
     line_numbers: bool,
+    record_numbers: bool,
+    null: bool,
+}
+
+/// A capture group of the pattern(s), by number or name.
+#[derive(Debug, Eq, PartialEq)]
+enum GroupKey {
+    Index(usize),
+    Name(String),
+    /// A named group of the log pattern, which is a field of every record.
+    LogField(String),
+}
+
+/// What a '--format' template field refers to.
+#[derive(Debug, Eq, PartialEq)]
+enum FormatField {
+    File,
+    Record,
+    Line,
+    Offset,
+    Text,
+    Group(GroupKey),
+}
+
+/// Which leading lines of a file are its header.
+enum FileHeader {
+    Lines(usize),
+    Pattern(Regex),
 }
 
 fn opt_re_match(opt_re: &Option<Regex>, hay: &str) -> bool {
@@ -43,32 +197,328 @@ fn opt_re_match(opt_re: &Option<Regex>, hay: &str) -> bool {
     }
 }
 
+/// Whether a record matches the pattern(s), at least `min_matches` times if
+/// set, and none of the negative patterns.
+fn is_match(
+    pattern_set: &Regex,
+    negative_set: Option<&Regex>,
+    match_all: bool,
+    min_matches: Option<usize>,
+    text: &str,
+) -> bool {
+    if negative_set.is_some_and(|re| re.is_match(text)) {
+        return false;
+    }
+    if match_all {
+        return true;
+    }
+    match min_matches {
+        Some(n) if n > 1 => pattern_set.find_iter(text).take(n).count() >= n,
+        Some(0) => true,
+        _ => pattern_set.is_match(text),
+    }
+}
+
 impl Handler {
     pub(crate) fn run(&self) -> Result<Exit> {
-        let lock = std::io::stdout().lock();
+        read::validate_files(&self.files)?;
+        if self.index == Some(IndexMode::Build) {
+            return self.build_indexes();
+        }
+        let (out, is_tty): (Box<dyn Write>, bool) = if let Some(path) = &self.output {
+            (Box::new(create_file(path)?), false)
+        } else {
+            let lock = std::io::stdout().lock();
+            let is_tty = is_terminal(&lock);
+            (Box::new(lock), is_tty)
+        };
+        let compression = self.output_compression.or_else(|| {
+            self.output
+                .as_deref()
+                .and_then(OutputCompression::for_filename)
+        });
+        let out = if let Some(c) = compression {
+            c.encoder(out)?
+        } else {
+            Encoder::Plain(out)
+        };
         let colorize = match self.color_mode {
-            ColorChoice::Auto => is_terminal(&lock),
+            _ if self.json => false,
+            ColorChoice::Auto => is_tty && compression.is_none(),
             ColorChoice::Always => true,
             ColorChoice::Never => false,
         };
-        let mut sink = BufWriter::new(lock);
-        let mut write = LgrepWrite::new(colorize, self.filenames, self.line_numbers, &mut sink);
-        self.run_with(&mut write)
+        let mut sink = BufWriter::new(out);
+        let mut tee_sink = if let Some(path) = &self.tee {
+            let file: Box<dyn Write> = Box::new(create_file(path)?);
+            Some(BufWriter::new(
+                if let Some(c) = OutputCompression::for_filename(path) {
+                    c.encoder(file)?
+                } else {
+                    Encoder::Plain(file)
+                },
+            ))
+        } else {
+            None
+        };
+        let mut write = self.configure(LgrepWrite::new(
+            colorize,
+            self.filenames,
+            self.line_numbers,
+            &mut sink,
+        ));
+        if let Some(ts) = &mut tee_sink {
+            // the copy is never colored
+            let tee = LgrepWrite::new(false, self.filenames, self.line_numbers, ts);
+            write = write.with_tee(self.configure(tee));
+        }
+        let wrap_width = match self.wrap {
+            Wrap::Never => None,
+            Wrap::Terminal if is_tty => terminal_size::terminal_size().map(|(w, _)| w.0 as usize),
+            Wrap::Terminal => None,
+            Wrap::Width(n) => Some(n),
+        };
+        if let Some(w) = wrap_width {
+            write = write.with_wrap(w);
+        }
+        if self.color_by_level && colorize {
+            write = write.with_level_colors(LevelDetector::new(&self.log_pattern, &self.level_map));
+        }
+        if self.color_by_pattern && colorize {
+            write = write.with_pattern_colors();
+        }
+        let exit = self.run_with(&mut write)?;
+        if let Some(ts) = &mut tee_sink {
+            finish(ts)?;
+        }
+        finish(&mut sink)?;
+        Ok(exit)
+    }
+
+    /// Configure a writer per the options shared by the output and the
+    /// '--tee' copy.
+    fn configure<'a>(&'a self, mut w: LgrepWrite<'a>) -> LgrepWrite<'a> {
+        if self.json {
+            w = w.with_json().with_pattern_labels(&self.pattern_labels);
+        }
+        if self.tag {
+            w = w.with_tags(self.tags());
+        }
+        if self.record_numbers {
+            w = w.with_record_numbers();
+        }
+        if self.null {
+            w = w.with_null();
+        }
+        if let Some(re) = &self.omit_lines {
+            w = w.with_omit_lines(re.clone());
+        }
+        if let Some(re) = &self.keep_lines {
+            w = w.with_keep_lines(re.clone());
+        }
+        if self.diff_similar {
+            w = w.with_diff_similar();
+        }
+        w
     }
 
     fn run_with(&self, sink: &mut LgrepWrite) -> Result<Exit> {
+        let started = Instant::now();
         let mut exit = Exit::NoMatch;
-        for f in self.files.iter() {
-            let reader = read::get_reader(f)?;
-            let source = Source::new(self.display_name_for_filename(f), reader);
-            match self.process_file(source, sink)? {
+        let sets: Vec<&[String]> = if self.merge {
+            // searched as one, interleaved
+            vec![&self.files]
+        } else {
+            self.files
+                .chunk_by(|a, b| self.is_continuation(a, b))
+                .collect()
+        };
+        for set in sets {
+            let set_exit = match self.search_set(set, sink) {
+                Ok(exit) => exit,
+                Err(e) => {
+                    if self.summary {
+                        eprintln!("{}", self.summarize(started, 1));
+                    }
+                    return Err(e);
+                }
+            };
+            match set_exit {
                 Exit::Terminate => {
                     exit = Exit::Terminate;
                     break;
                 }
+                Exit::Truncated => exit = Exit::Truncated,
                 Exit::Match => {
+                    if exit != Exit::Truncated {
+                        exit = Exit::Match;
+                    }
+                    if self.is_quiet_exit() {
+                        break;
+                    }
+                }
+                _ => {}
+            }
+        }
+        if exit != Exit::Terminate && self.count_by.is_some() && self.write_tallies(sink)? {
+            exit = Exit::Terminate;
+        }
+        if exit != Exit::Terminate
+            && self.count_distinct.is_some()
+            && sink.write_distinct(&self.distinct.borrow())? == Exit::Terminate
+        {
+            exit = Exit::Terminate;
+        }
+        if exit != Exit::Terminate && self.cluster && self.write_clusters(sink)? {
+            exit = Exit::Terminate;
+        }
+        if exit != Exit::Terminate
+            && self.pair_report == Some(PairReport::Unmatched)
+            && self.write_unmatched(sink)?
+        {
+            exit = Exit::Terminate;
+        }
+        if self.quiet && self.counts {
+            eprintln!("{}", self.quiet_total.get());
+        }
+        if self.show_suppressed_count {
+            eprintln!("lgrep: {} record(s) suppressed", self.suppressed.get());
+        }
+        if self.stats {
+            let file_stats = self.file_stats.borrow();
+            for s in file_stats.iter() {
+                eprintln!("{s}");
+            }
+            if file_stats.len() > 1 {
+                eprintln!("{}", FileStats::total(file_stats.iter()));
+            }
+        }
+        for s in self.pattern_stats.borrow().iter() {
+            eprintln!("{s}");
+        }
+        if self.summary {
+            eprintln!("{}", self.summarize(started, 0));
+        }
+        if exit != Exit::Terminate && !self.assertions.is_empty() {
+            exit = self.check_assertions();
+        }
+        Ok(exit)
+    }
+
+    /// Check the '--assert' expectations, describing any failures.
+    fn check_assertions(&self) -> Exit {
+        let file_stats = self.file_stats.borrow();
+        let mut passed = true;
+        for a in &self.assertions {
+            let counts = file_stats
+                .iter()
+                .filter(|s| s.skipped.is_none())
+                .map(|s| (s.filename.as_str(), s.selected));
+            for failure in a.check(counts) {
+                eprintln!("lgrep: assertion failed: {failure}");
+                passed = false;
+            }
+        }
+        if passed {
+            Exit::Match
+        } else {
+            Exit::AssertionFailed
+        }
+    }
+
+    /// Search a set of files, which is a single file unless it's a rotation
+    /// set being continued across.
+    fn search_set(&self, set: &[String], sink: &mut LgrepWrite) -> Result<Exit> {
+        let f = &set[0];
+        if self.merge && set.len() > 1 {
+            return self.search_merged(set, sink);
+        }
+        if set.len() == 1 && !self.follow && tar::is_archive(f) {
+            return self.search_archive(f, sink);
+        }
+        if set.len() == 1 && self.can_chunk() {
+            if let Some(chunks) = chunked::chunks(f, self.threads, &self.log_pattern)? {
+                return self.process_chunked(f, chunks, sink);
+            }
+        }
+        let grew = Arc::default();
+        let mut start = None;
+        let reader = if self.follow {
+            read::open_follow(f)?
+        } else if let Some((r, c)) = self.open_indexed(set, &grew)? {
+            start = Some(c);
+            r
+        } else if let Some(r) = self.open_since(set, &grew)? {
+            r
+        } else {
+            read::get_reader(f, self.decompress_thread, &grew)?
+        };
+        let reader = encoding::decode(reader, self.encoding)
+            .with_context(|| format!("Failed to read from '{f}'"))?;
+        let modified = if self.time_only && f != STDIN_FILENAME {
+            std::fs::metadata(f).and_then(|md| md.modified()).ok()
+        } else {
+            None
+        };
+        let mut source = Source::new(self.display_name_for_filename(f), reader)
+            .with_modified(modified)
+            .with_growth(grew.clone());
+        if let Some(c) = start {
+            source = source.starting_at(c);
+        }
+        for f in &set[1..] {
+            // opened as it's reached, so a long set only holds one open
+            let (f_owned, read_ahead, grew) = (f.clone(), self.decompress_thread, grew.clone());
+            let encoding = self.encoding;
+            let reader = LazyReader::new(move || {
+                let reader = read::get_reader(&f_owned, read_ahead, &grew)?;
+                encoding::decode(reader, encoding)
+                    .with_context(|| format!("Failed to read from '{f_owned}'"))
+            });
+            source = source.continued_by(f, Box::new(reader));
+        }
+        self.process_file(source, sink)
+    }
+
+    /// Search files as one, for '--merge', with their records interleaved by
+    /// timestamp. They're all open at once, as each is read a record at a
+    /// time, which is what raising the open files limit at startup is for.
+    fn search_merged(&self, set: &[String], sink: &mut LgrepWrite) -> Result<Exit> {
+        let grew = Arc::default();
+        let mut source: Option<Source> = None;
+        for f in set {
+            let reader = read::get_reader(f, self.decompress_thread, &grew)?;
+            let reader = encoding::decode(reader, self.encoding)
+                .with_context(|| format!("Failed to read from '{f}'"))?;
+            let name = self.display_name_for_filename(f);
+            source = Some(match source {
+                None => Source::new(name, reader).with_growth(grew.clone()),
+                Some(s) => s.continued_by(name, reader),
+            });
+        }
+        self.process_file(source.expect("a set has files"), sink)
+    }
+
+    /// Search each regular file in a tar archive as its own file, named like
+    /// `bundle.tar.gz!logs/app.log`.
+    fn search_archive(&self, filename: &str, sink: &mut LgrepWrite) -> Result<Exit> {
+        let reader = read::get_reader(&filename.to_owned(), false, &Arc::default())?;
+        let mut archive = Archive::new(reader);
+        let display_name = self.display_name_for_filename(filename);
+        let mut exit = Exit::NoMatch;
+        while let Some((member, reader)) = archive
+            .next_member()
+            .with_context(|| format!("Failed to read from '{filename}'"))?
+        {
+            let name = format!("{display_name}!{member}");
+            let reader = encoding::decode(reader, self.encoding)
+                .with_context(|| format!("Failed to read from '{name}'"))?;
+            match self.process_file(Source::new(&name, reader), sink)? {
+                Exit::Terminate => return Ok(Exit::Terminate),
+                Exit::Truncated => exit = Exit::Truncated,
+                Exit::Match if exit != Exit::Truncated => {
                     exit = Exit::Match;
-                    if self.quiet {
+                    if self.is_quiet_exit() {
                         break;
                     }
                 }
@@ -78,6 +528,169 @@ impl Handler {
         Ok(exit)
     }
 
+    /// For '--since', open a plain file at about its last record before the
+    /// time, found by binary search, instead of reading up to it. Not if
+    /// anything needs records' positions in the file, which can only be had by
+    /// reading, or undated (time-only) timestamps, which can't be compared.
+    fn open_since(
+        &self,
+        set: &[String],
+        grew: &Arc<AtomicBool>,
+    ) -> Result<Option<Box<dyn BufRead>>> {
+        let Some(since) = self.since else {
+            return Ok(None);
+        };
+        if set.len() > 1
+            || self.record_delimiter.is_some()
+            || self.file_header.is_some()
+            || self.time_only
+            || self.line_numbers
+            || self.record_numbers
+            || self.json
+            || self.annotate
+            || self.since_start
+            || self.stats
+            || self.manifest.is_some()
+            || self.encoding.is_some()
+        {
+            return Ok(None);
+        }
+        read::get_reader_at(&set[0], grew, |file, len| {
+            seek::find_start(file, len, |line| {
+                if !self.log_pattern.is_match(line) {
+                    return None;
+                }
+                self.timestamps.parse(line).map(|ts| ts < since)
+            })
+        })
+    }
+
+    /// Write an index beside each file, for '--index build'.
+    fn build_indexes(&self) -> Result<Exit> {
+        for f in &self.files {
+            let index = Index::build(f, &self.log_pattern, &self.log_pattern_source, |text| {
+                self.timestamps.parse(text)
+            })?;
+            if index.checkpoints() == 0 {
+                eprintln!("lgrep: {f}: no records with timestamps to index");
+            }
+            index.save(f)?;
+        }
+        Ok(Exit::Match)
+    }
+
+    /// For '--index use' with '--since', open a file at the last record its
+    /// index has before the time, which it starts numbering from. Like
+    /// [Handler::open_since], but as the numbers are known, they may be used.
+    fn open_indexed(
+        &self,
+        set: &[String],
+        grew: &Arc<AtomicBool>,
+    ) -> Result<Option<(Box<dyn BufRead>, Checkpoint)>> {
+        let (Some(IndexMode::Use), Some(since)) = (self.index, self.since) else {
+            return Ok(None);
+        };
+        if set.len() > 1
+            || self.record_delimiter.is_some()
+            || self.file_header.is_some()
+            || self.time_only
+            || self.since_start
+            || self.stats
+            || self.manifest.is_some()
+            || self.encoding.is_some()
+        {
+            return Ok(None);
+        }
+        let f = &set[0];
+        let Some(index) = Index::load(f, &self.log_pattern_source)? else {
+            eprintln!("lgrep: {f}: no current index; searching without it");
+            return Ok(None);
+        };
+        let Some(c) = index.before(since) else {
+            return Ok(None);
+        };
+        let reader = read::get_reader_at(f, grew, |_, _| Ok(c.offset))?;
+        Ok(reader.map(|r| (r, c)))
+    }
+
+    /// The '--summary' of the files searched so far.
+    fn summarize(&self, started: Instant, errors: usize) -> Summary {
+        let file_stats = self.file_stats.borrow();
+        Summary {
+            selected: file_stats.iter().map(|s| s.selected).sum(),
+            matched_files: file_stats.iter().filter(|s| s.selected > 0).count(),
+            files: self
+                .files
+                .chunk_by(|a, b| self.is_continuation(a, b))
+                .count(),
+            elapsed: started.elapsed(),
+            errors,
+        }
+    }
+
+    /// Complete the run's manifest with what was searched and found, and
+    /// write it to the '--manifest' file.
+    pub(crate) fn write_manifest(&self, manifest: Manifest, exit: &Exit) -> Result<()> {
+        let Some(filename) = &self.manifest else {
+            return Ok(());
+        };
+        manifest
+            .with_results(&self.files, self.file_stats.take(), exit)?
+            .write(filename)
+    }
+
+    /// Write the `--count-by` tallies, most frequent first, returning whether
+    /// the output was closed.
+    fn write_tallies(&self, sink: &mut LgrepWrite) -> Result<bool> {
+        let mut tallies: Vec<_> = self.tallies.borrow_mut().drain().collect();
+        tallies.sort_by(|(a, m), (b, n)| n.cmp(m).then_with(|| a.cmp(b)));
+        for (key, count) in tallies {
+            if sink.write_tally(&key, count)? == Exit::Terminate {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Write the first record of each '--cluster' cluster, largest first,
+    /// after its size, returning whether the output was closed.
+    fn write_clusters(&self, sink: &mut LgrepWrite) -> Result<bool> {
+        for c in self.clusters.borrow_mut().take_largest_first() {
+            let size = format!("×{}", c.count);
+            if sink.write_record(&c.filename, &c.record, Some(&size))? == Exit::Terminate {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Write the '--pair' records never paired, annotated with what they
+    /// lack, returning whether output was terminated.
+    fn write_unmatched(&self, sink: &mut LgrepWrite) -> Result<bool> {
+        for u in self.pairs.borrow_mut().take_unmatched() {
+            let (w, lacks) = match &u {
+                Unmatched::Start(w) => (w, "no END"),
+                Unmatched::End(w) => (w, "no START"),
+            };
+            if sink.write_record(&w.filename, &w.record, Some(lacks))? == Exit::Terminate {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    fn tags(&self) -> Tags {
+        Tags::for_files(self.files.iter().map(|f| self.display_name_for_filename(f)))
+    }
+
+    /// Whether file `b` continues file `a`, so records may span them.
+    fn is_continuation(&self, a: &str, b: &str) -> bool {
+        self.continue_records
+            && a != STDIN_FILENAME
+            && b != STDIN_FILENAME
+            && read::rotation_base(a) == read::rotation_base(b)
+    }
+
     fn display_name_for_filename<'a>(&'a self, f: &'a str) -> &'a str {
         if f == STDIN_FILENAME {
             if let Some(lbl) = &self.stdin_label {
@@ -91,56 +704,741 @@ impl Handler {
     }
 
     fn process_file(&self, source: Source, sink: &mut LgrepWrite) -> Result<Exit> {
+        let _span = span!(DEBUG, "search", file = source.filename);
         let mut file_started = !self.has_start();
+        let mut since_reached = self.since.is_none();
+        let (mut skipped_bytes, mut skipped_records) = (0, 0);
         let mut match_count = 0;
+        // only looking for one more selected record
+        let mut max_reached = false;
+        let mut truncated = false;
         let filename = source.filename;
-        let needs_matches = !self.invert_match && sink.needs_match_locations();
+        let grew = source.grew.clone();
+        let filenames = source.filenames();
+        let mut segment_counts = vec![0; filenames.len()];
+        let needs_matches = !self.invert_match
+            && self.pattern_set.pattern_len() > 0
+            && sink.needs_match_locations();
+        let mut timeline = self.timestamps.timeline(self.anchor_for(&source));
+        let mut file_epoch = None;
+        let records: Box<dyn Iterator<Item = Result<Record>>> =
+            if let Some(delimiter) = &self.record_delimiter {
+                Box::new(source.delimited_records(delimiter))
+            } else if self.merge {
+                Box::new(source.merged_records(
+                    &self.log_pattern,
+                    self.max_record_lines,
+                    Box::new(|text| self.timestamps.parse(text)),
+                ))
+            } else if self.follow {
+                Box::new(
+                    source
+                        .follow_records(&self.log_pattern, FOLLOW_FLUSH_AFTER)
+                        .with_max_lines(self.max_record_lines),
+                )
+            } else {
+                Box::new(
+                    source
+                        .records(&self.log_pattern)
+                        .with_max_lines(self.max_record_lines),
+                )
+            };
+        let searched = Instant::now();
+        let mut stats = FileStats {
+            filename: filename.to_owned(),
+            start: if file_started {
+                Start::Unused
+            } else {
+                Start::NotFound
+            },
+            ..FileStats::default()
+        };
+        let mut in_header = self.file_header.is_some();
+        let mut header: Option<Record> = None;
+        let mut header_lines = 0;
+        let mut prev_record = (0, 0);
+        // records matching the context pattern, by index, which may yet be
+        // before a selected record
+        let mut context: VecDeque<(usize, Record)> = VecDeque::new();
+        let mut last_selected = None;
+        let mut repeats = Repeats::default();
         // an entire log record
-        for record in source.records(&self.log_pattern) {
+        for record in records {
             // while let soaks up an Err; we want to propagate it
             match record {
                 Err(e) => {
                     return Err(e).with_context(|| format!("Failed to read from '{}'", filename))
                 }
                 Ok(r) => {
+                    debug_assert!(
+                        self.merge || (r.segment, r.record_num) > prev_record,
+                        "record {:?} follows record {prev_record:?}?!",
+                        (r.segment, r.record_num)
+                    );
+                    prev_record = (r.segment, r.record_num);
+                    stats.records += 1;
+                    stats.lines += r.text.matches('\n').count() + 1;
+                    stats.bytes += (r.text.len() + usize::from(r.terminated)) as u64;
+                    if in_header {
+                        if self.is_header(&r.text, header_lines) {
+                            header_lines += 1;
+                            if let Some(h) = &mut header {
+                                h.push_line_text(&r.text);
+                            } else {
+                                header = Some(r);
+                            }
+                            continue;
+                        }
+                        in_header = false;
+                    }
+                    // every record, so time-only records see each rollover
+                    let ts = if self.since_start
+                        || self.annotate
+                        || self.stamp
+                        || self.pair_key.is_some()
+                        || !since_reached
+                    {
+                        timeline.parse(&r.text)
+                    } else {
+                        None
+                    };
+                    if file_epoch.is_none() && self.since_start {
+                        file_epoch = ts;
+                    }
                     if self.is_end(&r.text) {
+                        stats.end = Some(Position::from(&r));
                         break;
                     }
+                    if !since_reached {
+                        if ts.zip(self.since).is_some_and(|(ts, since)| ts >= since) {
+                            since_reached = true;
+                        } else {
+                            continue;
+                        }
+                    }
                     if !file_started {
                         if self.is_start(&r.text) {
                             file_started = true;
+                            stats.start = Start::Found(Position::from(&r));
                         } else {
+                            skipped_bytes += r.text.len() + 1;
+                            skipped_records += 1;
+                            if self.is_start_budget_spent(skipped_bytes, skipped_records) {
+                                eprintln!(
+                                    "lgrep: {filename}: start pattern not found within {}; skipping the rest",
+                                    self.start_within.unwrap()
+                                );
+                                stats.start = Start::GaveUp(skipped_records);
+                                break;
+                            }
                             continue;
                         }
                     }
-                    if self.invert_match ^ self.pattern_set.is_match(&r.text) {
-                        if !self.counts && !self.quiet {
-                            if needs_matches {
-                                sink.write_record_with_matches(
-                                    filename,
-                                    &r,
-                                    self.pattern_set.find_iter(&r.text),
-                                )?;
+                    if !self.is_size_selected(&r.text) {
+                        continue;
+                    }
+                    let Some(is_match) = self.timed_match(&r.text) else {
+                        eprintln!(
+                            "lgrep: {}: matching {} exceeded --match-timeout; skipping it",
+                            filenames[r.segment],
+                            Position::from(&r)
+                        );
+                        stats.timed_out += 1;
+                        continue;
+                    };
+                    if !self.each_pattern.is_empty() {
+                        self.measure_patterns(&r.text);
+                    }
+                    if self.invert_match ^ is_match
+                        && !self.is_suppressed(&r.text)
+                        && self.is_sampled(&r.text)
+                    {
+                        if max_reached {
+                            eprintln!(
+                                "lgrep: {filename}: output stopped by --max-count; more records were selected"
+                            );
+                            truncated = true;
+                            break;
+                        }
+                        let filename = filenames[r.segment];
+                        if self.aggregate(filename, &r) {
+                            // it's written with the rest, at the end
+                        } else if let Some(key) = &self.pair_key {
+                            if !self.quiet
+                                && self.pair(key, filename, &r, ts, needs_matches, sink)?
+                                    == Exit::Terminate
+                            {
+                                return Ok(Exit::Terminate);
+                            }
+                        } else if self.writes_records()
+                            && self.squeeze_repeats
+                            && repeats.is_repeat(r.segment, &self.timestamps.strip(&r.text))
+                        {
+                            // written as the count of its run, once it ends
+                        } else if self.writes_records() {
+                            let squeezed = repeats.take_count();
+                            if squeezed > 0 && sink.write_repeated(squeezed)? == Exit::Terminate {
+                                return Ok(Exit::Terminate);
+                            }
+                            if let Some(h) = header.take() {
+                                let exit = if let Some(t) = &self.format {
+                                    sink.write_formatted(&self.render(t, filenames[h.segment], &h))?
+                                } else {
+                                    sink.write_record(filenames[h.segment], &h, None)?
+                                };
+                                if exit == Exit::Terminate {
+                                    return Ok(Exit::Terminate);
+                                }
+                            }
+                            for (i, c) in context.drain(..) {
+                                if i + CONTEXT_PATTERN_REACH >= stats.records
+                                    && sink.write_context_record(filenames[c.segment], &c)?
+                                        == Exit::Terminate
+                                {
+                                    return Ok(Exit::Terminate);
+                                }
+                            }
+                            last_selected = Some(stats.records);
+                            if self.annotate
+                                && sink.write_comment(&self.describe(filename, &r, ts))?
+                                    == Exit::Terminate
+                            {
+                                return Ok(Exit::Terminate);
+                            }
+                            let annotation = if self.stamp && ts.is_none() {
+                                Some(self.stamp_now())
                             } else {
-                                sink.write_record(filename, &r)?;
+                                file_epoch
+                                    .zip(ts)
+                                    .map(|(epoch, ts)| format_offset(ts.duration_since(epoch)))
+                            };
+                            let exit = self.write_selected(
+                                filename,
+                                &r,
+                                annotation.as_deref(),
+                                needs_matches,
+                                sink,
+                            )?;
+                            if exit == Exit::Terminate {
+                                return Ok(exit);
                             }
                         }
+                        if let Some(n) = &self.notifier {
+                            n.notify(&r);
+                        }
                         match_count += 1;
+                        segment_counts[r.segment] += if self.count_matches {
+                            self.occurrences(&r.text)
+                        } else {
+                            1
+                        };
                         if self.is_max_reached(match_count) {
-                            break; // reached max count
+                            if !self.truncation_status || self.quiet || self.lists_files() {
+                                break; // reached max count
+                            }
+                            max_reached = true;
+                        }
+                    } else if !self.quiet
+                        && self
+                            .context_pattern
+                            .as_ref()
+                            .is_some_and(|re| re.is_match(&r.text))
+                    {
+                        if last_selected.is_some_and(|s| stats.records <= s + CONTEXT_PATTERN_REACH)
+                        {
+                            if sink.write_context_record(filenames[r.segment], &r)?
+                                == Exit::Terminate
+                            {
+                                return Ok(Exit::Terminate);
+                            }
+                        } else {
+                            while context
+                                .front()
+                                .is_some_and(|(i, _)| i + CONTEXT_PATTERN_REACH < stats.records)
+                            {
+                                context.pop_front();
+                            }
+                            context.push_back((stats.records, r));
                         }
                     }
                 }
             }
         }
-        if self.counts {
-            sink.write_count(filename, match_count)?;
+        let squeezed = repeats.take_count();
+        if squeezed > 0 && sink.write_repeated(squeezed)? == Exit::Terminate {
+            return Ok(Exit::Terminate);
+        }
+        if self.write_file_results(&filenames, segment_counts, sink)? == Exit::Terminate {
+            return Ok(Exit::Terminate);
+        }
+        stats.selected = match_count;
+        stats.elapsed = searched.elapsed();
+        stats.grew = grew.load(Ordering::Relaxed);
+        if self.stats || self.summary || self.manifest.is_some() || !self.assertions.is_empty() {
+            self.file_stats.borrow_mut().push(stats);
+        }
+        if truncated {
+            Ok(Exit::Truncated)
+        } else {
+            Ok(Exit::from(match_count))
+        }
+    }
+
+    /// Search a large file in chunks, at once. Only the searches in which a
+    /// record's selection doesn't depend on those before it are, so see
+    /// [Handler::can_chunk].
+    fn process_chunked(
+        &self,
+        filename: &str,
+        chunks: Vec<Range<u64>>,
+        sink: &mut LgrepWrite,
+    ) -> Result<Exit> {
+        let display_name = self.display_name_for_filename(filename);
+        let needs_matches = !self.invert_match
+            && self.pattern_set.pattern_len() > 0
+            && sink.needs_match_locations();
+        let searched = Instant::now();
+        let mut records = ChunkedRecords::spawn(
+            filename,
+            chunks,
+            &self.log_pattern,
+            self.max_record_lines,
+            self.selector(),
+        );
+        let mut match_count = 0;
+        let mut max_reached = false;
+        let mut truncated = false;
+        for r in records.by_ref() {
+            let r = r.with_context(|| format!("Failed to read from '{filename}'"))?;
+            if max_reached {
+                eprintln!(
+                    "lgrep: {display_name}: output stopped by --max-count; more records were selected"
+                );
+                truncated = true;
+                break;
+            }
+            if !self.aggregate(display_name, &r)
+                && self.writes_records()
+                && self.write_selected(display_name, &r, None, needs_matches, sink)?
+                    == Exit::Terminate
+            {
+                return Ok(Exit::Terminate);
+            }
+            match_count += 1;
+            if self.is_max_reached(match_count) {
+                if !self.truncation_status || self.quiet || self.lists_files() {
+                    break; // reached max count
+                }
+                max_reached = true;
+            }
+        }
+        if self.write_file_results(&[display_name], vec![match_count], sink)? == Exit::Terminate {
+            return Ok(Exit::Terminate);
+        }
+        if self.stats || self.summary || self.manifest.is_some() || !self.assertions.is_empty() {
+            let (records, lines, bytes) = records.searched();
+            self.file_stats.borrow_mut().push(FileStats {
+                filename: display_name.to_owned(),
+                records,
+                lines,
+                bytes,
+                selected: match_count,
+                elapsed: searched.elapsed(),
+                ..FileStats::default()
+            });
+        }
+        if truncated {
+            Ok(Exit::Truncated)
+        } else {
+            Ok(Exit::from(match_count))
+        }
+    }
+
+    /// Whether each record can be selected on its own, so a file can be
+    /// searched in chunks, at once, with '--threads'.
+    fn can_chunk(&self) -> bool {
+        self.threads > 1
+            && !self.follow
+            && self.record_delimiter.is_none()
+            && self.file_header.is_none()
+            && !self.has_start()
+            && self.end.is_none()
+            && self.since.is_none()
+            && self.sample.is_none()
+            && self.match_timeout.is_none()
+            && self.context_pattern.is_none()
+            && !self.annotate
+            && !self.since_start
+            && !self.stamp
+            && self.pair_key.is_none()
+            && self.each_pattern.is_empty()
+            && self.encoding.is_none()
+            && !self.count_matches
+            && !self.show_suppressed_count
+            && !self.squeeze_repeats
+    }
+
+    /// Whether a record is selected, for the chunks' threads, which can't
+    /// share the handler.
+    fn selector(&self) -> Selector {
+        let (pattern_set, negative_set, match_all, min_matches) = (
+            self.pattern_set.clone(),
+            self.negative_set.clone(),
+            self.match_all,
+            self.min_matches,
+        );
+        let (invert_match, min_bytes, max_bytes) =
+            (self.invert_match, self.min_bytes, self.max_bytes);
+        let suppress_set = self.suppress_set.clone();
+        Arc::new(move |text| {
+            min_bytes.is_none_or(|n| text.len() >= n)
+                && max_bytes.is_none_or(|n| text.len() <= n)
+                && invert_match
+                    ^ is_match(
+                        &pattern_set,
+                        negative_set.as_ref(),
+                        match_all,
+                        min_matches,
+                        text,
+                    )
+                && !suppress_set.as_ref().is_some_and(|re| re.is_match(text))
+        })
+    }
+
+    /// Add a selected record to the count, set, or clusters being gathered,
+    /// if any, returning whether it was.
+    fn aggregate(&self, filename: &str, record: &Record) -> bool {
+        if let Some(key) = &self.count_by {
+            self.tally(key, &record.text);
+        } else if let Some(key) = &self.count_distinct {
+            if let Some(value) = self.group_value(key, &record.text) {
+                self.distinct.borrow_mut().insert(value);
+            }
+        } else if self.cluster {
+            self.clusters.borrow_mut().insert(filename, record);
+        } else {
+            return false;
+        }
+        true
+    }
+
+    /// Pair a '--pair' START or END record by its key, writing both records
+    /// of a pair once its END is found.
+    fn pair(
+        &self,
+        key: &GroupKey,
+        filename: &str,
+        record: &Record,
+        ts: Option<Timestamp>,
+        needs_matches: bool,
+        sink: &mut LgrepWrite,
+    ) -> Result<Exit> {
+        let Some(value) = self.group_value(key, &record.text) else {
+            return Ok(Exit::NoMatch);
+        };
+        let is_start = self
+            .pattern_set
+            .find(record.text.as_str())
+            .is_some_and(|m| m.pattern() == PatternID::ZERO);
+        if is_start {
+            let waiting = Waiting {
+                filename: filename.to_owned(),
+                record: record.clone(),
+                ts,
+            };
+            self.pairs.borrow_mut().start(value, waiting);
+            return Ok(Exit::NoMatch);
+        }
+        let end = || Waiting {
+            filename: filename.to_owned(),
+            record: record.clone(),
+            ts,
+        };
+        let Some(start) = self.pairs.borrow_mut().end(value, end) else {
+            return Ok(Exit::NoMatch);
+        };
+        if self.pair_report.is_some() {
+            // only reported at the end
+            return Ok(Exit::NoMatch);
+        }
+        if self.write_selected(&start.filename, &start.record, None, needs_matches, sink)?
+            == Exit::Terminate
+        {
+            return Ok(Exit::Terminate);
+        }
+        let elapsed = start
+            .ts
+            .zip(ts)
+            .map(|(start, end)| format_offset(end.duration_since(start)));
+        self.write_selected(filename, record, elapsed.as_deref(), needs_matches, sink)
+    }
+
+    /// Whether selected records are written, rather than counted, etc.
+    fn writes_records(&self) -> bool {
+        !self.counts && !self.lists_files() && !self.quiet
+    }
+
+    fn write_selected(
+        &self,
+        filename: &str,
+        record: &Record,
+        annotation: Option<&str>,
+        needs_matches: bool,
+        sink: &mut LgrepWrite,
+    ) -> Result<Exit> {
+        let _span = span!(TRACE, "write");
+        if let Some(t) = &self.format {
+            sink.write_formatted(&self.render(t, filename, record))
+        } else if needs_matches {
+            sink.write_record_with_matches(
+                filename,
+                record,
+                self.find_matches(&record.text),
+                annotation,
+            )
+        } else {
+            sink.write_record(filename, record, annotation)
+        }
+    }
+
+    /// Write a file's count, or its name, once it's been searched, if that's
+    /// the output.
+    fn write_file_results(
+        &self,
+        filenames: &[&str],
+        segment_counts: Vec<usize>,
+        sink: &mut LgrepWrite,
+    ) -> Result<Exit> {
+        let match_count: usize = segment_counts.iter().sum();
+        if self.counts && self.quiet {
+            self.quiet_total.set(self.quiet_total.get() + match_count);
+        } else if self.counts {
+            for (name, count) in filenames.iter().zip(segment_counts) {
+                if sink.write_count(name, count)? == Exit::Terminate {
+                    return Ok(Exit::Terminate);
+                }
+            }
+        } else if self.files_with_matches && !self.quiet {
+            for (name, count) in filenames.iter().zip(segment_counts) {
+                if count > 0 && sink.write_filename(name)? == Exit::Terminate {
+                    return Ok(Exit::Terminate);
+                }
+            }
+        } else if self.files_without_match && !self.quiet && match_count == 0 {
+            // a set of continued files is one log, so has a match or doesn't
+            for name in filenames {
+                if sink.write_filename(name)? == Exit::Terminate {
+                    return Ok(Exit::Terminate);
+                }
+            }
+        }
+        Ok(Exit::NoMatch)
+    }
+
+    /// Whether a record (a line, as it's before the first log record) is part
+    /// of the file's header, which has `header_lines` already.
+    fn is_header(&self, text: &str, header_lines: usize) -> bool {
+        if self.log_pattern.is_match(text) {
+            return false;
+        }
+        match &self.file_header {
+            Some(FileHeader::Lines(n)) => header_lines < *n,
+            Some(FileHeader::Pattern(re)) => re.is_match(text),
+            None => false,
+        }
+    }
+
+    /// Whether the record is within the size limits, if any.
+    fn is_size_selected(&self, text: &str) -> bool {
+        self.min_bytes.is_none_or(|n| text.len() >= n)
+            && self.max_bytes.is_none_or(|n| text.len() <= n)
+    }
+
+    /// Whether the record matches the pattern(s), at least `min_matches`
+    /// times if set. An empty pattern matches every record, without running
+    /// any regex at all.
+    fn is_match(&self, text: &str) -> bool {
+        is_match(
+            &self.pattern_set,
+            self.negative_set.as_ref(),
+            self.match_all,
+            self.min_matches,
+            text,
+        )
+    }
+
+    /// Whether an otherwise selected record is left out by '--suppress-file',
+    /// which is counted if so.
+    fn is_suppressed(&self, text: &str) -> bool {
+        let suppressed = self
+            .suppress_set
+            .as_ref()
+            .is_some_and(|re| re.is_match(text));
+        if suppressed {
+            self.suppressed.set(self.suppressed.get() + 1);
+        }
+        suppressed
+    }
+
+    /// Search a record for each pattern on its own, for '--pattern-stats'.
+    fn measure_patterns(&self, text: &str) {
+        let mut pattern_stats = self.pattern_stats.borrow_mut();
+        for (re, stats) in self.each_pattern.iter().zip(pattern_stats.iter_mut()) {
+            let started = Instant::now();
+            let is_match = re.is_match(text);
+            stats.elapsed += started.elapsed();
+            stats.searched += 1;
+            stats.matched += usize::from(is_match);
+        }
+    }
+
+    /// Whether a record matches, or `None` if finding out took longer than
+    /// `--match-timeout`.
+    fn timed_match(&self, text: &str) -> Option<bool> {
+        let _span = span!(TRACE, "match");
+        let Some(watchdog) = &self.match_timeout else {
+            return Some(self.is_match(text));
+        };
+        watchdog.is_match(text)
+    }
+
+    /// Describe a record for `--annotate`.
+    fn describe(&self, filename: &str, record: &Record, ts: Option<Timestamp>) -> String {
+        let mut desc = format!(
+            "file={filename} record={} lines={}",
+            record.record_num,
+            record.text.split('\n').count()
+        );
+        if let Some(ts) = ts {
+            desc.push_str(&format!(" ts={}", self.timestamps.civil(ts)));
+        }
+        desc
+    }
+
+    /// Render a record with a '--format' template.
+    fn render(&self, template: &Template<FormatField>, filename: &str, record: &Record) -> String {
+        template.render(|f| match f {
+            FormatField::File => Some(filename.into()),
+            FormatField::Record => Some(record.record_num.to_string().into()),
+            FormatField::Line => Some(record.first_line.to_string().into()),
+            FormatField::Offset => Some(record.offset.to_string().into()),
+            FormatField::Text => Some(record.text.as_str().into()),
+            FormatField::Group(key) => self.group_value(key, &record.text).map(Into::into),
+        })
+    }
+
+    /// The current time, formatted like a record's timestamp, for '--stamp'.
+    fn stamp_now(&self) -> String {
+        self.timestamps
+            .civil(Timestamp::now())
+            .strftime("%Y-%m-%d %H:%M:%S%.3f")
+            .to_string()
+    }
+
+    /// Count a selected record under the text of its `key` group, if it
+    /// participated in the match.
+    fn tally(&self, key: &GroupKey, text: &str) {
+        if let Some(value) = self.group_value(key, text) {
+            *self
+                .tallies
+                .borrow_mut()
+                .entry(value.to_owned())
+                .or_default() += 1;
+        }
+    }
+
+    /// The text of a group in a record, if it participated in the match.
+    fn group_value<'t>(&self, key: &GroupKey, text: &'t str) -> Option<&'t str> {
+        if let GroupKey::LogField(n) = key {
+            return self.log_field(text, n);
+        }
+        let mut caps = self.pattern_set.create_captures();
+        self.pattern_set.captures(text, &mut caps);
+        let span = match key {
+            GroupKey::Index(i) => caps.get_group(*i),
+            GroupKey::Name(n) | GroupKey::LogField(n) => caps.get_group_by_name(n),
+        };
+        span.map(|s| group_text(text, s.range()))
+    }
+
+    /// Whether a (otherwise selected) record is in the `--sample`, by its
+    /// `--sample-by` group or its whole text.
+    fn is_sampled(&self, text: &str) -> bool {
+        let Some(rate) = self.sample else {
+            return true;
+        };
+        let key = match &self.sample_by {
+            Some(k) => match self.group_value(k, text) {
+                Some(v) => v,
+                None => return false,
+            },
+            None => text,
+        };
+        (stable_hash(key) as f64) < rate * u64::MAX as f64
+    }
+
+    /// The value of a named group of the log pattern, which is matched
+    /// against the record's first line.
+    fn log_field<'t>(&self, text: &'t str, name: &str) -> Option<&'t str> {
+        let first_line = text.split('\n').next().unwrap_or_default();
+        let mut caps = self.log_pattern.create_captures();
+        self.log_pattern.captures(first_line, &mut caps);
+        caps.get_group_by_name(name)
+            .map(|s| group_text(first_line, s.range()))
+    }
+
+    /// How many times the patterns match a record, for '--count-matches'.
+    /// An empty match (e.g., of an empty pattern) isn't a hit.
+    fn occurrences(&self, text: &str) -> usize {
+        self.pattern_set
+            .find_iter(text)
+            .filter(|m| !m.is_empty())
+            .count()
+    }
+
+    /// Find the matches to highlight in a record. In fast highlight mode,
+    /// only the line(s) of the first match are searched.
+    fn find_matches<'r>(&'r self, text: &'r str) -> FindMatches<'r, 'r> {
+        if self.fast_highlight {
+            if let Some(m) = self.pattern_set.find(text) {
+                let start = floor_char_boundary(text, m.start());
+                let end = ceil_char_boundary(text, m.end());
+                let start = text[..start].rfind('\n').map_or(0, |i| i + 1);
+                let end = text[end..].find('\n').map_or(text.len(), |i| end + i);
+                return self
+                    .pattern_set
+                    .find_iter(Input::new(text).range(start..end));
+            }
+        }
+        self.pattern_set.find_iter(text)
+    }
+
+    fn anchor_for(&self, source: &Source) -> Option<Anchor> {
+        if let Some(d) = self.start_date {
+            Some(Anchor::Date(d))
+        } else if self.time_only {
+            Some(Anchor::Modified(
+                source.modified.unwrap_or_else(SystemTime::now),
+            ))
+        } else {
+            None
         }
-        Ok(Exit::from(match_count))
+    }
+
+    /// Whether only filenames are written, not records.
+    fn lists_files(&self) -> bool {
+        self.files_with_matches || self.files_without_match
+    }
+
+    /// Whether the first match is all that's needed, as nothing is written.
+    /// With `--count`, quiet still counts everything, for a final total.
+    fn is_quiet_exit(&self) -> bool {
+        self.quiet && !self.counts && self.assertions.is_empty()
     }
 
     fn is_max_reached(&self, match_count: usize) -> bool {
-        self.quiet
+        self.is_quiet_exit()
+            || self.lists_files()
             || if let Some(mc) = self.max_count {
                 match_count >= mc
             } else {
@@ -156,6 +1454,14 @@ impl Handler {
         opt_re_match(&self.start, hay)
     }
 
+    fn is_start_budget_spent(&self, bytes: usize, records: usize) -> bool {
+        match self.start_within {
+            Some(Budget::Bytes(n)) => bytes >= n,
+            Some(Budget::Records(n)) => records >= n,
+            None => false,
+        }
+    }
+
     #[allow(dead_code)]
     fn has_end(&self) -> bool {
         self.end.is_some()
@@ -169,75 +1475,643 @@ impl Handler {
 impl Handler {
     pub(crate) fn new(cli: Cli) -> Result<Handler> {
         let mut re_builder = Regex::builder();
-        if cli.ignore_case {
-            re_builder.syntax(syntax::Config::new().case_insensitive(true));
+        let syntax_config = syntax::Config::new()
+            .case_insensitive(cli.ignore_case)
+            // without Unicode, '.' matches any byte, not any char
+            .unicode(!cli.no_unicode)
+            .utf8(!cli.no_unicode);
+        re_builder.syntax(syntax_config);
+        let unicode_word_boundaries = cli.no_unicode && cli.unicode_word_boundaries;
+        let mut re_config = Regex::config();
+        if let Some(limit) = cli.regex_size_limit {
+            re_config = re_config.nfa_size_limit(Some(limit));
+        }
+        if let Some(limit) = cli.dfa_size_limit {
+            re_config = re_config
+                .dfa_size_limit(Some(limit))
+                .hybrid_cache_capacity(limit);
+        }
+        re_builder.configure(re_config);
+        let build_re = |p: &str| {
+            if unicode_word_boundaries {
+                build_re(&re_builder, &unicode_word_boundaries_in(p))
+            } else {
+                build_re(&re_builder, p)
+            }
+        };
+        if cli.pattern_label.len() > cli.patterns.len() {
+            bail!(
+                "{} pattern labels were given, but only {} patterns",
+                cli.pattern_label.len(),
+                cli.patterns.len()
+            );
+        }
+        let literal = |p: String| {
+            if cli.fixed_strings {
+                regex_syntax::escape(&p)
+            } else {
+                p
+            }
+        };
+        let mut patterns: Vec<_> = cli.patterns.into_iter().map(literal).collect();
+        let mut pattern_labels = cli.pattern_label;
+        pattern_labels.resize(patterns.len(), String::new());
+        let mut negatives = Vec::new();
+        for f in &cli.pattern_files {
+            let text = std::fs::read_to_string(f)
+                .with_context(|| format!("Failed to read patterns from '{f}'"))?;
+            for p in pattern_file::parse(f, &text, cli.fixed_strings)? {
+                if p.negative {
+                    negatives.push(p.pattern);
+                } else {
+                    patterns.push(p.pattern);
+                    pattern_labels.push(p.label);
+                }
+            }
         }
-        let mut patterns = cli.patterns;
         if let Some(p) = cli.pattern {
-            patterns.push(p);
+            patterns.push(literal(p));
+            pattern_labels.push(String::new());
         }
-        let log_pattern = if let Some(p) = cli.log_pattern {
-            re_builder.build(&p)?
-        } else if let Ok(p) = env::var(ENV_LOG_PATTERN) {
-            re_builder.build(&p)?
+        // an empty pattern matches everything, so needn't be searched for, and
+        // so do only negative patterns
+        let match_all =
+            patterns.iter().any(String::is_empty) || patterns.is_empty() && !negatives.is_empty();
+        let (patterns, pattern_labels): (Vec<_>, Vec<_>) = patterns
+            .into_iter()
+            .zip(pattern_labels)
+            .filter(|(p, _)| !p.is_empty())
+            .unzip();
+        // with '--pair', START and END are the patterns, in that order
+        let patterns = if cli.pair.is_empty() {
+            patterns
         } else {
-            re_builder.build(DEFAULT_LOG_PATTERN)?
+            cli.pair.clone()
+        };
+        let (patterns, negatives): (Vec<_>, Vec<_>) = if unicode_word_boundaries {
+            let rewrite = |ps: Vec<String>| -> Vec<String> {
+                ps.iter().map(|p| unicode_word_boundaries_in(p)).collect()
+            };
+            (rewrite(patterns), rewrite(negatives))
+        } else {
+            (patterns, negatives)
+        };
+        let log_pattern_source = cli
+            .log_pattern
+            .or_else(|| env::var(ENV_LOG_PATTERN).ok())
+            .unwrap_or_else(|| DEFAULT_LOG_PATTERN.to_owned());
+        let log_pattern = build_re(&log_pattern_source)?;
+        let file_header = match cli.file_header {
+            Some(h) => Some(if let Ok(n) = h.parse() {
+                FileHeader::Lines(n)
+            } else {
+                FileHeader::Pattern(build_re(&h)?)
+            }),
+            None => None,
         };
         let start = if let Some(p) = cli.start {
-            Some(re_builder.build(&p)?)
+            Some(build_re(&p)?)
         } else {
             None
         };
         let end = if let Some(p) = cli.end {
-            Some(re_builder.build(&p)?)
+            Some(build_re(&p)?)
         } else {
             None
         };
+        let context_pattern = if let Some(p) = cli.context_pattern {
+            Some(build_re(&p)?)
+        } else {
+            None
+        };
+        let timestamps = if let Some(tz) = &cli.assume_tz {
+            TimestampParser::for_time_zone_name(tz)?
+        } else {
+            TimestampParser::new()
+        };
+        let cutoff = |arg: &Option<String>, option: &str| -> Result<Option<Timestamp>> {
+            arg.as_ref()
+                .map(|s| timestamps.parse_cutoff(s, Timestamp::now()))
+                .transpose()
+                .with_context(|| format!("Invalid {option}"))
+        };
+        let newer_than = cutoff(&cli.newer_than, "--newer-than")?;
+        let older_than = cutoff(&cli.older_than, "--older-than")?;
+        let since = if let Some(s) = &cli.since {
+            Some(timestamps.parse_arg(s)?)
+        } else {
+            None
+        };
+        let omit_lines = if let Some(p) = cli.omit_lines {
+            Some(build_re(&p)?)
+        } else {
+            None
+        };
+        let keep_lines = if let Some(p) = cli.keep_lines {
+            Some(build_re(&p)?)
+        } else {
+            None
+        };
+        let directories = if cli.recursive {
+            Directories::Recurse
+        } else {
+            cli.directories
+        };
         let mut files = cli.files;
         if files.is_empty() {
-            files.push(STDIN_FILENAME.to_owned())
+            files.push(if directories == Directories::Recurse {
+                ".".to_owned()
+            } else {
+                STDIN_FILENAME.to_owned()
+            })
+        }
+        let stdin_count = files.iter().filter(|f| *f == STDIN_FILENAME).count();
+        if stdin_count > 1 {
+            // the second read would silently find nothing
+            bail!("STDIN ('-') can only be searched once, but was given {stdin_count} times");
+        }
+        let recursing =
+            directories == Directories::Recurse && files.iter().any(|f| read::is_dir(f));
+        let files = Traversal {
+            action: directories,
+            dedupe: !cli.no_dedupe_files,
+            filter: FileFilter::new(&cli.include, &cli.exclude, &cli.exclude_dir)?
+                .with_modified(newer_than, older_than),
+        }
+        .expand(files)?;
+        let files = match cli.sort_files {
+            Some(order) => order.sort(files),
+            None => files,
+        };
+        let mut skipped = Vec::new();
+        let files = if let Some(max) = cli.max_filesize {
+            let (files, oversized) = read::partition_by_size(files, max as u64);
+            for (f, size) in oversized {
+                eprintln!("lgrep: '{f}': larger than --max-filesize; skipping");
+                skipped.push(FileStats {
+                    filename: f,
+                    skipped: Some(size),
+                    ..FileStats::default()
+                });
+            }
+            files
+        } else {
+            files
+        };
+        if cli.follow && files.len() != 1 {
+            bail!(
+                "--follow needs a single file, but was given {}",
+                files.len()
+            );
+        }
+        if cli.follow {
+            for f in [&cli.output, &cli.tee].into_iter().flatten() {
+                if OutputCompression::for_filename(f).is_some() {
+                    bail!("--follow never ends, so can't write '{f}' compressed");
+                }
+            }
         }
-        // no-filename wins, otherwise if requested or multi-file
+        // no-filename wins, otherwise if requested, multi-file, or recursive
         let filenames = if cli.no_filename {
             false
         } else {
-            cli.filename || files.len() > 1
+            // an archive is many files, and skipped files were still given
+            cli.filename
+                || files.len() + skipped.len() > 1
+                || recursing
+                || files.iter().any(|f| tar::is_archive(f))
+        };
+        let pattern_set = re_builder
+            .build_many(&patterns)
+            .map_err(explain_build_error)?;
+        let mut suppressions = Vec::new();
+        for f in &cli.suppress_file {
+            let text = std::fs::read_to_string(f)
+                .with_context(|| format!("Failed to read suppressions from '{f}'"))?;
+            for p in pattern_file::parse(f, &text, cli.fixed_strings)? {
+                if p.negative {
+                    bail!("'{f}' has a negative pattern, but suppressions can't");
+                }
+                if !p.pattern.is_empty() {
+                    suppressions.push(p.pattern);
+                }
+            }
+        }
+        if unicode_word_boundaries {
+            suppressions = suppressions
+                .iter()
+                .map(|p| unicode_word_boundaries_in(p))
+                .collect();
+        }
+        let suppress_set = if suppressions.is_empty() {
+            None
+        } else {
+            Some(
+                re_builder
+                    .build_many(&suppressions)
+                    .map_err(explain_build_error)?,
+            )
+        };
+        let negative_set = if negatives.is_empty() {
+            None
+        } else {
+            Some(
+                re_builder
+                    .build_many(&negatives)
+                    .map_err(explain_build_error)?,
+            )
+        };
+        let (each_pattern, pattern_stats) = if cli.pattern_stats {
+            patterns
+                .iter()
+                .map(|p| {
+                    let prefiltered = syntax::parse_with(p, &syntax_config).is_ok_and(|hir| {
+                        Prefilter::from_hir_prefix(MatchKind::LeftmostFirst, &hir).is_some()
+                    });
+                    let stats = PatternStats {
+                        pattern: p.clone(),
+                        prefiltered,
+                        ..PatternStats::default()
+                    };
+                    Ok((re_builder.build(p).map_err(explain_build_error)?, stats))
+                })
+                .collect::<Result<Vec<_>>>()?
+                .into_iter()
+                .unzip()
+        } else {
+            (Vec::new(), Vec::new())
+        };
+        let count_by = if let Some(g) = cli.count_by {
+            Some(group_key(&pattern_set, &log_pattern, g)?)
+        } else {
+            None
+        };
+        let pair_key = if cli.pair.is_empty() {
+            None
+        } else {
+            Some(group_key(&pattern_set, &log_pattern, cli.pair_key)?)
         };
+        let count_distinct = if let Some(g) = cli.count_distinct {
+            Some(group_key(&pattern_set, &log_pattern, g)?)
+        } else {
+            None
+        };
+        let format = if let Some(f) = &cli.format {
+            Some(Template::parse(f)?.try_map(|name| {
+                Ok(match name.as_str() {
+                    "file" => FormatField::File,
+                    "record" => FormatField::Record,
+                    "line" => FormatField::Line,
+                    "offset" => FormatField::Offset,
+                    "text" => FormatField::Text,
+                    _ => FormatField::Group(group_key(&pattern_set, &log_pattern, name)?),
+                })
+            })?)
+        } else {
+            None
+        };
+        let notifier = if let Some(url) = cli.notify_webhook {
+            let template = cli
+                .notify_template
+                .as_deref()
+                .map(NotifyField::parse_template)
+                .transpose()?;
+            Some(Notifier::new(
+                url,
+                files[0].clone(),
+                template,
+                cli.notify_interval,
+            ))
+        } else {
+            None
+        };
+        let sample_by = if let Some(g) = cli.sample_by {
+            Some(group_key(&pattern_set, &log_pattern, g)?)
+        } else {
+            None
+        };
+        let match_timeout = cli.match_timeout.map(|t| {
+            let (pattern_set, negative_set, min_matches) =
+                (pattern_set.clone(), negative_set.clone(), cli.min_matches);
+            // on the watchdog's thread, which can't share the handler
+            Watchdog::new(
+                t,
+                Arc::new(move |text| {
+                    is_match(
+                        &pattern_set,
+                        negative_set.as_ref(),
+                        match_all,
+                        min_matches,
+                        text,
+                    )
+                }),
+            )
+        });
         Ok(Handler {
             files,
-            pattern_set: re_builder.build_many(&patterns)?,
+            pattern_set,
+            negative_set,
+            suppress_set,
+            show_suppressed_count: cli.show_suppressed_count,
+            suppressed: Cell::default(),
+            match_all,
             max_count: cli.max_count,
+            truncation_status: cli.truncation_status,
+            min_matches: cli.min_matches,
+            min_bytes: cli.min_bytes,
+            max_bytes: cli.max_bytes_record,
             invert_match: cli.invert_match,
-            counts: cli.count,
-            line_numbers: cli.line_number && !cli.count,
+            pattern_labels,
+            counts: cli.count || cli.count_matches,
+            count_matches: cli.count_matches,
+            files_with_matches: cli.files_with_matches,
+            files_without_match: cli.files_without_match,
+            count_by,
+            sample: cli.sample,
+            match_timeout,
+            sample_by,
+            tallies: RefCell::default(),
+            pair_key,
+            pairs: RefCell::new(if cli.pair_report.is_some() {
+                Pairs::keeping_unmatched()
+            } else {
+                Pairs::default()
+            }),
+            pair_report: cli.pair_report,
+            each_pattern,
+            pattern_stats: RefCell::new(pattern_stats),
+            count_distinct,
+            cluster: cli.cluster,
+            clusters: RefCell::default(),
+            distinct: RefCell::new(if cli.approx {
+                Distinct::approx()
+            } else {
+                Distinct::default()
+            }),
+            line_numbers: cli.line_number
+                && !cli.count
+                && !cli.count_matches
+                && !cli.files_with_matches
+                && !cli.files_without_match,
+            record_numbers: cli.record_number
+                && !cli.count
+                && !cli.count_matches
+                && !cli.files_with_matches
+                && !cli.files_without_match,
+            null: cli.null,
             color_mode: cli.color,
+            tee: cli.tee,
+            output: cli.output,
+            output_compression: cli.compress_output,
             quiet: cli.quiet,
+            quiet_total: Cell::default(),
             stdin_label: cli.label,
+            file_header,
             log_pattern,
+            log_pattern_source,
+            record_delimiter: if cli.null_data {
+                Some(vec![0])
+            } else if let Some(d) = &cli.record_delimiter_bytes {
+                Some(parse_delimiter(d)?)
+            } else {
+                None
+            },
             start,
+            start_within: cli.start_within,
+            since,
+            index: cli.index,
             end,
+            context_pattern,
+            since_start: cli.since_start,
+            stamp: cli.stamp,
+            annotate: cli.annotate,
+            timestamps,
+            time_only: cli.time_only || cli.start_date.is_some(),
+            start_date: cli.start_date,
+            continue_records: cli.continue_records,
+            follow: cli.follow,
+            notifier,
+            merge: cli.merge,
+            decompress_thread: cli.decompress_thread,
+            encoding: cli.encoding,
+            max_record_lines: cli.max_record_lines.map(NonZeroUsize::get),
+            threads: cli.threads,
+            fast_highlight: cli.fast_highlight,
+            color_by_level: cli.color_by_level,
+            color_by_pattern: cli.color_by_pattern,
+            level_map: cli.level_map,
+            wrap: cli.wrap,
+            omit_lines,
+            keep_lines,
+            diff_similar: cli.diff_similar,
+            squeeze_repeats: cli.squeeze_repeats,
+            format,
+            json: cli.json,
+            tag: cli.tag,
+            stats: cli.stats,
+            summary: cli.summary,
+            manifest: cli.manifest,
+            file_stats: RefCell::new(skipped),
+            assertions: cli.assert,
             filenames,
         })
     }
 }
 
+fn create_file(path: &str) -> Result<File> {
+    File::create(path).with_context(|| format!("Failed to open '{path}' for writing"))
+}
+
+/// Flush an output, and end it if compressed, so a full disk or the like
+/// isn't silently ignored.
+fn finish(sink: &mut BufWriter<Encoder>) -> Result<()> {
+    match sink.flush().and_then(|_| sink.get_mut().finish()) {
+        // nothing is listening anymore
+        Err(e) if e.kind() == ErrorKind::BrokenPipe => Ok(()),
+        r => r.context("Failed to write"),
+    }
+}
+
+/// The text of a capture group, widened to char boundaries.
+fn group_text(text: &str, range: Range<usize>) -> &str {
+    let start = floor_char_boundary(text, range.start);
+    let end = ceil_char_boundary(text, range.end);
+    &text[start..end]
+}
+
+/// Resolve a `--count-by` (or `--sample-by`) group, which must exist in at least one pattern, or
+/// be a named group of the log pattern.
+fn group_key(re: &Regex, log_pattern: &Regex, spec: String) -> Result<GroupKey> {
+    let key = if let Ok(i) = spec.parse() {
+        GroupKey::Index(i)
+    } else {
+        GroupKey::Name(spec)
+    };
+    let info = re.group_info();
+    let known = (0..re.pattern_len())
+        .map(PatternID::must)
+        .any(|pid| match &key {
+            GroupKey::Index(i) => *i < info.group_len(pid),
+            GroupKey::Name(n) | GroupKey::LogField(n) => info.to_index(pid, n).is_some(),
+        });
+    if known {
+        return Ok(key);
+    }
+    match key {
+        GroupKey::Name(n)
+            if log_pattern
+                .group_info()
+                .to_index(PatternID::ZERO, &n)
+                .is_some() =>
+        {
+            Ok(GroupKey::LogField(n))
+        }
+        GroupKey::Index(i) => bail!("No pattern has a capture group {i}"),
+        GroupKey::Name(n) | GroupKey::LogField(n) => {
+            bail!("Neither the pattern(s) nor the log pattern have a capture group named '{n}'")
+        }
+    }
+}
+
+fn build_re(builder: &Builder, pattern: &str) -> Result<Regex> {
+    builder.build(pattern).map_err(explain_build_error)
+}
+
+/// Rewrite a pattern so its word boundary assertions (`\b`, `\B`, and the
+/// `\b{...}` forms) are Unicode-aware, even if the rest of it isn't.
+fn unicode_word_boundaries_in(pattern: &str) -> String {
+    let mut result = String::with_capacity(pattern.len() + 16);
+    let mut chars = pattern.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some(b @ ('b' | 'B')) => {
+                result.push_str("(?u:\\");
+                result.push(b);
+                if chars.peek() == Some(&'{') {
+                    for c in chars.by_ref() {
+                        result.push(c);
+                        if c == '}' {
+                            break;
+                        }
+                    }
+                }
+                result.push(')');
+            }
+            Some(e) => {
+                result.push(c);
+                result.push(e);
+            }
+            None => result.push(c),
+        }
+    }
+    result
+}
+
+/// Regex compilation errors are pretty opaque, especially when a size limit is
+/// exceeded, so add a hint about what to do.
+fn explain_build_error(e: BuildError) -> anyhow::Error {
+    if let Some(limit) = e.size_limit() {
+        anyhow!(
+            "{e}: the pattern(s) compile to more than {limit} bytes; simplify them, \
+             or raise the limit with '--regex-size-limit'"
+        )
+    } else {
+        anyhow::Error::from(e)
+    }
+}
+
 impl Handler {
     #[cfg(test)]
     fn empty() -> Handler {
         Handler {
             files: Vec::new(),
             pattern_set: Regex::new_many(&[r"a"]).unwrap(),
+            negative_set: None,
+            suppress_set: None,
+            show_suppressed_count: false,
+            suppressed: Cell::default(),
+            match_all: false,
             max_count: None,
+            truncation_status: false,
+            min_matches: None,
+            min_bytes: None,
+            max_bytes: None,
             invert_match: false,
+            pattern_labels: vec![],
             counts: false,
+            count_matches: false,
+            files_with_matches: false,
+            files_without_match: false,
+            count_by: None,
+            sample: None,
+            match_timeout: None,
+            sample_by: None,
+            tallies: RefCell::default(),
+            count_distinct: None,
+            pair_key: None,
+            pairs: RefCell::default(),
+            pair_report: None,
+            each_pattern: Vec::new(),
+            pattern_stats: RefCell::default(),
+            cluster: false,
+            clusters: RefCell::default(),
+            distinct: RefCell::default(),
             color_mode: ColorChoice::Auto,
+            tee: None,
+            output: None,
+            output_compression: None,
             quiet: false,
+            quiet_total: Cell::default(),
             stdin_label: None,
+            file_header: None,
             log_pattern: Regex::new(DEFAULT_LOG_PATTERN).unwrap(),
+            log_pattern_source: DEFAULT_LOG_PATTERN.to_owned(),
+            record_delimiter: None,
             start: None,
+            start_within: None,
+            since: None,
+            index: None,
             end: None,
+            context_pattern: None,
+            since_start: false,
+            stamp: false,
+            annotate: false,
+            timestamps: TimestampParser::new(),
+            time_only: false,
+            start_date: None,
+            continue_records: false,
+            follow: false,
+            notifier: None,
+            merge: false,
+            decompress_thread: false,
+            encoding: None,
+            max_record_lines: None,
+            threads: 1,
+            fast_highlight: false,
+            color_by_level: false,
+            color_by_pattern: false,
+            level_map: Vec::new(),
+            wrap: Wrap::Never,
+            omit_lines: None,
+            keep_lines: None,
+            diff_similar: false,
+            squeeze_repeats: false,
+            format: None,
+            json: false,
+            tag: false,
+            stats: false,
+            summary: false,
+            manifest: None,
+            file_stats: RefCell::default(),
+            assertions: Vec::new(),
             filenames: false,
             line_numbers: false,
+            record_numbers: false,
+            null: false,
         }
     }
 }
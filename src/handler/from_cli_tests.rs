@@ -35,6 +35,34 @@ fn pattern() {
     assert_re(&h.pattern_set, &["a goat horn"], &["a cow horn"]);
 }
 
+#[test]
+fn pattern_stats() {
+    let h = Handler::new(Cli {
+        patterns: vec!["queue".to_owned(), r"\d+ms".to_owned()],
+        pattern_stats: true,
+        ..Cli::empty()
+    })
+    .unwrap();
+    assert_eq!(2, h.each_pattern.len());
+    let source = Source::new(
+        "app.log",
+        Box::new(std::io::Cursor::new(
+            "2024-07-01 01:25:46.123 draining queue\n2024-07-01 01:25:47.790 took 12ms\n",
+        )),
+    );
+    let mut buf = std::io::BufWriter::new(Vec::new());
+    h.process_file(source, &mut LgrepWrite::new(false, false, false, &mut buf))
+        .unwrap();
+    let stats = h.pattern_stats.borrow();
+    assert_eq!(
+        vec![("queue", true, 2, 1), (r"\d+ms", false, 2, 1)],
+        stats
+            .iter()
+            .map(|s| (s.pattern.as_str(), s.prefiltered, s.searched, s.matched))
+            .collect::<Vec<_>>()
+    );
+}
+
 #[test]
 fn patterns() {
     let h = Handler::new(Cli {
@@ -60,6 +88,169 @@ fn pattern_and_patterns() {
     );
 }
 
+#[test]
+fn empty_pattern() {
+    let h = Handler::new(Cli {
+        pattern: Some("".to_owned()),
+        patterns: vec!["b".to_owned()],
+        ..Cli::empty()
+    })
+    .unwrap();
+    assert!(h.match_all);
+    assert_eq!(1, h.pattern_set.pattern_len());
+    assert!(h.is_match("anything"));
+}
+
+#[test]
+fn pattern_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("patterns.txt");
+    std::fs::write(&path, "goat\r\ncow\n").unwrap();
+    let h = Handler::new(Cli {
+        patterns: vec!["pig".to_owned()],
+        pattern_files: vec![path.to_string_lossy().into_owned()],
+        ..Cli::empty()
+    })
+    .unwrap();
+    assert_eq!(3, h.pattern_set.pattern_len());
+    assert_re(&h.pattern_set, &["a goat", "cowpen", "pigsty"], &["horse"]);
+    assert!(Handler::new(Cli {
+        pattern_files: vec!["no-such-patterns.txt".to_owned()],
+        ..Cli::empty()
+    })
+    .is_err());
+}
+
+#[test]
+fn pattern_file_directives() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("directives.txt");
+    std::fs::write(
+        &path,
+        "# known-benign errors\n# flags: i\nlabel: TO timeout\n! retrying\n",
+    )
+    .unwrap();
+    let h = Handler::new(Cli {
+        patterns: vec!["refused".to_owned()],
+        pattern_label: vec!["REFUSED".to_owned()],
+        pattern_files: vec![path.to_string_lossy().into_owned()],
+        ..Cli::empty()
+    })
+    .unwrap();
+    assert_eq!(vec!["REFUSED", "TO"], h.pattern_labels);
+    assert!(h.is_match("TIMEOUT"));
+    assert!(h.is_match("connection refused"));
+    assert!(!h.is_match("Timeout; Retrying"));
+    assert!(!h.is_match("Refused"));
+}
+
+#[test]
+fn fixed_strings() {
+    let h = Handler::new(Cli {
+        patterns: vec!["foo(bar)+".to_owned(), "[x]".to_owned()],
+        fixed_strings: true,
+        log_pattern: Some(r"^\d".to_owned()),
+        ..Cli::empty()
+    })
+    .unwrap();
+    assert_re(
+        &h.pattern_set,
+        &["a foo(bar)+ b", "[x]"],
+        &["foobarbar", "x"],
+    );
+    // only the main patterns are fixed
+    assert_re(&h.log_pattern, &["2024"], &[r"^\d"]);
+}
+
+#[test]
+fn pattern_labels() {
+    let h = Handler::new(Cli {
+        patterns: vec!["a".to_owned(), "".to_owned(), "c".to_owned()],
+        pattern_label: vec!["A".to_owned(), "ALL".to_owned()],
+        ..Cli::empty()
+    })
+    .unwrap();
+    // the empty pattern's label goes with it
+    assert_eq!(vec!["A", ""], h.pattern_labels);
+    assert!(Handler::new(Cli {
+        patterns: vec!["a".to_owned()],
+        pattern_label: vec!["A".to_owned(), "B".to_owned()],
+        ..Cli::empty()
+    })
+    .is_err());
+}
+
+#[test]
+fn follow_compressed_output() {
+    let cli = || Cli {
+        pattern: Some("queue".to_owned()),
+        files: vec!["app.log".to_owned()],
+        follow: true,
+        ..Cli::empty()
+    };
+    assert!(Handler::new(Cli {
+        tee: Some("out.log".to_owned()),
+        ..cli()
+    })
+    .is_ok());
+    for f in ["out.log.gz", "out.log.zst"] {
+        assert!(Handler::new(Cli {
+            tee: Some(f.to_owned()),
+            ..cli()
+        })
+        .is_err());
+        assert!(Handler::new(Cli {
+            output: Some(f.to_owned()),
+            ..cli()
+        })
+        .is_err());
+    }
+}
+
+#[test]
+fn count_by() {
+    let cli = || Cli {
+        pattern: Some(r"user=(?<user>\w+)".to_owned()),
+        ..Cli::empty()
+    };
+    let h = Handler::new(Cli {
+        count_by: Some("user".to_owned()),
+        ..cli()
+    })
+    .unwrap();
+    assert_eq!(Some(GroupKey::Name("user".to_owned())), h.count_by);
+    let h = Handler::new(Cli {
+        count_by: Some("1".to_owned()),
+        ..cli()
+    })
+    .unwrap();
+    assert_eq!(Some(GroupKey::Index(1)), h.count_by);
+    for bad in ["2", "host"] {
+        assert!(Handler::new(Cli {
+            count_by: Some(bad.to_owned()),
+            ..cli()
+        })
+        .is_err());
+    }
+}
+
+#[test]
+fn count_by_log_field() {
+    let h = Handler::new(Cli {
+        pattern: Some("user=(?<user>\\w+)".to_owned()),
+        log_pattern: Some(r"^\d+ (?<level>[A-Z]+) ".to_owned()),
+        count_by: Some("level".to_owned()),
+        ..Cli::empty()
+    })
+    .unwrap();
+    assert_eq!(Some(GroupKey::LogField("level".to_owned())), h.count_by);
+    assert_eq!(
+        Some("WARN"),
+        h.log_field("123 WARN login user=bob\n  more", "level")
+    );
+    assert_eq!(None, h.log_field("continued", "level"));
+}
+
 #[test]
 fn ignore_case() {
     let h = Handler::new(Cli {
@@ -92,6 +283,17 @@ fn explicit_stdin() {
     assert!(!h.filenames);
 }
 
+#[test]
+fn stdin_twice() {
+    let e = Handler::new(Cli {
+        files: vec!["-".to_owned(), "app.log".to_owned(), "-".to_owned()],
+        ..Cli::empty()
+    })
+    .err()
+    .unwrap();
+    assert!(e.to_string().contains("given 2 times"), "{e}");
+}
+
 #[test]
 fn one_file() {
     let h = Handler::new(Cli {
@@ -115,6 +317,22 @@ fn one_file_with_filenames() {
     assert!(h.filenames);
 }
 
+#[test]
+fn recursive() {
+    let h = Handler::new(Cli {
+        files: vec!["src/write".to_owned()],
+        recursive: true,
+        ..Cli::empty()
+    })
+    .unwrap();
+    assert_eq!(
+        Some(&"src/write/capabilities.rs".to_owned()),
+        h.files.first()
+    );
+    assert!(h.files.iter().all(|f| f.starts_with("src/write/")));
+    assert!(h.filenames);
+}
+
 #[test]
 fn several_files() {
     let h = Handler::new(Cli {
@@ -142,6 +360,7 @@ fn several_files_no_filenames() {
 fn passthroughs() {
     let h = Handler::new(Cli {
         max_count: Some(1),
+        min_matches: Some(2),
         invert_match: true,
         count: true,
         color: ColorChoice::Always,
@@ -151,9 +370,96 @@ fn passthroughs() {
     })
     .unwrap();
     assert_eq!(Some(1), h.max_count);
+    assert_eq!(Some(2), h.min_matches);
     assert!(h.invert_match);
     assert!(h.counts);
     assert_eq!(ColorChoice::Always, h.color_mode);
     assert!(h.quiet);
     assert_eq!(Some("goat".to_owned()), h.stdin_label);
 }
+
+#[test]
+fn null_data() {
+    let h = Handler::new(Cli {
+        null_data: true,
+        ..Cli::empty()
+    })
+    .unwrap();
+    assert_eq!(Some(vec![0]), h.record_delimiter);
+}
+
+#[test]
+fn assume_tz() {
+    assert!(Handler::new(Cli {
+        assume_tz: Some("Europe/Berlin".to_owned()),
+        ..Cli::empty()
+    })
+    .is_ok());
+    assert!(Handler::new(Cli {
+        assume_tz: Some("Nowhere/Special".to_owned()),
+        ..Cli::empty()
+    })
+    .is_err());
+}
+
+#[test]
+fn start_date_implies_time_only() {
+    let h = Handler::new(Cli {
+        start_date: Some(jiff::civil::date(2024, 7, 1)),
+        ..Cli::empty()
+    })
+    .unwrap();
+    assert!(h.time_only);
+    assert_eq!(Some(jiff::civil::date(2024, 7, 1)), h.start_date);
+}
+
+#[test]
+fn regex_size_limit() {
+    let err = Handler::new(Cli {
+        pattern: Some(r"\w{1000}".to_owned()),
+        regex_size_limit: Some(1024),
+        ..Cli::empty()
+    })
+    .err()
+    .unwrap();
+    assert!(
+        err.to_string().contains("--regex-size-limit"),
+        "unhelpful message: {err}"
+    );
+    assert!(Handler::new(Cli {
+        pattern: Some(r"\w{10}".to_owned()),
+        regex_size_limit: Some(1024 * 1024),
+        dfa_size_limit: Some(1024 * 1024),
+        ..Cli::empty()
+    })
+    .is_ok());
+}
+
+#[test]
+fn no_unicode() {
+    let h = Handler::new(Cli {
+        pattern: Some(r"^\w+$".to_owned()),
+        ..Cli::empty()
+    })
+    .unwrap();
+    assert_re(&h.pattern_set, &["café"], &["a b"]);
+    let h = Handler::new(Cli {
+        pattern: Some(r"^\w+$".to_owned()),
+        no_unicode: true,
+        ..Cli::empty()
+    })
+    .unwrap();
+    assert_re(&h.pattern_set, &["cafe"], &["café"]);
+}
+
+#[test]
+fn unicode_word_boundaries() {
+    let h = Handler::new(Cli {
+        pattern: Some(r"\bfé\b".to_owned()),
+        no_unicode: true,
+        unicode_word_boundaries: true,
+        ..Cli::empty()
+    })
+    .unwrap();
+    assert_re(&h.pattern_set, &["café fé au lait"], &["caféfé"]);
+}
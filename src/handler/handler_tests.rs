@@ -1,6 +1,8 @@
 use std::fmt::{Display, Formatter};
 use std::io::Cursor;
 use std::io::{BufWriter, Write};
+use std::thread;
+use std::time::Duration;
 
 use clap::ColorChoice;
 
@@ -337,6 +339,571 @@ four'n'stuff",
     assert_eq!(Some(Exit::Match), mac.exit);
 }
 
+#[test]
+fn line_endings_preserved() {
+    let handler = Handler {
+        pattern_set: Regex::new(r"o").unwrap(),
+        log_pattern: Regex::new(r"^\S").unwrap(),
+        ..Handler::empty()
+    };
+    let mac = MatchesAndCount::run(&handler, "one\r\n  more\r\ntwo\nthree\nfour");
+    assert_eq!(vec!["one\r\n  more\r\n", "two\n", "four"], mac.records);
+}
+
+#[test]
+fn line_endings_prefixed() {
+    let handler = Handler {
+        pattern_set: Regex::new(r"o").unwrap(),
+        line_numbers: true,
+        ..Handler::empty()
+    };
+    let mac = MatchesAndCount::run(&handler, "one\r\nfour");
+    assert_eq!(vec!["1:one\r\n", "2:four\n"], mac.records);
+}
+
+#[test]
+fn files_with_matches() {
+    let handler = Handler {
+        pattern_set: Regex::new(r"o").unwrap(),
+        files_with_matches: true,
+        ..Handler::empty()
+    };
+    let mac = MatchesAndCount::run(&handler, "one\ntwo\nthree");
+    assert_eq!(vec!["input.txt\n"], mac.records);
+    assert_eq!(Some(Exit::Match), mac.exit);
+    let mac = MatchesAndCount::run(&handler, "three\nfive");
+    assert!(mac.records.is_empty());
+    assert_eq!(Some(Exit::NoMatch), mac.exit);
+}
+
+#[test]
+fn files_without_match() {
+    let handler = Handler {
+        pattern_set: Regex::new(r"o").unwrap(),
+        files_without_match: true,
+        ..Handler::empty()
+    };
+    let mac = MatchesAndCount::run(&handler, "one\ntwo\nthree");
+    assert!(mac.records.is_empty());
+    assert_eq!(Some(Exit::Match), mac.exit);
+    let mac = MatchesAndCount::run(&handler, "three\nfive");
+    assert_eq!(vec!["input.txt\n"], mac.records);
+    assert_eq!(Some(Exit::NoMatch), mac.exit);
+}
+
+#[test]
+fn sample_by_key() {
+    let text = "login user=amy\nlogin user=bob\nlogout user=amy\nlogin user=cat\nlogout user=bob\nlogout user=cat";
+    let handler = |rate| Handler {
+        pattern_set: Regex::new(r"user=(?<user>\w+)").unwrap(),
+        log_pattern: Regex::new(r"").unwrap(),
+        sample: Some(rate),
+        sample_by: Some(GroupKey::Name("user".to_owned())),
+        ..Handler::empty()
+    };
+    assert!(MatchesAndCount::run(&handler(0.0), text).records.is_empty());
+    assert_eq!(6, MatchesAndCount::run(&handler(1.0), text).records.len());
+    let records = MatchesAndCount::run(&handler(0.5), text).records;
+    for user in ["amy", "bob", "cat"] {
+        let n = records.iter().filter(|r| r.contains(user)).count();
+        // all or nothing
+        assert!(n == 0 || n == 2, "{user} had {n} records sampled");
+    }
+}
+
+#[test]
+fn match_timeout() {
+    let handler = Handler {
+        pattern_set: Regex::new(r"o").unwrap(),
+        match_timeout: Some(Watchdog::new(
+            Duration::from_millis(10),
+            Arc::new(|_| {
+                thread::sleep(Duration::from_secs(5));
+                true
+            }),
+        )),
+        stats: true,
+        ..Handler::empty()
+    };
+    let mac = MatchesAndCount::run(&handler, "one\ntwo\nthree");
+    assert!(mac.records.is_empty());
+    assert_eq!(Some(Exit::NoMatch), mac.exit);
+    assert_eq!(3, handler.file_stats.borrow()[0].timed_out);
+}
+
+#[test]
+fn quiet_count() {
+    let handler = Handler {
+        pattern_set: Regex::new(r"o").unwrap(),
+        quiet: true,
+        counts: true,
+        ..Handler::empty()
+    };
+    let mac = MatchesAndCount::run(&handler, "one\ntwo\nthree\nfour");
+    assert!(mac.records.is_empty());
+    assert_eq!(Some(Exit::Match), mac.exit);
+    // not just the first
+    assert_eq!(3, handler.quiet_total.get());
+}
+
+#[test]
+fn record_numbers() {
+    let handler = Handler {
+        pattern_set: Regex::new(r"r").unwrap(),
+        log_pattern: Regex::new(r"e").unwrap(),
+        filenames: true,
+        line_numbers: true,
+        ..Handler::empty()
+    };
+    let source = Source::new("spiffy.txt", Box::new(Cursor::new("one\ntwo\nthree\nfour")));
+    let mut buf_writer = BufWriter::new(MatchesAndCount::default());
+    let mut write = LgrepWrite::new(false, true, true, &mut buf_writer).with_record_numbers();
+    handler.process_file(source, &mut write).unwrap();
+    drop(write);
+    assert_eq!(
+        vec!["spiffy.txt:2:3:three\nspiffy.txt-2-4-four\n"],
+        buf_writer.into_inner().unwrap().records
+    );
+}
+
+#[test]
+fn null_after_filenames() {
+    let handler = Handler {
+        pattern_set: Regex::new(r"o").unwrap(),
+        files_with_matches: true,
+        ..Handler::empty()
+    };
+    let source = || Source::new("in put.txt", Box::new(Cursor::new("one\ntwo\nthree")));
+    let mut buf_writer = BufWriter::new(MatchesAndCount::default());
+    let mut write = LgrepWrite::new(false, true, false, &mut buf_writer).with_null();
+    handler.process_file(source(), &mut write).unwrap();
+    let handler = Handler {
+        files_with_matches: false,
+        ..handler
+    };
+    handler.process_file(source(), &mut write).unwrap();
+    drop(write);
+    assert_eq!(
+        vec!["in put.txt\0", "in put.txt\0one\n", "in put.txt\0two\n"],
+        buf_writer.into_inner().unwrap().records
+    );
+}
+
+#[test]
+fn wrapped() {
+    let handler = Handler {
+        pattern_set: Regex::new(r"o").unwrap(),
+        log_pattern: Regex::new(r"^L").unwrap(),
+        ..Handler::empty()
+    };
+    let source = Source::new(
+        "a.log",
+        Box::new(Cursor::new(
+            "L one two three four five six\nseven eight\nL nine",
+        )),
+    );
+    let mut buf_writer = BufWriter::new(MatchesAndCount::default());
+    let mut write = LgrepWrite::new(false, true, true, &mut buf_writer).with_wrap(28);
+    handler.process_file(source, &mut write).unwrap();
+    drop(write);
+    assert_eq!(
+        vec!["a.log:1:L one two three four\n        ↪  five six\na.log-2-seven eight\n"],
+        buf_writer.into_inner().unwrap().records
+    );
+}
+
+#[test]
+fn omitted_lines() {
+    let handler = Handler {
+        pattern_set: Regex::new(r"(?i)error").unwrap(),
+        ..Handler::empty()
+    };
+    let source = Source::new("app.log", Box::new(Cursor::new(APP_LOG)));
+    let mut buf_writer = BufWriter::new(MatchesAndCount::default());
+    let mut write = LgrepWrite::new(false, false, true, &mut buf_writer)
+        .with_omit_lines(Regex::new(r"^\s+at org\.").unwrap());
+    handler.process_file(source, &mut write).unwrap();
+    drop(write);
+    assert_eq!(
+        vec![
+            "2:2024-07-01 01:25:47.755 Unexpected error occurred in scheduled task\n\
+             3-org.springframework.transaction.CannotCreateTransactionException: Could not open JPA EntityManager for transaction\n\
+             8-    at com.brennaswitzer.cookbook.async.QueueProcessor.drainQueueInternal(QueueProcessor.java:68)\n\
+             9-    ... many more frames ...\n",
+            "10:2024-07-01 01:25:47.790 queue draining complete (ERROR)\n",
+        ],
+        buf_writer.into_inner().unwrap().records
+    );
+}
+
+#[test]
+fn kept_lines() {
+    let handler = Handler {
+        pattern_set: Regex::new(r"Unexpected").unwrap(),
+        ..Handler::empty()
+    };
+    let source = Source::new("app.log", Box::new(Cursor::new(APP_LOG)));
+    let mut buf_writer = BufWriter::new(MatchesAndCount::default());
+    let mut write = LgrepWrite::new(false, false, true, &mut buf_writer)
+        .with_keep_lines(Regex::new(r"cookbook|Exception").unwrap())
+        .with_omit_lines(Regex::new(r"^\s+at ").unwrap());
+    handler.process_file(source, &mut write).unwrap();
+    drop(write);
+    assert_eq!(
+        vec![
+            "2:2024-07-01 01:25:47.755 Unexpected error occurred in scheduled task\n\
+             3-org.springframework.transaction.CannotCreateTransactionException: Could not open JPA EntityManager for transaction\n",
+        ],
+        buf_writer.into_inner().unwrap().records
+    );
+}
+
+#[test]
+fn since() {
+    let mut log = String::new();
+    for i in 0..10_000 {
+        log.push_str(&format!(
+            "2024-07-01 {:02}:{:02}:{:02}.000 record {i}\n  detail\n",
+            i / 3600,
+            i / 60 % 60,
+            i % 60
+        ));
+    }
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("since.log");
+    std::fs::write(&path, &log).unwrap();
+    let handler = Handler {
+        files: vec![path.to_string_lossy().into_owned()],
+        pattern_set: Regex::new(r"record \d*7\b").unwrap(),
+        since: Some("2024-07-01T02:30:00Z".parse().unwrap()),
+        timestamps: TimestampParser::with_time_zone(jiff::tz::TimeZone::UTC),
+        ..Handler::empty()
+    };
+    let mut buf_writer = BufWriter::new(MatchesAndCount::default());
+    let mut write = LgrepWrite::new(false, false, false, &mut buf_writer);
+    assert_eq!(Exit::Match, handler.run_with(&mut write).unwrap());
+    drop(write);
+    let records = buf_writer.into_inner().unwrap().records;
+    // 9000, 9001, ... 9999
+    assert_eq!(100, records.len());
+    assert_eq!(
+        "2024-07-01 02:30:07.000 record 9007\n  detail\n",
+        records[0]
+    );
+}
+
+#[test]
+fn stamped() {
+    let handler = Handler {
+        pattern_set: Regex::new(r"line").unwrap(),
+        stamp: true,
+        ..Handler::empty()
+    };
+    let mac = MatchesAndCount::run(
+        &handler,
+        "raw line\n2024-07-01 01:25:47.755 a stamped line\nanother raw line\n",
+    );
+    let stamp = Regex::new(r"^\d{4}-\d{2}-\d{2} \d{2}:\d{2}:\d{2}\.\d{3} ").unwrap();
+    assert_eq!(2, mac.records.len());
+    assert!(stamp.is_match(&mac.records[0]), "{}", mac.records[0]);
+    assert!(mac.records[0].ends_with(" raw line\n"));
+    assert_eq!(
+        "2024-07-01 01:25:47.755 a stamped line\nanother raw line\n",
+        mac.records[1]
+    );
+}
+
+#[test]
+fn formatted() {
+    let handler = Handler {
+        pattern_set: Regex::new(r"(?<what>draining|complete)(?: (queue))?").unwrap(),
+        format: Some(
+            Template::parse("{file}#{record}:{line}@{offset} {what}/{2} {{{text}}}")
+                .unwrap()
+                .try_map(|n| {
+                    Ok(match n.as_str() {
+                        "file" => FormatField::File,
+                        "record" => FormatField::Record,
+                        "line" => FormatField::Line,
+                        "offset" => FormatField::Offset,
+                        "text" => FormatField::Text,
+                        "what" => FormatField::Group(GroupKey::Name(n)),
+                        _ => FormatField::Group(GroupKey::Index(n.parse().unwrap())),
+                    })
+                })
+                .unwrap(),
+        ),
+        ..Handler::empty()
+    };
+    let mac = MatchesAndCount::run(&handler, APP_LOG);
+    assert_eq!(
+        vec![
+            "input.txt#1:1@0 draining/queue {2024-07-01 01:25:46.123 draining queue}\n",
+            "input.txt#3:10@837 draining/ {2024-07-01 01:25:47.790 queue draining complete (ERROR)}\n",
+        ],
+        mac.records
+    );
+}
+
+#[test]
+fn diffed_similar() {
+    let handler = Handler {
+        pattern_set: Regex::new(r"failed").unwrap(),
+        ..Handler::empty()
+    };
+    let log = "\
+2024-07-01 01:00:00.000 job 1 failed
+  at a
+  at b
+  at c
+  at d
+2024-07-01 01:00:01.000 job 2 failed
+  at a
+  at b
+  at c
+  at e
+2024-07-01 01:00:02.000 job 3 failed, differently
+  at x
+";
+    let source = Source::new("jobs.log", Box::new(Cursor::new(log)));
+    let mut buf_writer = BufWriter::new(MatchesAndCount::default());
+    let mut write = LgrepWrite::new(false, false, true, &mut buf_writer).with_diff_similar();
+    handler.process_file(source, &mut write).unwrap();
+    drop(write);
+    assert_eq!(
+        vec![
+            "1:2024-07-01 01:00:00.000 job 1 failed\n2-  at a\n3-  at b\n4-  at c\n5-  at d\n",
+            "6:2024-07-01 01:00:01.000 job 2 failed\n\
+             7-≈ 3 lines as in the last full record\n\
+             10-  at e\n",
+            "11:2024-07-01 01:00:02.000 job 3 failed, differently\n12-  at x\n",
+        ],
+        buf_writer.into_inner().unwrap().records
+    );
+}
+
+#[test]
+fn paired() {
+    let pattern_set = Regex::new_many(&[r"begin (\w+)", r"done (\w+)"]).unwrap();
+    let handler = Handler {
+        log_pattern: Regex::new(DEFAULT_LOG_PATTERN).unwrap(),
+        pair_key: Some(GroupKey::Index(1)),
+        pattern_set,
+        ..Handler::empty()
+    };
+    let log = "\
+2024-07-01 01:00:00.000 begin a
+2024-07-01 01:00:00.500 begin b
+2024-07-01 01:00:01.000 done c
+2024-07-01 01:00:02.250 done a
+2024-07-01 01:00:03.000 begin b
+2024-07-01 01:00:04.000 done b
+";
+    let source = Source::new("requests.log", Box::new(Cursor::new(log)));
+    let mut buf_writer = BufWriter::new(MatchesAndCount::default());
+    let mut write = LgrepWrite::new(false, false, false, &mut buf_writer);
+    handler.process_file(source, &mut write).unwrap();
+    drop(write);
+    assert_eq!(
+        vec![
+            "2024-07-01 01:00:00.000 begin a\n",
+            "[+00:00:02.2] 2024-07-01 01:00:02.250 done a\n",
+            "2024-07-01 01:00:03.000 begin b\n",
+            "[+00:00:01.0] 2024-07-01 01:00:04.000 done b\n",
+        ],
+        buf_writer.into_inner().unwrap().records
+    );
+}
+
+#[test]
+fn unmatched_pairs() {
+    let pattern_set = Regex::new_many(&[r"begin (\w+)", r"done (\w+)"]).unwrap();
+    let handler = Handler {
+        log_pattern: Regex::new(DEFAULT_LOG_PATTERN).unwrap(),
+        pair_key: Some(GroupKey::Index(1)),
+        pairs: RefCell::new(Pairs::keeping_unmatched()),
+        pair_report: Some(PairReport::Unmatched),
+        pattern_set,
+        ..Handler::empty()
+    };
+    let log = "\
+2024-07-01 01:00:00.000 begin a
+2024-07-01 01:00:00.500 begin b
+2024-07-01 01:00:01.000 done c
+2024-07-01 01:00:02.250 done a
+";
+    let source = Source::new("requests.log", Box::new(Cursor::new(log)));
+    let mut buf_writer = BufWriter::new(MatchesAndCount::default());
+    let mut write = LgrepWrite::new(false, false, false, &mut buf_writer);
+    handler.process_file(source, &mut write).unwrap();
+    assert!(!handler.write_unmatched(&mut write).unwrap());
+    drop(write);
+    assert_eq!(
+        vec![
+            "no END 2024-07-01 01:00:00.500 begin b\n",
+            "no START 2024-07-01 01:00:01.000 done c\n",
+        ],
+        buf_writer.into_inner().unwrap().records
+    );
+}
+
+#[test]
+fn clustered() {
+    let handler = Handler {
+        files: vec![
+            "record_draining.log".to_owned(),
+            "app.log".to_owned(),
+            "record_complete.log".to_owned(),
+        ],
+        pattern_set: Regex::new(r"queue").unwrap(),
+        cluster: true,
+        ..Handler::empty()
+    };
+    let mut buf_writer = BufWriter::new(MatchesAndCount::default());
+    let mut write = LgrepWrite::new(false, true, false, &mut buf_writer);
+    assert_eq!(Exit::Match, handler.run_with(&mut write).unwrap());
+    drop(write);
+    assert_eq!(
+        vec![
+            "record_draining.log:×2 2024-07-01 01:25:46.123 draining queue\n",
+            "app.log:×2 2024-07-01 01:25:47.790 queue draining complete (ERROR)\n",
+        ],
+        buf_writer.into_inner().unwrap().records
+    );
+}
+
+#[test]
+fn archive_members() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("bundle.tar");
+    let bundle =
+        crate::read::tar::test::tar(&[("app.log", APP_LOG), ("complete.log", RECORD_COMPLETE)]);
+    std::fs::write(&path, bundle).unwrap();
+    let filename = path.to_string_lossy().into_owned();
+    let handler = Handler {
+        files: vec![filename.clone()],
+        pattern_set: Regex::new(r"complete").unwrap(),
+        ..Handler::empty()
+    };
+    let mut buf_writer = BufWriter::new(MatchesAndCount::default());
+    let mut write = LgrepWrite::new(false, true, false, &mut buf_writer);
+    assert_eq!(Exit::Match, handler.run_with(&mut write).unwrap());
+    drop(write);
+    assert_eq!(
+        vec![
+            format!("{filename}!app.log:2024-07-01 01:25:47.790 queue draining complete (ERROR)\n"),
+            format!(
+                "{filename}!complete.log:2024-07-01 01:25:47.790 queue draining complete (ERROR)\n"
+            ),
+        ],
+        buf_writer.into_inner().unwrap().records
+    );
+}
+
+#[test]
+fn context_pattern() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("context.log");
+    let log: String = (0..40)
+        .map(|i| {
+            let msg = match i {
+                1 | 12 | 25 | 35 => "Started request",
+                20 => "request failed",
+                _ => "tick",
+            };
+            format!("2024-07-01 00:00:{i:02}.0 {msg}\n")
+        })
+        .collect();
+    std::fs::write(&path, log).unwrap();
+    let handler = Handler {
+        files: vec![path.to_string_lossy().into_owned()],
+        pattern_set: Regex::new(r"failed").unwrap(),
+        log_pattern: Regex::new(DEFAULT_LOG_PATTERN).unwrap(),
+        context_pattern: Some(Regex::new(r"Started").unwrap()),
+        ..Handler::empty()
+    };
+    let mut buf_writer = BufWriter::new(MatchesAndCount::default());
+    let mut write = LgrepWrite::new(false, false, true, &mut buf_writer);
+    assert_eq!(Exit::Match, handler.run_with(&mut write).unwrap());
+    drop(write);
+    assert_eq!(
+        vec![
+            "13-2024-07-01 00:00:12.0 Started request\n",
+            "21:2024-07-01 00:00:20.0 request failed\n",
+            "26-2024-07-01 00:00:25.0 Started request\n",
+        ],
+        buf_writer.into_inner().unwrap().records
+    );
+}
+
+#[test]
+fn chunked() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("chunks.log");
+    let log = [RECORD_UNRELATED, APP_LOG, RECORD_WITH_TRACE, APP_LOG].concat();
+    std::fs::write(&path, &log).unwrap();
+    let filename = path.to_string_lossy().into_owned();
+    let handler = Handler {
+        files: vec![filename.clone()],
+        pattern_set: Regex::new(r"queue").unwrap(),
+        log_pattern: Regex::new(DEFAULT_LOG_PATTERN).unwrap(),
+        ..Handler::empty()
+    };
+    let search = |chunks: Option<Vec<Range<u64>>>| {
+        let mut buf_writer = BufWriter::new(MatchesAndCount::default());
+        let mut write = LgrepWrite::new(false, false, true, &mut buf_writer);
+        let exit = match chunks {
+            Some(c) => handler.process_chunked(&filename, c, &mut write),
+            None => handler.run_with(&mut write),
+        };
+        assert_eq!(Exit::Match, exit.unwrap());
+        drop(write);
+        buf_writer.into_inner().unwrap().records
+    };
+    let len = log.len() as u64;
+    let starts = seek::record_starts(&mut File::open(&path).unwrap(), len, 4, |l| {
+        handler.log_pattern.is_match(l)
+    })
+    .unwrap();
+    assert_eq!(3, starts.len());
+    let chunks = [0].into_iter().chain(starts.clone());
+    let chunks = chunks
+        .zip(starts.into_iter().chain([len]))
+        .map(|(s, e)| s..e);
+    let chunked = search(Some(chunks.collect()));
+    let unchunked = search(None);
+    assert_eq!(unchunked, chunked);
+    assert_eq!(4, chunked.len());
+}
+
+#[test]
+fn utf16() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("utf16.log");
+    let log: Vec<u8> = [0xff, 0xfe]
+        .into_iter()
+        .chain(APP_LOG.encode_utf16().flat_map(u16::to_le_bytes))
+        .collect();
+    std::fs::write(&path, log).unwrap();
+    let handler = Handler {
+        files: vec![path.to_string_lossy().into_owned()],
+        pattern_set: Regex::new(r"queue").unwrap(),
+        log_pattern: Regex::new(DEFAULT_LOG_PATTERN).unwrap(),
+        ..Handler::empty()
+    };
+    let mut buf_writer = BufWriter::new(MatchesAndCount::default());
+    let mut write = LgrepWrite::new(false, false, false, &mut buf_writer);
+    assert_eq!(Exit::Match, handler.run_with(&mut write).unwrap());
+    drop(write);
+    assert_eq!(
+        vec![
+            "2024-07-01 01:25:46.123 draining queue\n",
+            "2024-07-01 01:25:47.790 queue draining complete (ERROR)\n",
+        ],
+        buf_writer.into_inner().unwrap().records
+    );
+}
+
 #[test]
 fn color_multiline_match() {
     let handler = Handler {
@@ -354,9 +921,9 @@ YYYfour",
     );
     assert_eq!(
         vec![
+            // as input, without a final newline
             "three\u{1b}[1m\u{1b}[31mXXX\u{1b}[0m
-\u{1b}[1m\u{1b}[31mYYY\u{1b}[0mfour
-"
+\u{1b}[1m\u{1b}[31mYYY\u{1b}[0mfour"
         ],
         mac.records
     );
@@ -569,3 +1136,725 @@ fn quiet_no_match() {
     assert_eq!("", mac.to_string());
     assert_eq!(Some(Exit::NoMatch), mac.exit);
 }
+
+#[test]
+fn since_start() {
+    let handler = Handler {
+        pattern_set: Regex::new(r"(?i)queue").unwrap(),
+        since_start: true,
+        ..Handler::empty()
+    };
+    let mac = MatchesAndCount::run(
+        &handler,
+        "banner without a timestamp
+2024-07-01 01:25:46.123 draining queue
+2024-07-01 01:40:47.790 queue draining complete
+",
+    );
+    assert_eq!(
+        vec![
+            "[+00:00:00.0] 2024-07-01 01:25:46.123 draining queue\n",
+            "[+00:15:01.6] 2024-07-01 01:40:47.790 queue draining complete\n",
+        ],
+        mac.records
+    );
+}
+
+#[test]
+fn since_start_time_only() {
+    let handler = Handler {
+        pattern_set: Regex::new(r"tick").unwrap(),
+        log_pattern: Regex::new(r"^\d{2}:").unwrap(),
+        since_start: true,
+        time_only: true,
+        start_date: Some(jiff::civil::date(2024, 7, 1)),
+        ..Handler::empty()
+    };
+    let mac = MatchesAndCount::run(
+        &handler,
+        "23:59:00 tick
+00:01:00 tock
+00:02:00 tick
+",
+    );
+    assert_eq!(
+        vec![
+            "[+00:00:00.0] 23:59:00 tick\n",
+            "[+00:03:00.0] 00:02:00 tick\n",
+        ],
+        mac.records
+    );
+}
+
+#[test]
+fn continued_records() {
+    let handler = Handler {
+        pattern_set: Regex::new(r"boom").unwrap(),
+        log_pattern: Regex::new(r"^LOG").unwrap(),
+        filenames: true,
+        continue_records: true,
+        ..Handler::empty()
+    };
+    let source = Source::new("app.log.1", Box::new(Cursor::new("LOG one\nLOG two\nboom")))
+        .continued_by(
+            "app.log",
+            Box::new(Cursor::new("  at here\nLOG three boom\n")),
+        );
+    let mut buf_writer = BufWriter::new(MatchesAndCount::default());
+    let mut write = LgrepWrite::new(false, true, false, &mut buf_writer);
+    let exit = handler.process_file(source, &mut write).unwrap();
+    let mac = buf_writer.into_inner().unwrap();
+    assert_eq!(
+        vec![
+            "app.log.1:LOG two\napp.log.1-boom\napp.log.1-  at here\n",
+            "app.log:LOG three boom\n",
+        ],
+        mac.records
+    );
+    assert_eq!(Exit::Match, exit);
+}
+
+#[test]
+fn merged() {
+    let handler = Handler {
+        pattern_set: Regex::new(r"ERROR").unwrap(),
+        log_pattern: Regex::new(DEFAULT_LOG_PATTERN).unwrap(),
+        filenames: true,
+        line_numbers: true,
+        merge: true,
+        ..Handler::empty()
+    };
+    let source = Source::new(
+        "node1.log",
+        Box::new(Cursor::new(
+            "2024-07-01 10:00:00.1 ERROR a\n2024-07-01 10:00:02.1 ERROR b\n  at here\n",
+        )),
+    )
+    .continued_by(
+        "node2.log",
+        Box::new(Cursor::new(
+            "2024-07-01 10:00:01.1 ERROR c\n2024-07-01 10:00:03.1 INFO d\n",
+        )),
+    );
+    let mut buf_writer = BufWriter::new(MatchesAndCount::default());
+    let mut write = LgrepWrite::new(false, true, true, &mut buf_writer);
+    handler.process_file(source, &mut write).unwrap();
+    drop(write);
+    assert_eq!(
+        vec![
+            "node1.log:1:2024-07-01 10:00:00.1 ERROR a\n",
+            "node2.log:1:2024-07-01 10:00:01.1 ERROR c\n",
+            "node1.log:2:2024-07-01 10:00:02.1 ERROR b\nnode1.log-3-  at here\n",
+        ],
+        buf_writer.into_inner().unwrap().records
+    );
+}
+
+#[test]
+fn squeezed_repeats() {
+    let handler = Handler {
+        pattern_set: Regex::new(r"ERROR").unwrap(),
+        log_pattern: Regex::new(DEFAULT_LOG_PATTERN).unwrap(),
+        squeeze_repeats: true,
+        ..Handler::empty()
+    };
+    let mac = MatchesAndCount::run(
+        &handler,
+        "2024-07-01 10:00:00.1 ERROR x\n\
+         2024-07-01 10:00:01.1 ERROR x\n\
+         2024-07-01 10:00:01.2 INFO y\n\
+         2024-07-01 10:00:02.1 ERROR x\n\
+         2024-07-01 10:00:03.1 ERROR x\n  at z\n\
+         2024-07-01 10:00:04.1 ERROR x\n  at z\n",
+    );
+    assert_eq!(
+        vec![
+            "2024-07-01 10:00:00.1 ERROR x\n",
+            "... repeated 2 times\n",
+            "2024-07-01 10:00:03.1 ERROR x\n  at z\n",
+            "... repeated 1 time\n",
+        ],
+        mac.records
+    );
+}
+
+#[test]
+fn squeezed_repeats_not_chunked() {
+    let handler = Handler {
+        threads: 4,
+        ..Handler::empty()
+    };
+    assert!(handler.can_chunk());
+    // a run of repeats can span chunks
+    let handler = Handler {
+        squeeze_repeats: true,
+        ..handler
+    };
+    assert!(!handler.can_chunk());
+}
+
+#[test]
+fn continuation_needs_same_rotation_set() {
+    let handler = Handler {
+        continue_records: true,
+        ..Handler::empty()
+    };
+    assert!(handler.is_continuation("app.log.1", "app.log"));
+    assert!(!handler.is_continuation("app.log", "other.log"));
+    assert!(!handler.is_continuation("-", "-"));
+    assert!(!Handler::empty().is_continuation("app.log.1", "app.log"));
+}
+
+#[test]
+fn fast_highlight() {
+    let handler = Handler {
+        pattern_set: Regex::new(r"o").unwrap(),
+        log_pattern: Regex::new(r"^one").unwrap(),
+        color_mode: ColorChoice::Always,
+        fast_highlight: true,
+        ..Handler::empty()
+    };
+    let mac = MatchesAndCount::run(&handler, "one\ntwo\n");
+    assert_eq!(vec!["\u{1b}[1m\u{1b}[31mo\u{1b}[0mne\ntwo\n"], mac.records);
+}
+
+#[test]
+fn min_matches() {
+    let handler = Handler {
+        pattern_set: Regex::new_many(&[r"retry", r"again"]).unwrap(),
+        log_pattern: Regex::new(r"^LOG").unwrap(),
+        min_matches: Some(3),
+        ..Handler::empty()
+    };
+    let mac = MatchesAndCount::run(
+        &handler,
+        "LOG retry retry
+LOG retry
+again
+retry
+LOG again
+",
+    );
+    assert_eq!(vec!["LOG retry\nagain\nretry\n"], mac.records);
+}
+
+#[test]
+fn unicode_word_boundaries_rewrite() {
+    assert_eq!(r"(?u:\b)a(?u:\B)", unicode_word_boundaries_in(r"\ba\B"));
+    assert_eq!(
+        r"(?u:\b{start})a\\b",
+        unicode_word_boundaries_in(r"\b{start}a\\b")
+    );
+    assert_eq!(r"\d\w", unicode_word_boundaries_in(r"\d\w"));
+}
+
+#[test]
+fn highlight_partial_char() {
+    let handler = Handler {
+        pattern_set: Regex::builder()
+            .syntax(syntax::Config::new().unicode(false).utf8(false))
+            .build(r"caf.")
+            .unwrap(),
+        color_mode: ColorChoice::Always,
+        ..Handler::empty()
+    };
+    let mac = MatchesAndCount::run(&handler, "le café\n");
+    assert_eq!(vec!["le \u{1b}[1m\u{1b}[31mcafé\u{1b}[0m\n"], mac.records);
+}
+
+#[test]
+fn record_delimiter() {
+    let handler = Handler {
+        pattern_set: Regex::new(r"two").unwrap(),
+        record_delimiter: Some(b"\x1e".to_vec()),
+        line_numbers: true,
+        ..Handler::empty()
+    };
+    let mac = MatchesAndCount::run(
+        &handler,
+        "2024-07-01 01:25:46.123 one\x1etwo\nthree\x1efour",
+    );
+    assert_eq!(vec!["1:two\n2-three\n"], mac.records);
+}
+
+struct ClosedPipe;
+
+impl Write for ClosedPipe {
+    fn write(&mut self, _: &[u8]) -> std::io::Result<usize> {
+        Err(std::io::ErrorKind::BrokenPipe.into())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Err(std::io::ErrorKind::BrokenPipe.into())
+    }
+}
+
+fn run_closed(handler: &Handler) -> Exit {
+    let source = Source::new("input.txt", Box::new(Cursor::new("one\ntwo\n")));
+    let mut buf_writer = BufWriter::new(ClosedPipe);
+    let mut write = LgrepWrite::new(false, false, false, &mut buf_writer);
+    handler.process_file(source, &mut write).unwrap()
+}
+
+#[test]
+fn closed_output_terminates() {
+    let handler = Handler {
+        pattern_set: Regex::new(r"o").unwrap(),
+        ..Handler::empty()
+    };
+    assert_eq!(Exit::Terminate, run_closed(&handler));
+}
+
+#[test]
+fn closed_output_terminates_counts() {
+    let handler = Handler {
+        pattern_set: Regex::new(r"o").unwrap(),
+        counts: true,
+        ..Handler::empty()
+    };
+    assert_eq!(Exit::Terminate, run_closed(&handler));
+}
+
+#[test]
+fn tee() {
+    let handler = Handler {
+        pattern_set: Regex::new(r"t").unwrap(),
+        log_pattern: Regex::new(r"").unwrap(),
+        filenames: true,
+        ..Handler::empty()
+    };
+    let source = Source::new("input.txt", Box::new(Cursor::new("one\ntwo\n")));
+    let mut buf_writer = BufWriter::new(MatchesAndCount::default());
+    let mut tee_writer = BufWriter::new(MatchesAndCount::default());
+    let mut write = LgrepWrite::new(true, true, false, &mut buf_writer).with_tee(LgrepWrite::new(
+        false,
+        true,
+        false,
+        &mut tee_writer,
+    ));
+    handler.process_file(source, &mut write).unwrap();
+    drop(write);
+    let mac = buf_writer.into_inner().unwrap();
+    let tee = tee_writer.into_inner().unwrap();
+    assert_eq!("input.txt:two\n", tee.to_string());
+    assert_eq!(
+        "\u{1b}[35minput.txt\u{1b}[0m\u{1b}[36m:\u{1b}[0m\u{1b}[1m\u{1b}[31mt\u{1b}[0mwo\n",
+        mac.to_string()
+    );
+}
+
+#[test]
+fn closed_tee_terminates() {
+    let handler = Handler {
+        pattern_set: Regex::new(r"o").unwrap(),
+        ..Handler::empty()
+    };
+    let source = Source::new("input.txt", Box::new(Cursor::new("one\ntwo\n")));
+    let mut buf_writer = BufWriter::new(MatchesAndCount::default());
+    let mut tee_writer = BufWriter::new(ClosedPipe);
+    let mut write = LgrepWrite::new(false, false, false, &mut buf_writer)
+        .with_tee(LgrepWrite::new(false, false, false, &mut tee_writer));
+    assert_eq!(
+        Exit::Terminate,
+        handler.process_file(source, &mut write).unwrap()
+    );
+}
+
+#[test]
+fn files_in_given_order() {
+    let handler = Handler {
+        files: vec![
+            "record_unrelated.log".to_owned(),
+            "app.log".to_owned(),
+            "record_draining.log".to_owned(),
+        ],
+        pattern_set: Regex::new(r"queue").unwrap(),
+        filenames: true,
+        ..Handler::empty()
+    };
+    let mut buf_writer = BufWriter::new(MatchesAndCount::default());
+    let mut write = LgrepWrite::new(false, true, false, &mut buf_writer);
+    assert_eq!(Exit::Match, handler.run_with(&mut write).unwrap());
+    drop(write);
+    assert_eq!(
+        vec![
+            "app.log:2024-07-01 01:25:46.123 draining queue\n",
+            "app.log:2024-07-01 01:25:47.790 queue draining complete (ERROR)\n",
+            "record_draining.log:2024-07-01 01:25:46.123 draining queue\n",
+        ],
+        buf_writer.into_inner().unwrap().records
+    );
+}
+
+#[test]
+fn match_all_slices_window() {
+    let handler = Handler {
+        pattern_set: Regex::new_many::<&str>(&[]).unwrap(),
+        match_all: true,
+        start: Some(Regex::new(r"QueueProcessor").unwrap()),
+        end: Some(Regex::new(r"unrelated").unwrap()),
+        ..Handler::empty()
+    };
+    let mac = MatchesAndCount::run(&handler, APP_LOG);
+    assert_eq!(vec![RECORD_WITH_TRACE, RECORD_COMPLETE], mac.records);
+}
+
+#[test]
+fn count_by() {
+    let handler = Handler {
+        files: vec![
+            "app.log".to_owned(),
+            "record_draining.log".to_owned(),
+            "record_complete.log".to_owned(),
+        ],
+        pattern_set: Regex::new(r"\d{2}:\d{2}:(?<sec>\d{2})").unwrap(),
+        count_by: Some(GroupKey::Name("sec".to_owned())),
+        ..Handler::empty()
+    };
+    let mut buf_writer = BufWriter::new(MatchesAndCount::default());
+    let mut write = LgrepWrite::new(false, true, false, &mut buf_writer);
+    assert_eq!(Exit::Match, handler.run_with(&mut write).unwrap());
+    drop(write);
+    assert_eq!(
+        vec!["47\t3\n", "46\t2\n", "48\t1\n"],
+        buf_writer.into_inner().unwrap().records
+    );
+}
+
+#[test]
+fn count_distinct() {
+    let handler = Handler {
+        files: vec!["app.log".to_owned(), "record_draining.log".to_owned()],
+        pattern_set: Regex::new(r"\d{2}:\d{2}:(?<sec>\d{2})").unwrap(),
+        count_distinct: Some(GroupKey::Name("sec".to_owned())),
+        ..Handler::empty()
+    };
+    let mut buf_writer = BufWriter::new(MatchesAndCount::default());
+    let mut write = LgrepWrite::new(false, true, false, &mut buf_writer);
+    assert_eq!(Exit::Match, handler.run_with(&mut write).unwrap());
+    drop(write);
+    assert_eq!(vec!["3\n"], buf_writer.into_inner().unwrap().records);
+}
+
+#[test]
+fn count_distinct_approx() {
+    let handler = Handler {
+        files: vec!["app.log".to_owned(), "record_draining.log".to_owned()],
+        pattern_set: Regex::new(r"\d{2}:\d{2}:(?<sec>\d{2})").unwrap(),
+        count_distinct: Some(GroupKey::Name("sec".to_owned())),
+        distinct: RefCell::new(Distinct::approx()),
+        ..Handler::empty()
+    };
+    let mut buf_writer = BufWriter::new(MatchesAndCount::default());
+    let mut write = LgrepWrite::new(false, true, false, &mut buf_writer);
+    assert_eq!(Exit::Match, handler.run_with(&mut write).unwrap());
+    drop(write);
+    assert_eq!(vec!["3\t±0.8%\n"], buf_writer.into_inner().unwrap().records);
+}
+
+#[test]
+fn assertions() {
+    let exit = |pattern: &str, assertions: &[&str]| {
+        let handler = Handler {
+            files: vec!["app.log".to_owned(), "record_draining.log".to_owned()],
+            pattern_set: Regex::new(pattern).unwrap(),
+            assertions: assertions.iter().map(|a| a.parse().unwrap()).collect(),
+            ..Handler::empty()
+        };
+        let mut buf_writer = BufWriter::new(MatchesAndCount::default());
+        let mut write = LgrepWrite::new(false, true, false, &mut buf_writer);
+        handler.run_with(&mut write).unwrap()
+    };
+    assert_eq!(
+        Exit::Match,
+        exit("draining", &["count == 3", "file-count >= 1"])
+    );
+    assert_eq!(
+        Exit::AssertionFailed,
+        exit("draining", &["file-count >= 2"])
+    );
+    assert_eq!(Exit::Match, exit("nope", &["count == 0"]));
+    assert_eq!(Exit::AssertionFailed, exit("nope", &["count > 0"]));
+}
+
+#[test]
+fn start_within_records() {
+    let handler = |n| Handler {
+        pattern_set: Regex::new(r"(?i)error").unwrap(),
+        start: Some(Regex::new(r"QueueProcessor").unwrap()),
+        start_within: Some(Budget::Records(n)),
+        ..Handler::empty()
+    };
+    let mac = MatchesAndCount::run(&handler(1), APP_LOG);
+    assert_eq!(Some(Exit::NoMatch), mac.exit);
+    assert!(mac.records.is_empty());
+    let mac = MatchesAndCount::run(&handler(2), APP_LOG);
+    assert_eq!(vec![RECORD_WITH_TRACE, RECORD_COMPLETE], mac.records);
+}
+
+#[test]
+fn start_within_bytes() {
+    let handler = |n| Handler {
+        pattern_set: Regex::new(r"(?i)error").unwrap(),
+        start: Some(Regex::new(r"QueueProcessor").unwrap()),
+        start_within: Some(Budget::Bytes(n)),
+        ..Handler::empty()
+    };
+    let mac = MatchesAndCount::run(&handler(RECORD_DRAINING.len()), APP_LOG);
+    assert!(mac.records.is_empty());
+    let mac = MatchesAndCount::run(&handler(RECORD_DRAINING.len() + 1), APP_LOG);
+    assert_eq!(vec![RECORD_WITH_TRACE, RECORD_COMPLETE], mac.records);
+}
+
+#[test]
+fn annotate() {
+    let handler = Handler {
+        pattern_set: Regex::new(r"queue").unwrap(),
+        annotate: true,
+        timestamps: TimestampParser::with_time_zone(jiff::tz::TimeZone::UTC),
+        ..Handler::empty()
+    };
+    let mac = MatchesAndCount::run_with_filename(&handler, "app.log", APP_LOG);
+    assert_eq!(
+        vec![
+            "# file=app.log record=1 lines=1 ts=2024-07-01T01:25:46.123\n",
+            RECORD_DRAINING,
+            "# file=app.log record=3 lines=1 ts=2024-07-01T01:25:47.79\n",
+            RECORD_COMPLETE,
+        ],
+        mac.records
+    );
+}
+
+#[test]
+fn truncation_status() {
+    let handler = |max_count| Handler {
+        pattern_set: Regex::new(r"queue").unwrap(),
+        max_count: Some(max_count),
+        truncation_status: true,
+        ..Handler::empty()
+    };
+    let mac = MatchesAndCount::run(&handler(1), APP_LOG);
+    assert_eq!(vec![RECORD_DRAINING], mac.records);
+    assert_eq!(Some(Exit::Truncated), mac.exit);
+    let mac = MatchesAndCount::run(&handler(2), APP_LOG);
+    assert_eq!(vec![RECORD_DRAINING, RECORD_COMPLETE], mac.records);
+    assert_eq!(Some(Exit::Match), mac.exit);
+}
+
+#[test]
+fn record_sizes() {
+    let handler = |min_bytes, max_bytes| Handler {
+        pattern_set: Regex::new(r"(?i)queue").unwrap(),
+        min_bytes,
+        max_bytes,
+        ..Handler::empty()
+    };
+    let mac = MatchesAndCount::run(&handler(Some(512), None), APP_LOG);
+    assert_eq!(vec![RECORD_WITH_TRACE], mac.records);
+    let mac = MatchesAndCount::run(&handler(None, Some(512)), APP_LOG);
+    assert_eq!(vec![RECORD_DRAINING, RECORD_COMPLETE], mac.records);
+}
+
+#[test]
+fn json() {
+    let handler = Handler {
+        pattern_set: Regex::new(r"t").unwrap(),
+        log_pattern: Regex::new(r"").unwrap(),
+        counts: true,
+        ..Handler::empty()
+    };
+    let source = || Source::new("input.txt", Box::new(Cursor::new("one\ntwo\n")));
+    let mut buf_writer = BufWriter::new(MatchesAndCount::default());
+    let mut write = LgrepWrite::new(true, true, false, &mut buf_writer).with_json();
+    assert!(write.needs_match_locations());
+    handler.process_file(source(), &mut write).unwrap();
+    let handler = Handler {
+        counts: false,
+        ..handler
+    };
+    handler.process_file(source(), &mut write).unwrap();
+    drop(write);
+    assert_eq!(
+        vec![
+            "{\"schema\":1,\"file\":\"input.txt\",\"count\":1}\n",
+            "{\"schema\":1,\"file\":\"input.txt\",\"record\":2,\"line\":2,\"offset\":4,\"text\":\"input.txt:two\",\"spans\":[\
+             {\"offset\":0,\"length\":9,\"kind\":\"filename\"},\
+             {\"offset\":10,\"length\":1,\"kind\":\"match\"}]}\n",
+        ],
+        buf_writer.into_inner().unwrap().records
+    );
+}
+
+#[test]
+fn json_pattern_labels() {
+    let handler = Handler {
+        pattern_set: Regex::new_many(&["timeout", "refused", "reset"]).unwrap(),
+        log_pattern: Regex::new(r"").unwrap(),
+        pattern_labels: vec!["TO".to_owned(), "".to_owned(), "RST".to_owned()],
+        ..Handler::empty()
+    };
+    let source = Source::new("input.txt", Box::new(Cursor::new("reset, refused\nok\n")));
+    let mut buf_writer = BufWriter::new(MatchesAndCount::default());
+    let mut write = LgrepWrite::new(false, false, false, &mut buf_writer)
+        .with_json()
+        .with_pattern_labels(&handler.pattern_labels);
+    handler.process_file(source, &mut write).unwrap();
+    drop(write);
+    assert_eq!(
+        vec![
+            "{\"schema\":1,\"file\":\"input.txt\",\"record\":1,\"line\":1,\"offset\":0,\"text\":\"reset, refused\",\"spans\":[\
+             {\"offset\":0,\"length\":5,\"kind\":\"match\"},\
+             {\"offset\":7,\"length\":7,\"kind\":\"match\"}],\"patterns\":[\"RST\"]}\n",
+        ],
+        buf_writer.into_inner().unwrap().records
+    );
+}
+
+#[test]
+fn json_tee() {
+    let handler = Handler {
+        pattern_set: Regex::new_many(&["timeout", "refused", "reset"]).unwrap(),
+        log_pattern: Regex::new(r"").unwrap(),
+        pattern_labels: vec!["TO".to_owned(), "".to_owned(), "RST".to_owned()],
+        json: true,
+        ..Handler::empty()
+    };
+    let source = Source::new(
+        "input.txt",
+        Box::new(Cursor::new(
+            "reset, refused
+ok
+",
+        )),
+    );
+    let mut buf_writer = BufWriter::new(MatchesAndCount::default());
+    let mut tee_writer = BufWriter::new(MatchesAndCount::default());
+    let tee = handler.configure(LgrepWrite::new(false, false, false, &mut tee_writer));
+    let mut write = handler
+        .configure(LgrepWrite::new(false, false, false, &mut buf_writer))
+        .with_tee(tee);
+    handler.process_file(source, &mut write).unwrap();
+    drop(write);
+    let out = buf_writer.into_inner().unwrap().to_string();
+    assert!(out.contains("\"kind\":\"match\""), "{out}");
+    assert!(out.contains("\"patterns\":[\"RST\"]"), "{out}");
+    // a faithful copy, spans and all
+    assert_eq!(out, tee_writer.into_inner().unwrap().to_string());
+}
+
+#[test]
+fn stats_window() {
+    let handler = Handler {
+        pattern_set: Regex::new(r"(?i)error").unwrap(),
+        start: Some(Regex::new(r"QueueProcessor").unwrap()),
+        end: Some(Regex::new(r"unrelated").unwrap()),
+        stats: true,
+        ..Handler::empty()
+    };
+    MatchesAndCount::run_with_filename(&handler, "app.log", APP_LOG);
+    let handler = Handler {
+        start: Some(Regex::new(r"nope").unwrap()),
+        ..handler
+    };
+    MatchesAndCount::run_with_filename(&handler, "app.log", APP_LOG);
+    for s in handler.file_stats.borrow_mut().iter_mut() {
+        // timing varies from run to run
+        s.elapsed = Duration::ZERO;
+    }
+    assert_eq!(
+        vec![
+            "app.log: 4 records, 2 selected; 11 lines, 950 B in 0.0s; \
+             started at record 2 (line 2); ended early at record 4 (line 11)",
+            "app.log: 4 records, 0 selected; 11 lines, 950 B in 0.0s; \
+             start pattern never matched; ended early at record 4 (line 11)",
+        ],
+        handler
+            .file_stats
+            .borrow()
+            .iter()
+            .map(|s| s.to_string())
+            .collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn file_header() {
+    let text = "name,level\nbanner\n2024-07-01 01:25:46.123 draining queue\n";
+    let handler = |header| Handler {
+        pattern_set: Regex::new(r"queue|level").unwrap(),
+        file_header: Some(header),
+        line_numbers: true,
+        ..Handler::empty()
+    };
+    let mac = MatchesAndCount::run(&handler(FileHeader::Lines(1)), text);
+    assert_eq!(
+        vec![
+            "1:name,level\n",
+            "3:2024-07-01 01:25:46.123 draining queue\n"
+        ],
+        mac.records
+    );
+    let mac = MatchesAndCount::run(&handler(FileHeader::Lines(5)), text);
+    assert_eq!(
+        vec![
+            "1:name,level\n2-banner\n",
+            "3:2024-07-01 01:25:46.123 draining queue\n"
+        ],
+        mac.records
+    );
+    let header = FileHeader::Pattern(Regex::new(",").unwrap());
+    let mac = MatchesAndCount::run(&handler(header), "name,level\nno match\n");
+    assert!(mac.records.is_empty());
+}
+
+#[test]
+fn tags() {
+    let handler = Handler {
+        pattern_set: Regex::new(r"t").unwrap(),
+        log_pattern: Regex::new(r"").unwrap(),
+        files: vec!["api.log".to_owned(), "worker.log".to_owned()],
+        tag: true,
+        ..Handler::empty()
+    };
+    let mut buf_writer = BufWriter::new(MatchesAndCount::default());
+    let mut write = LgrepWrite::new(false, true, false, &mut buf_writer).with_tags(handler.tags());
+    for f in &handler.files {
+        let source = Source::new(f, Box::new(Cursor::new("one\ntwo\n")));
+        handler.process_file(source, &mut write).unwrap();
+    }
+    drop(write);
+    assert_eq!(
+        "api    | two\nworker | two\n",
+        buf_writer.into_inner().unwrap().to_string()
+    );
+}
+
+#[test]
+fn count_matches() {
+    let handler = Handler {
+        pattern_set: Regex::new_many(&["o", "e"]).unwrap(),
+        counts: true,
+        count_matches: true,
+        ..Handler::empty()
+    };
+    let mac = MatchesAndCount::run(&handler, "one\ntwo\nthree\nfour\nsix\nfoo boo");
+    assert_eq!("10\n", mac.to_string());
+    assert_eq!(Some(Exit::Match), mac.exit);
+}
+
+#[test]
+fn suppressed() {
+    let handler = Handler {
+        pattern_set: Regex::new(r"error").unwrap(),
+        suppress_set: Some(Regex::new_many(&["cache miss", "retrying"]).unwrap()),
+        ..Handler::empty()
+    };
+    let mac = MatchesAndCount::run(
+        &handler,
+        "error: cache miss\nerror: disk full\nwarn: retrying\nerror: retrying\n",
+    );
+    assert_eq!("error: disk full\n", mac.to_string());
+    // only selected records are suppressed
+    assert_eq!(2, handler.suppressed.get());
+}
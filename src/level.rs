@@ -0,0 +1,143 @@
+use regex_automata::meta::Regex;
+use regex_automata::PatternID;
+
+/// A record's severity, from its log level.
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub(crate) enum Level {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+    Fatal,
+}
+
+impl Level {
+    /// Parse a level name, ignoring case, including common aliases (e.g.,
+    /// `WARNING`, `ERR`, or `CRITICAL`).
+    pub(crate) fn parse(s: &str) -> Option<Level> {
+        use Level::*;
+        Some(match s.to_ascii_uppercase().as_str() {
+            "TRACE" | "FINEST" | "FINER" => Trace,
+            "DEBUG" | "DBG" | "FINE" => Debug,
+            "INFO" | "INF" | "NOTICE" => Info,
+            "WARN" | "WARNING" | "WRN" => Warn,
+            "ERROR" | "ERR" | "SEVERE" => Error,
+            "FATAL" | "CRITICAL" | "CRIT" | "PANIC" | "EMERG" | "ALERT" => Fatal,
+            _ => return None,
+        })
+    }
+}
+
+/// Finds a record's level: the log pattern's `level` field, if it has one,
+/// otherwise the first level-like word on the record's first line. Names in
+/// the level map (e.g., a framework's `AUDIT`) are levels too, and override
+/// the usual meaning of any they share.
+#[derive(Clone, Debug)]
+pub(crate) struct LevelDetector {
+    log_pattern: Option<Regex>,
+    words: Regex,
+    map: Vec<(String, Level)>,
+}
+
+impl LevelDetector {
+    pub(crate) fn new(log_pattern: &Regex, map: &[(String, Level)]) -> LevelDetector {
+        let has_field = log_pattern
+            .group_info()
+            .to_index(PatternID::ZERO, "level")
+            .is_some();
+        let mut words = String::from(
+            r"trace|debug|info|notice|warn(?:ing)?|err(?:or)?|severe|fatal|crit(?:ical)?|panic",
+        );
+        for (name, _) in map {
+            words.push('|');
+            words.push_str(&regex_syntax::escape(name));
+        }
+        LevelDetector {
+            log_pattern: has_field.then(|| log_pattern.clone()),
+            words: Regex::new(&format!(r"(?i)\b(?:{words})\b")).unwrap(),
+            map: map.to_vec(),
+        }
+    }
+
+    fn parse(&self, s: &str) -> Option<Level> {
+        self.map
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(s))
+            .map(|&(_, level)| level)
+            .or_else(|| Level::parse(s))
+    }
+
+    pub(crate) fn detect(&self, text: &str) -> Option<Level> {
+        let first_line = text.split('\n').next().unwrap_or_default();
+        if let Some(re) = &self.log_pattern {
+            let mut caps = re.create_captures();
+            re.captures(first_line, &mut caps);
+            if let Some(s) = caps.get_group_by_name("level") {
+                return self.parse(&first_line[s.range()]);
+            }
+        }
+        self.words
+            .find(first_line)
+            .and_then(|m| self.parse(&first_line[m.range()]))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        assert_eq!(Some(Level::Warn), Level::parse("warning"));
+        assert_eq!(Some(Level::Error), Level::parse("ERR"));
+        assert_eq!(Some(Level::Fatal), Level::parse("Critical"));
+        assert_eq!(None, Level::parse("loud"));
+    }
+
+    #[test]
+    fn detect_by_word() {
+        let levels = LevelDetector::new(&Regex::new(r"^\d").unwrap(), &[]);
+        assert_eq!(
+            Some(Level::Warn),
+            levels.detect("2024-07-01 12:34:56 WARN [main] disk low")
+        );
+        assert_eq!(
+            Some(Level::Error),
+            levels.detect("2024-07-01 [error] boom\n  at INFO.java")
+        );
+        assert_eq!(None, levels.detect("2024-07-01 informative"));
+    }
+
+    #[test]
+    fn detect_by_field() {
+        let levels = LevelDetector::new(&Regex::new(r"^\d+ (?<level>[A-Z]+) ").unwrap(), &[]);
+        assert_eq!(Some(Level::Debug), levels.detect("123 DEBUG an error"));
+        // the field wins, even if it's not a level
+        assert_eq!(None, levels.detect("123 AUDIT an error"));
+    }
+
+    #[test]
+    fn mapped() {
+        let map = [
+            ("SEVERE".to_owned(), Level::Fatal),
+            ("AUDIT".to_owned(), Level::Info),
+            ("I".to_owned(), Level::Info),
+        ];
+        let levels = LevelDetector::new(&Regex::new(r"^\d").unwrap(), &map);
+        assert_eq!(
+            Some(Level::Fatal),
+            levels.detect("2024-07-01 SEVERE: disk gone")
+        );
+        assert_eq!(
+            Some(Level::Info),
+            levels.detect("2024-07-01 audit: an error")
+        );
+        assert_eq!(
+            Some(Level::Warn),
+            levels.detect("2024-07-01 WARNING: I'm low")
+        );
+        let levels = LevelDetector::new(&Regex::new(r"^\d+ (?<level>[A-Z]) ").unwrap(), &map);
+        assert_eq!(Some(Level::Info), levels.detect("123 I an error"));
+    }
+}
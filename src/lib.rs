@@ -1,17 +1,44 @@
 use std::process::ExitCode;
 
 use anyhow::Result;
-use clap::Parser;
+use clap::{CommandFactory, FromArgMatches};
 
 use cli::Cli;
 
 use crate::handler::Handler;
+use crate::manifest::Manifest;
+#[cfg(feature = "stream")]
+pub use crate::read::records::Record;
+#[cfg(feature = "stream")]
+pub use crate::read::stream::RecordStream;
 
+mod assertion;
 mod cli;
+mod cluster;
+mod distinct;
 mod handler;
+mod level;
+mod manifest;
+mod notify;
+mod pair;
+mod pattern_file;
 mod read;
+mod repeats;
+mod saved;
+mod stats;
+mod timestamp;
+mod trace;
+mod watchdog;
 mod write;
 
+/// A stable (FNV-1a) hash, so whatever's derived from it (e.g., sampling) is
+/// the same from run to run, and release to release.
+pub(crate) fn stable_hash(key: &str) -> u64 {
+    key.bytes().fold(0xcbf2_9ce4_8422_2325, |h, b| {
+        (h ^ b as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
 #[derive(Eq, PartialEq, Debug)]
 pub enum Exit {
     Help,
@@ -19,18 +46,29 @@ pub enum Exit {
     Terminate,
     NoMatch,
     Match,
+    /// Matched, but `--max-count` left more selected records unprinted.
+    Truncated,
+    /// An `--assert` expectation wasn't met.
+    AssertionFailed,
 }
 
-impl From<Exit> for ExitCode {
-    fn from(value: Exit) -> Self {
+impl Exit {
+    fn code(&self) -> u8 {
         use Exit::*;
         // these match grep's behavior
-        ExitCode::from(match value {
+        match self {
             Help => 2,
             Error => 2,
-            NoMatch => 1,
+            NoMatch | AssertionFailed => 1,
             Match | Terminate => 0,
-        })
+            Truncated => 3,
+        }
+    }
+}
+
+impl From<Exit> for ExitCode {
+    fn from(value: Exit) -> Self {
+        ExitCode::from(value.code())
     }
 }
 
@@ -46,7 +84,21 @@ impl From<usize> for Exit {
 
 /// Run the grep, returning how many records matched.
 pub fn run() -> Result<Exit> {
-    let args = Cli::parse().like_grep();
+    let (argv, replayed) = saved::expand(std::env::args_os().collect())?;
+    // so a replay's arguments can override those saved
+    let matches = Cli::command()
+        .args_override_self(replayed)
+        .get_matches_from(&argv);
+    let args = Cli::from_arg_matches(&matches)
+        .unwrap_or_else(|e| e.exit())
+        .like_grep();
+    trace::init(args.log_level.as_deref())?;
+    if let Some(name) = &args.save {
+        saved::save(name, &argv[1..])?;
+    }
+    if args.json_schema {
+        return args.print_json_schema();
+    }
     // if no-filename (-h) without any patterns
     if args.no_filename && !args.has_patterns() {
         args.print_help()
@@ -56,7 +108,17 @@ pub fn run() -> Result<Exit> {
         // weird, but permitted
         Ok(Exit::NoMatch)
     } else {
+        let manifest = args
+            .manifest
+            .as_ref()
+            .map(|_| Manifest::new(cli::build::PKG_VERSION, &matches));
+        // thousands of files mustn't run out of descriptors part way through
+        read::raise_open_files_limit();
         let handler = Handler::new(args)?;
-        handler.run()
+        let exit = handler.run()?;
+        if let Some(m) = manifest {
+            handler.write_manifest(m, &exit)?;
+        }
+        Ok(exit)
     }
 }
@@ -0,0 +1,151 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+use anyhow::{Context, Result};
+use clap::parser::ValueSource;
+use clap::{ArgMatches, CommandFactory};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::cli::Cli;
+use crate::read::{is_s3_url, STDIN_FILENAME};
+use crate::stats::FileStats;
+use crate::Exit;
+
+/// A record of a run, for '--manifest': how lgrep was invoked, what it read,
+/// and what it found, so an investigation can be reproduced (or audited).
+#[derive(Debug, Serialize)]
+pub(crate) struct Manifest {
+    version: &'static str,
+    invocation: Vec<String>,
+    /// Every option's value, including defaults, by option name.
+    options: BTreeMap<String, OptionValue>,
+    inputs: Vec<Input>,
+    results: Vec<FileStats>,
+    exit: u8,
+}
+
+#[derive(Debug, Serialize)]
+struct OptionValue {
+    values: Vec<String>,
+    /// Where the value came from: the command line, the environment, or a
+    /// default.
+    source: &'static str,
+}
+
+#[derive(Debug, Serialize)]
+struct Input {
+    file: String,
+    /// Size on disk (so before any decompression), if it's a local file.
+    size: Option<u64>,
+    /// SHA-256 of the bytes on disk, if it's a local file.
+    sha256: Option<String>,
+}
+
+impl Manifest {
+    pub(crate) fn new(version: &'static str, matches: &ArgMatches) -> Manifest {
+        let mut options = BTreeMap::new();
+        // the arguments, not any groups of them
+        for arg in Cli::command().get_arguments() {
+            let id = arg.get_id().as_str();
+            let Ok(Some(raw)) = matches.try_get_raw(id) else {
+                continue;
+            };
+            let source = match matches.value_source(id) {
+                Some(ValueSource::CommandLine) => "command line",
+                Some(ValueSource::EnvVariable) => "environment",
+                _ => "default",
+            };
+            options.insert(
+                id.to_owned(),
+                OptionValue {
+                    values: raw.map(|v| v.to_string_lossy().into_owned()).collect(),
+                    source,
+                },
+            );
+        }
+        Manifest {
+            version,
+            invocation: std::env::args().collect(),
+            options,
+            inputs: Vec::new(),
+            results: Vec::new(),
+            exit: 0,
+        }
+    }
+
+    /// Add what was searched, and what was found.
+    pub(crate) fn with_results(
+        self,
+        files: &[String],
+        results: Vec<FileStats>,
+        exit: &Exit,
+    ) -> Result<Manifest> {
+        let inputs = files.iter().map(|f| Input::new(f)).collect::<Result<_>>()?;
+        Ok(Manifest {
+            inputs,
+            results,
+            exit: exit.code(),
+            ..self
+        })
+    }
+
+    pub(crate) fn write(&self, filename: &str) -> Result<()> {
+        let mut out = BufWriter::new(
+            File::create(filename)
+                .with_context(|| format!("Failed to create manifest '{filename}'"))?,
+        );
+        serde_json::to_writer_pretty(&mut out, self)
+            .map_err(io::Error::from)
+            .and_then(|_| writeln!(out))
+            .and_then(|_| out.flush())
+            .with_context(|| format!("Failed to write manifest '{filename}'"))
+    }
+}
+
+impl Input {
+    fn new(filename: &str) -> Result<Input> {
+        if filename == STDIN_FILENAME || is_s3_url(filename) {
+            return Ok(Input {
+                file: filename.to_owned(),
+                size: None,
+                sha256: None,
+            });
+        }
+        let hash = || -> io::Result<_> {
+            let mut hasher = Sha256::new();
+            let size = io::copy(&mut File::open(filename)?, &mut hasher)?;
+            let sha256 = hasher
+                .finalize()
+                .iter()
+                .map(|b| format!("{b:02x}"))
+                .collect();
+            Ok((size, sha256))
+        };
+        let (size, sha256) =
+            hash().with_context(|| format!("Failed to hash '{filename}' for the manifest"))?;
+        Ok(Input {
+            file: filename.to_owned(),
+            size: Some(size),
+            sha256: Some(sha256),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn inputs() {
+        let stdin = Input::new(STDIN_FILENAME).unwrap();
+        assert_eq!(None, stdin.size);
+        let f = Input::new("Cargo.toml").unwrap();
+        assert_eq!(fs::metadata("Cargo.toml").unwrap().len(), f.size.unwrap());
+        assert_eq!(64, f.sha256.unwrap().len());
+        assert!(Input::new("no-such-file.log").is_err());
+    }
+}
@@ -0,0 +1,309 @@
+use std::borrow::Cow;
+use std::io::Write;
+use std::mem;
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context, Result};
+use serde::Serialize;
+
+use crate::read::records::Record;
+use crate::write::template::Template;
+
+/// How many records a notification's default payload includes, at most.
+/// Its count is of all of them.
+const MAX_RECORDS: usize = 10;
+
+/// How long to wait for a webhook to respond.
+const TIMEOUT_SECS: &str = "30";
+
+/// What a '--notify-template' field refers to.
+#[derive(Debug, Eq, PartialEq)]
+pub(crate) enum NotifyField {
+    File,
+    Count,
+    First,
+    Last,
+}
+
+impl NotifyField {
+    pub(crate) fn parse_template(template: &str) -> Result<Template<NotifyField>> {
+        Template::parse(template)?.try_map(|name| {
+            Ok(match name.as_str() {
+                "file" => NotifyField::File,
+                "count" => NotifyField::Count,
+                "first" => NotifyField::First,
+                "last" => NotifyField::Last,
+                _ => bail!("Unknown field '{{{name}}}' in --notify-template"),
+            })
+        })
+    }
+}
+
+/// A selected record, as it's notified of.
+#[derive(Debug, Eq, PartialEq, Serialize)]
+struct Notice {
+    record: usize,
+    line: usize,
+    text: String,
+}
+
+/// The records selected since the last POST. Only the first few and the
+/// last are kept, as that's all a notification includes, so a noisy log
+/// doesn't pile them up.
+#[derive(Debug, Default)]
+struct Batch {
+    first: Vec<Notice>,
+    last: Option<Notice>,
+    count: usize,
+}
+
+impl Batch {
+    fn push(&mut self, notice: Notice) {
+        self.count += 1;
+        if self.first.len() < MAX_RECORDS {
+            self.first.push(notice);
+        } else {
+            self.last = Some(notice);
+        }
+    }
+
+    fn last(&self) -> Option<&Notice> {
+        self.last.as_ref().or(self.first.last())
+    }
+}
+
+/// The batch being gathered, shared between the searching thread and the
+/// notifying one.
+#[derive(Default)]
+struct Queue {
+    state: Mutex<State>,
+    changed: Condvar,
+}
+
+#[derive(Default)]
+struct State {
+    batch: Batch,
+    /// The search is over, so send what's left, and stop.
+    closed: bool,
+}
+
+impl Queue {
+    fn push(&self, notice: Notice) {
+        self.state.lock().unwrap().batch.push(notice);
+        self.changed.notify_one();
+    }
+
+    fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.changed.notify_one();
+    }
+}
+
+/// The default payload: the first few records of a batch, and how many
+/// there were.
+#[derive(Serialize)]
+struct Payload<'a> {
+    file: &'a str,
+    count: usize,
+    records: &'a [Notice],
+}
+
+/// POSTs selected records to a webhook, for '--notify-webhook', off of the
+/// searching thread, so a slow webhook doesn't hold up following a file.
+/// Records are batched: once one is notified of, any more which come within
+/// `interval` of the last POST go in the same one. A failed POST is warned
+/// about, and its records dropped, rather than ending the search.
+pub(crate) struct Notifier {
+    queue: Arc<Queue>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Notifier {
+    pub(crate) fn new(
+        url: String,
+        filename: String,
+        template: Option<Template<NotifyField>>,
+        interval: Option<Duration>,
+    ) -> Notifier {
+        let queue = Arc::new(Queue::default());
+        let shared = queue.clone();
+        let thread = thread::spawn(move || {
+            send_batches(&shared, interval.unwrap_or_default(), |batch| {
+                let payload = payload(&filename, template.as_ref(), batch);
+                if let Err(e) = post(&url, &payload) {
+                    eprintln!("lgrep: failed to notify '{url}': {e:#}");
+                }
+            })
+        });
+        Notifier {
+            queue,
+            thread: Some(thread),
+        }
+    }
+
+    pub(crate) fn notify(&self, record: &Record) {
+        self.queue.push(Notice {
+            record: record.record_num,
+            line: record.first_line,
+            text: record.text.clone(),
+        });
+    }
+}
+
+impl Drop for Notifier {
+    /// Send any records still waiting, before the search ends.
+    fn drop(&mut self) {
+        self.queue.close();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Take batches from the queue until it's closed, sending them no closer
+/// together than `interval`.
+fn send_batches(queue: &Queue, interval: Duration, mut send: impl FnMut(&Batch)) {
+    let mut last_sent: Option<Instant> = None;
+    loop {
+        let mut state = queue.state.lock().unwrap();
+        while state.batch.count == 0 && !state.closed {
+            state = queue.changed.wait(state).unwrap();
+        }
+        if state.batch.count == 0 {
+            return;
+        }
+        let due = last_sent.map_or_else(Instant::now, |t| t + interval);
+        while !state.closed {
+            let wait = due.saturating_duration_since(Instant::now());
+            if wait.is_zero() {
+                break;
+            }
+            state = queue.changed.wait_timeout(state, wait).unwrap().0;
+        }
+        let batch = mem::take(&mut state.batch);
+        drop(state);
+        send(&batch);
+        last_sent = Some(Instant::now());
+    }
+}
+
+/// The body to POST for a batch of records: the template filled in, with
+/// values escaped as in a JSON string, or else the default JSON payload.
+fn payload(filename: &str, template: Option<&Template<NotifyField>>, batch: &Batch) -> String {
+    let Some(template) = template else {
+        let payload = Payload {
+            file: filename,
+            count: batch.count,
+            records: &batch.first,
+        };
+        return serde_json::to_string(&payload).expect("a payload is JSON");
+    };
+    template.render(|f| {
+        let value: Cow<str> = match f {
+            NotifyField::File => filename.into(),
+            NotifyField::Count => batch.count.to_string().into(),
+            NotifyField::First => batch.first.first()?.text.as_str().into(),
+            NotifyField::Last => batch.last()?.text.as_str().into(),
+        };
+        Some(json_escaped(&value).into())
+    })
+}
+
+/// A value as it'd be inside a JSON string, without the quotes.
+fn json_escaped(value: &str) -> String {
+    let quoted = serde_json::to_string(value).expect("a string is JSON");
+    quoted[1..quoted.len() - 1].to_owned()
+}
+
+/// POST a JSON body, via `curl`, so proxies, certificates, and such are as
+/// the environment has them.
+fn post(url: &str, body: &str) -> Result<()> {
+    let mut child = Command::new("curl")
+        .args([
+            "--silent",
+            "--show-error",
+            "--fail",
+            "--max-time",
+            TIMEOUT_SECS,
+        ])
+        .args(["--header", "Content-Type: application/json"])
+        .args(["--data-binary", "@-", url])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()
+        .context("Failed to run 'curl'; is it on your $PATH?")?;
+    let written = child.stdin.take().unwrap().write_all(body.as_bytes());
+    // even if it exited early, so it's not left a zombie
+    let status = child.wait()?;
+    if !status.success() {
+        bail!("'curl' exited with {status}");
+    }
+    written.context("Failed to write to 'curl'")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn notice(record: usize, text: &str) -> Notice {
+        Notice {
+            record,
+            line: record * 2,
+            text: text.to_owned(),
+        }
+    }
+
+    fn batch_of(notices: impl IntoIterator<Item = Notice>) -> Batch {
+        let mut batch = Batch::default();
+        notices.into_iter().for_each(|n| batch.push(n));
+        batch
+    }
+
+    #[test]
+    fn payloads() {
+        let batch = batch_of([notice(1, "a \"quoted\"\n  error"), notice(4, "b")]);
+        assert_eq!(
+            r#"{"file":"app.log","count":2,"records":[{"record":1,"line":2,"text":"a \"quoted\"\n  error"},{"record":4,"line":8,"text":"b"}]}"#,
+            payload("app.log", None, &batch)
+        );
+        let template =
+            NotifyField::parse_template(r#"{{"text":"{count} in {file}: {first}"}}"#).unwrap();
+        assert_eq!(
+            r#"{"text":"2 in app.log: a \"quoted\"\n  error"}"#,
+            payload("app.log", Some(&template), &batch)
+        );
+        assert!(NotifyField::parse_template("{user}").is_err());
+    }
+
+    #[test]
+    fn bounded() {
+        let batch = batch_of((1..=25).map(|i| notice(i, "x")));
+        assert_eq!(25, batch.count);
+        assert_eq!(MAX_RECORDS, batch.first.len());
+        assert_eq!(Some(25), batch.last().map(|n| n.record));
+    }
+
+    #[test]
+    fn batched() {
+        let queue = Arc::new(Queue::default());
+        for i in 1..=3 {
+            queue.push(notice(i, "x"));
+        }
+        let shared = queue.clone();
+        let feeder = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            shared.push(notice(4, "x"));
+            shared.close();
+        });
+        let mut batches = Vec::new();
+        send_batches(&queue, Duration::from_secs(1), |b| {
+            batches.push(b.first.iter().map(|n| n.record).collect::<Vec<_>>())
+        });
+        feeder.join().unwrap();
+        // the first is sent at once, and the next waits out the interval
+        assert_eq!(vec![vec![1, 2, 3], vec![4]], batches);
+    }
+}
@@ -0,0 +1,141 @@
+use std::collections::HashMap;
+
+use clap::ValueEnum;
+use jiff::Timestamp;
+
+use crate::read::records::Record;
+
+/// What to report about '--pair' records, instead of writing each pair.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+pub(crate) enum PairReport {
+    /// START records without an END, and END records without a START.
+    Unmatched,
+}
+
+/// Records matching the '--pair' START pattern, waiting for a record matching
+/// its END pattern with the same key.
+#[derive(Debug, Default)]
+pub(crate) struct Pairs {
+    waiting: HashMap<String, (usize, Waiting)>,
+    /// Records which will never be paired, for '--pair-report unmatched', by
+    /// when they were seen.
+    unmatched: Option<Vec<(usize, Unmatched)>>,
+    seen: usize,
+}
+
+/// A START record, and when it was, if it has a timestamp.
+#[derive(Debug, Eq, PartialEq)]
+pub(crate) struct Waiting {
+    pub(crate) filename: String,
+    pub(crate) record: Record,
+    pub(crate) ts: Option<Timestamp>,
+}
+
+/// A record without its other half.
+#[derive(Debug, Eq, PartialEq)]
+pub(crate) enum Unmatched {
+    Start(Waiting),
+    End(Waiting),
+}
+
+impl Pairs {
+    /// Pairs which keep the records never paired, to report.
+    pub(crate) fn keeping_unmatched() -> Pairs {
+        Pairs {
+            unmatched: Some(Vec::new()),
+            ..Pairs::default()
+        }
+    }
+
+    /// Wait for a START record's END. Returns the record waiting with the
+    /// same key, if any, which it replaces.
+    pub(crate) fn start(&mut self, key: &str, waiting: Waiting) -> Option<Waiting> {
+        self.seen += 1;
+        let (seen, replaced) = self.waiting.insert(key.to_owned(), (self.seen, waiting))?;
+        match &mut self.unmatched {
+            Some(u) => {
+                u.push((seen, Unmatched::Start(replaced)));
+                None
+            }
+            None => Some(replaced),
+        }
+    }
+
+    /// The START record an END record pairs with, if one's waiting.
+    pub(crate) fn end(&mut self, key: &str, end: impl FnOnce() -> Waiting) -> Option<Waiting> {
+        self.seen += 1;
+        let start = self.waiting.remove(key).map(|(_, w)| w);
+        if let (None, Some(u)) = (&start, &mut self.unmatched) {
+            u.push((self.seen, Unmatched::End(end())));
+        }
+        start
+    }
+
+    /// The records never paired, in the order they were seen. Those replaced,
+    /// and lone ENDs, are only kept if [Pairs::keeping_unmatched].
+    pub(crate) fn take_unmatched(&mut self) -> Vec<Unmatched> {
+        let mut unmatched = self.unmatched.take().unwrap_or_default();
+        unmatched.extend(
+            self.waiting
+                .drain()
+                .map(|(_, (seen, w))| (seen, Unmatched::Start(w))),
+        );
+        unmatched.sort_by_key(|(seen, _)| *seen);
+        unmatched.into_iter().map(|(_, u)| u).collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn waiting(text: &str) -> Waiting {
+        Waiting {
+            filename: "app.log".to_owned(),
+            record: Record::new(text, 1, 1),
+            ts: None,
+        }
+    }
+
+    #[test]
+    fn pairing() {
+        let mut pairs = Pairs::default();
+        assert_eq!(None, pairs.start("a", waiting("start a")));
+        assert_eq!(None, pairs.start("b", waiting("start b")));
+        assert_eq!(None, pairs.end("c", || waiting("end c")));
+        assert_eq!(
+            Some(waiting("start a")),
+            pairs.end("a", || waiting("end a"))
+        );
+        assert_eq!(None, pairs.end("a", || waiting("end a")));
+        assert_eq!(
+            Some(waiting("start b")),
+            pairs.start("b", waiting("start b again"))
+        );
+        assert_eq!(
+            Some(waiting("start b again")),
+            pairs.end("b", || waiting("end b"))
+        );
+        assert_eq!(Vec::<Unmatched>::new(), pairs.take_unmatched());
+    }
+
+    #[test]
+    fn unmatched() {
+        let mut pairs = Pairs::keeping_unmatched();
+        pairs.start("a", waiting("start a"));
+        pairs.start("b", waiting("start b"));
+        pairs.end("c", || waiting("end c"));
+        pairs.end("a", || waiting("end a"));
+        pairs.start("b", waiting("start b again"));
+        pairs.start("d", waiting("start d"));
+        assert_eq!(
+            vec![
+                Unmatched::Start(waiting("start b")),
+                Unmatched::End(waiting("end c")),
+                Unmatched::Start(waiting("start b again")),
+                Unmatched::Start(waiting("start d")),
+            ],
+            pairs.take_unmatched()
+        );
+    }
+}
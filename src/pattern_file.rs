@@ -0,0 +1,123 @@
+use anyhow::{bail, Result};
+
+/// The inline flags a '# flags:' directive may set, as for `(?flags:...)`.
+const FLAGS: &str = "imsUuxR-";
+
+/// A pattern read from a pattern file ('-f'), with its directives applied.
+#[derive(Debug, Eq, PartialEq)]
+pub(crate) struct FilePattern {
+    pub(crate) pattern: String,
+    /// Its label, from a 'label:' directive; empty if unlabeled.
+    pub(crate) label: String,
+    /// Whether it's from a '!' line, so records it matches aren't selected.
+    pub(crate) negative: bool,
+}
+
+/// Read a pattern file's patterns, one per line, but for directives:
+///
+/// - `# flags: i` sets inline flags (e.g., `i` to ignore case) for the
+///   patterns after it, until the next one; `# flags:` clears them
+/// - any other line starting with `#` is a comment
+/// - `! PATTERN` is a negative pattern: records it matches aren't selected
+/// - `label: NAME PATTERN` labels the pattern, as '--pattern-label' does
+///
+/// A leading `#` or `!` can be escaped with a `\` to search for it. With
+/// `fixed_strings`, patterns are escaped before any flags are applied.
+pub(crate) fn parse(filename: &str, text: &str, fixed_strings: bool) -> Result<Vec<FilePattern>> {
+    let mut flags = String::new();
+    let mut patterns = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        if let Some(comment) = line.strip_prefix('#') {
+            if let Some(f) = comment.trim_start().strip_prefix("flags:") {
+                let f = f.trim();
+                if let Some(c) = f.chars().find(|c| !FLAGS.contains(*c)) {
+                    bail!("Unknown flag '{c}' at {filename}:{}", i + 1);
+                }
+                flags = f.to_owned();
+            }
+            continue;
+        }
+        let (line, negative) = match line.strip_prefix('!') {
+            Some(p) => (p.strip_prefix(' ').unwrap_or(p), true),
+            None => (line, false),
+        };
+        let (pattern, label) = match line.strip_prefix("label:") {
+            Some(rest) => {
+                let rest = rest.trim_start();
+                let (name, pattern) = rest.split_once(' ').unwrap_or((rest, ""));
+                if name.is_empty() {
+                    bail!("Missing label name at {filename}:{}", i + 1);
+                }
+                (pattern, name.to_owned())
+            }
+            None => (line, String::new()),
+        };
+        let pattern = if fixed_strings {
+            let p = pattern
+                .strip_prefix("\\#")
+                .or_else(|| pattern.strip_prefix("\\!"))
+                .map_or(pattern, |_| &pattern[1..]);
+            regex_syntax::escape(p)
+        } else {
+            pattern.to_owned()
+        };
+        let pattern = if flags.is_empty() || pattern.is_empty() {
+            pattern
+        } else {
+            format!("(?{flags}:{pattern})")
+        };
+        patterns.push(FilePattern {
+            pattern,
+            label,
+            negative,
+        });
+    }
+    Ok(patterns)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn pattern(pattern: &str, label: &str, negative: bool) -> FilePattern {
+        FilePattern {
+            pattern: pattern.to_owned(),
+            label: label.to_owned(),
+            negative,
+        }
+    }
+
+    #[test]
+    fn directives() {
+        let text = "\
+# known-benign errors
+timeout
+# flags: i
+! connection reset
+label: DISK no space left
+\\#123
+# flags:
+label: EMPTY
+";
+        assert_eq!(
+            vec![
+                pattern("timeout", "", false),
+                pattern("(?i:connection reset)", "", true),
+                pattern("(?i:no space left)", "DISK", false),
+                pattern("(?i:\\#123)", "", false),
+                pattern("", "EMPTY", false),
+            ],
+            parse("p.txt", text, false).unwrap()
+        );
+        assert_eq!(
+            vec![pattern("\\#1\\.2", "", false), pattern("a\\+", "", true)],
+            parse("p.txt", "\\#1.2\n!a+", true).unwrap()
+        );
+        assert_eq!(
+            "Unknown flag 'q' at p.txt:2",
+            parse("p.txt", "a\n# flags: iq", false)
+                .unwrap_err()
+                .to_string()
+        );
+    }
+}
@@ -1,47 +1,389 @@
-use std::io::BufRead;
+use std::io::{self, BufRead};
 
-use anyhow::{Context, Result};
+use std::fs;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+
+use anyhow::{bail, Context, Result};
+use clap::ValueEnum;
+
+use crate::read::decompress::{decompress, open_stream, Format};
+use crate::read::read_ahead::ReadAhead;
+use crate::read::snapshot::Snapshot;
+use crate::trace::span;
 
 pub(crate) const STDIN_FILENAME: &str = "-";
 
+/// The errno for a process being out of file descriptors, on Linux, macOS,
+/// and the BSDs.
+const EMFILE: i32 = 24;
+
+pub(crate) mod chunked;
+pub(crate) mod decompress;
+pub(crate) mod delimited;
+pub(crate) mod encoding;
+pub(crate) mod follow;
+pub(crate) mod index;
+pub(crate) mod lazy;
 pub(crate) mod lines;
+pub(crate) mod merge;
+pub(crate) mod read_ahead;
 pub(crate) mod records;
+#[cfg(feature = "s3")]
+pub(crate) mod s3;
+pub(crate) mod seek;
+pub(crate) mod snapshot;
 pub(crate) mod source;
+#[cfg(feature = "stream")]
+pub(crate) mod stream;
+pub(crate) mod tar;
+pub(crate) mod traverse;
 
 /// Open a [BufRead] for the named file, or STDIN if the filename is '-'. If the
 /// stream is compressed using a well-known format (e.g. gzip), it will be
-/// decompressed automatically _on Unix-ish platforms_, by shelling out to an
-/// appropriate utility on your `$PATH`. On Windows, you must manually
-/// decompress the stream/file first.
-pub(crate) fn get_reader(filename: &String) -> Result<Box<dyn BufRead>> {
+/// decompressed automatically. See [decompress].
+///
+/// With `read_ahead`, a compressed file is read (and decompressed) on its own
+/// thread, overlapping with matching.
+///
+/// An uncompressed file is only read as far as its size when opened, and
+/// `grew` is set if it's an active log which grew in the meantime. See
+/// [Snapshot].
+pub(crate) fn get_reader(
+    filename: &String,
+    read_ahead: bool,
+    grew: &Arc<AtomicBool>,
+) -> Result<Box<dyn BufRead>> {
+    let _span = span!(DEBUG, "open", file = filename.as_str());
     if filename == STDIN_FILENAME {
         open_stdin().with_context(|| "Failed to open STDIN for reading")
     } else {
-        open_file(filename).with_context(|| format!("Failed to open '{filename}' for reading"))
+        let reader = if is_s3_url(filename) {
+            open_s3(filename)
+        } else {
+            open_file(filename, grew)
+        }
+        .map_err(explain_open_error)
+        .with_context(|| format!("Failed to open '{filename}' for reading"))?;
+        if read_ahead && is_compressed(filename) {
+            Ok(Box::new(ReadAhead::spawn(reader)))
+        } else {
+            Ok(reader)
+        }
     }
 }
 
-#[cfg(not(target_os = "windows"))]
-fn open_stdin() -> Result<Box<dyn BufRead>> {
-    use compress_io::compress::CompressIo;
-    Ok(Box::new(CompressIo::new().bufreader()?))
+/// Open a file to follow as it's written. It's read as it is, since there's
+/// no end to decompress up to, or stop at.
+pub(crate) fn open_follow(filename: &str) -> Result<Box<dyn BufRead>> {
+    if filename == STDIN_FILENAME || is_s3_url(filename) || is_compressed(filename) {
+        bail!("Can't follow '{filename}'; only uncompressed files can be followed");
+    }
+    let file = fs::File::open(filename)
+        .map_err(anyhow::Error::from)
+        .map_err(explain_open_error)
+        .with_context(|| format!("Failed to open '{filename}' for reading"))?;
+    Ok(Box::new(io::BufReader::new(file)))
+}
+
+/// Open an uncompressed file to read from an offset, which `seek` finds in
+/// it, given its size. Like [get_reader], it's only read as far as its size
+/// when opened. If the file isn't a plain, uncompressed one, which can be
+/// read from an offset, it isn't opened.
+pub(crate) fn get_reader_at(
+    filename: &str,
+    grew: &Arc<AtomicBool>,
+    seek: impl FnOnce(&mut fs::File, u64) -> io::Result<u64>,
+) -> Result<Option<Box<dyn BufRead>>> {
+    if filename == STDIN_FILENAME || is_s3_url(filename) || is_compressed(filename) {
+        return Ok(None);
+    }
+    let open = || -> Result<Option<Box<dyn BufRead>>> {
+        let mut file = fs::File::open(filename)?;
+        let md = file.metadata()?;
+        if !md.is_file() || Format::of_file(&mut file)?.is_some() {
+            return Ok(None);
+        }
+        let offset = seek(&mut file, md.len())?;
+        let snapshot = Snapshot::at(file, offset, md.len(), grew.clone())?;
+        Ok(Some(Box::new(snapshot)))
+    };
+    open()
+        .map_err(explain_open_error)
+        .with_context(|| format!("Failed to open '{filename}' for reading"))
+}
+
+/// Split off files larger than `max` bytes (on disk), along with their
+/// sizes. Those which aren't plain files (e.g., STDIN) are kept.
+pub(crate) fn partition_by_size(
+    filenames: Vec<String>,
+    max: u64,
+) -> (Vec<String>, Vec<(String, u64)>) {
+    let mut kept = Vec::with_capacity(filenames.len());
+    let mut oversized = Vec::new();
+    for f in filenames {
+        match fs::metadata(&f) {
+            Ok(md) if md.is_file() && md.len() > max => oversized.push((f, md.len())),
+            _ => kept.push(f),
+        }
+    }
+    (kept, oversized)
+}
+
+/// Out of file descriptors (EMFILE) is an environment problem, not a file
+/// problem, so say how to fix it.
+fn explain_open_error(e: anyhow::Error) -> anyhow::Error {
+    let exhausted = e
+        .downcast_ref::<io::Error>()
+        .is_some_and(|e| e.raw_os_error() == Some(EMFILE));
+    if exhausted {
+        e.context("Too many open files; raise the limit (e.g., 'ulimit -n 4096') and retry")
+    } else {
+        e
+    }
+}
+
+/// Raise the soft limit on open files to the hard limit, as far as the
+/// system allows, so huge file lists (e.g., for '--merge', which has them all
+/// open at once) don't run out of file descriptors part way through.
+#[cfg(unix)]
+pub(crate) fn raise_open_files_limit() {
+    /// macOS refuses more than this, even with an unlimited hard limit.
+    const OPEN_MAX: libc::rlim_t = 10240;
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    // SAFETY: they only read and write the given limit
+    unsafe {
+        if libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) != 0 {
+            return;
+        }
+        for max in [limit.rlim_max, limit.rlim_max.min(OPEN_MAX)] {
+            if max <= limit.rlim_cur {
+                return;
+            }
+            let raised = libc::rlimit {
+                rlim_cur: max,
+                ..limit
+            };
+            if libc::setrlimit(libc::RLIMIT_NOFILE, &raised) == 0 {
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(not(unix))]
+pub(crate) fn raise_open_files_limit() {}
+
+/// What to do with a directory given as a file to search.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+pub(crate) enum Directories {
+    Read,
+    Skip,
+    Recurse,
+}
+
+pub(crate) fn is_dir(filename: &str) -> bool {
+    filename != STDIN_FILENAME && fs::metadata(filename).is_ok_and(|md| md.is_dir())
+}
+
+/// Check every file can be opened for reading, before any are searched, so
+/// problems are reported all together and up front, rather than after partial
+/// output from earlier files.
+pub(crate) fn validate_files(filenames: &[String]) -> Result<()> {
+    let problems: Vec<_> = filenames
+        .iter()
+        .filter(|f| *f != STDIN_FILENAME)
+        .filter_map(|f| validate_file(f).err().map(|e| format!("'{f}': {e}")))
+        .collect();
+    match problems.len() {
+        0 => Ok(()),
+        1 => bail!("Can't read {}", problems[0]),
+        n => bail!("Can't read {n} files:\n  {}", problems.join("\n  ")),
+    }
+}
+
+fn validate_file(filename: &str) -> std::io::Result<()> {
+    if is_s3_url(filename) {
+        return if cfg!(feature = "s3") {
+            Ok(())
+        } else {
+            Err(std::io::Error::other(S3_NOT_ENABLED))
+        };
+    }
+    if std::fs::metadata(filename)?.is_dir() {
+        return Err(std::io::Error::other("Is a directory"));
+    }
+    std::fs::File::open(filename).map(|_| ())
 }
 
-#[cfg(not(target_os = "windows"))]
-fn open_file(filename: &String) -> Result<Box<dyn BufRead>> {
-    use compress_io::compress::CompressIo;
-    Ok(Box::new(CompressIo::new().path(filename).bufreader()?))
+const S3_NOT_ENABLED: &str = "S3 support isn't enabled in this build (the 's3' feature)";
+
+/// Whether a "filename" is actually an S3 object URL.
+pub(crate) fn is_s3_url(filename: &str) -> bool {
+    filename.starts_with("s3://")
+}
+
+#[cfg(feature = "s3")]
+fn open_s3(url: &str) -> Result<Box<dyn BufRead + Send>> {
+    s3::open_object(url)
+}
+
+#[cfg(not(feature = "s3"))]
+fn open_s3(_url: &str) -> Result<Box<dyn BufRead + Send>> {
+    bail!(S3_NOT_ENABLED)
+}
+
+fn is_compressed(filename: &str) -> bool {
+    Format::for_filename(filename).is_some()
+}
+
+/// The name of the log a (possibly rotated and compressed) file belongs to.
+/// E.g., `app.log.1`, `app.log.2.gz`, `app.log-20240701` and `app.log` are
+/// all part of `app.log`'s rotation set.
+pub(crate) fn rotation_base(filename: &str) -> &str {
+    // compressed extensions are ignored when comparing rotated filenames
+    let mut base = Format::for_filename(filename)
+        .and_then(|f| filename.strip_suffix(f.extension()))
+        .unwrap_or(filename);
+    loop {
+        if let Some((head, tail)) = base.rsplit_once('.') {
+            if !tail.is_empty() && tail.chars().all(|c| c.is_ascii_digit() || c == '-') {
+                base = head;
+                continue;
+            }
+        }
+        if let Some((head, tail)) = base.rsplit_once('-') {
+            if tail.len() == 8 && tail.chars().all(|c| c.is_ascii_digit()) {
+                base = head;
+                continue;
+            }
+        }
+        return base;
+    }
 }
 
-#[cfg(target_os = "windows")]
 fn open_stdin() -> Result<Box<dyn BufRead>> {
-    use std::io::stdin;
-    Ok(Box::new(stdin().lock()))
+    Ok(open_stream(io::stdin())?)
+}
+
+/// Open a file, decompressing it per its extension, or else if its content is
+/// compressed. A regular, uncompressed file is opened as a [Snapshot].
+fn open_file(filename: &String, grew: &Arc<AtomicBool>) -> Result<Box<dyn BufRead + Send>> {
+    let mut file = fs::File::open(filename)?;
+    if let Some(f) = Format::for_filename(filename) {
+        return decompress(f, file);
+    }
+    if !file.metadata()?.is_file() {
+        // e.g., a FIFO, which can't be rewound after checking its start
+        return open_stream(file);
+    }
+    match Format::of_file(&mut file)? {
+        Some(f) => decompress(f, file),
+        None => {
+            let len = file.metadata()?.len();
+            Ok(Box::new(Snapshot::new(file, len, grew.clone())))
+        }
+    }
 }
 
-#[cfg(target_os = "windows")]
-fn open_file(filename: &String) -> Result<Box<dyn BufRead>> {
-    use std::fs::File;
-    use std::io::BufReader;
-    Ok(Box::new(BufReader::new(File::open(filename)?)))
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn validate() {
+        let files = |fs: &[&str]| fs.iter().map(|f| f.to_string()).collect::<Vec<_>>();
+        assert!(validate_files(&files(&["app.log", "-"])).is_ok());
+        assert_eq!(
+            cfg!(feature = "s3"),
+            validate_files(&files(&["s3://bucket/app.log.gz"])).is_ok()
+        );
+        assert!(validate_files(&files(&["src"]))
+            .unwrap_err()
+            .to_string()
+            .contains("'src': Is a directory"));
+        let msg = validate_files(&files(&["app.log", "nope.log", "src"]))
+            .unwrap_err()
+            .to_string();
+        assert!(msg.starts_with("Can't read 2 files:"), "{msg}");
+        assert!(msg.contains("\n  'nope.log': "), "{msg}");
+        assert!(msg.contains("\n  'src': "), "{msg}");
+    }
+
+    #[test]
+    fn oversized() {
+        let files = ["app.log", "record_draining.log", "-"]
+            .map(String::from)
+            .to_vec();
+        let (kept, oversized) = partition_by_size(files, 100);
+        assert_eq!(vec!["record_draining.log", "-"], kept);
+        assert_eq!(vec![("app.log".to_owned(), 950)], oversized);
+    }
+
+    #[test]
+    fn compressed_positions() {
+        use std::io::Write;
+
+        use flate2::write::GzEncoder;
+
+        use crate::read::lines::Lines;
+
+        let text = "one\r\ntwo\n\nthree";
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("positions.gz");
+        let mut gz = GzEncoder::new(fs::File::create(&path).unwrap(), Default::default());
+        gz.write_all(text.as_bytes()).unwrap();
+        gz.finish().unwrap();
+        let filename = path.to_string_lossy().into_owned();
+        let reader = get_reader(&filename, false, &Arc::default()).unwrap();
+        let lines: Vec<_> = Lines::chained(reader, Vec::new())
+            .map(|l| l.unwrap())
+            .map(|l| (l.line_num, l.offset))
+            .collect();
+        // as if it weren't compressed
+        assert_eq!(vec![(1, 0), (2, 5), (3, 9), (4, 10)], lines);
+    }
+
+    #[test]
+    fn explained_open_errors() {
+        let exhausted = explain_open_error(io::Error::from_raw_os_error(EMFILE).into());
+        assert!(
+            format!("{exhausted:#}").starts_with("Too many open files; raise the limit"),
+            "{exhausted:#}"
+        );
+        let missing = explain_open_error(io::Error::from(io::ErrorKind::NotFound).into());
+        assert_eq!("entity not found", format!("{missing:#}"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn raised_open_files_limit() {
+        let soft = || {
+            let mut limit = libc::rlimit {
+                rlim_cur: 0,
+                rlim_max: 0,
+            };
+            assert_eq!(0, unsafe {
+                libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit)
+            });
+            limit.rlim_cur
+        };
+        let before = soft();
+        raise_open_files_limit();
+        assert!(soft() >= before);
+    }
+
+    #[test]
+    fn rotation_bases() {
+        assert_eq!("app.log", rotation_base("app.log"));
+        assert_eq!("app.log", rotation_base("app.log.1"));
+        assert_eq!("app.log", rotation_base("app.log.12.gz"));
+        assert_eq!("app.log", rotation_base("app.log-20240701.bz2"));
+        assert_eq!("jvm-1.log", rotation_base("jvm-1.log.2024-07-06-15.gz"));
+        assert_eq!("jvm-2", rotation_base("jvm-2"));
+        assert_eq!("log.txt", rotation_base("log.txt"));
+    }
 }
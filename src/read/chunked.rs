@@ -0,0 +1,225 @@
+use std::collections::VecDeque;
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
+use std::ops::Range;
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+use std::sync::Arc;
+use std::thread;
+
+use anyhow::{anyhow, Result};
+use regex_automata::meta::Regex;
+
+use crate::read::decompress::Format;
+use crate::read::encoding::Encoding;
+use crate::read::lines::Lines;
+use crate::read::records::{Record, Records};
+use crate::read::seek;
+
+/// Below this many bytes a chunk, splitting a file isn't worth it.
+const MIN_CHUNK: u64 = 8 * 1024 * 1024;
+
+/// How many selected records a chunk's thread can get ahead of the output by,
+/// so memory use stays flat, even if the output is slow.
+const BACKLOG: usize = 1024;
+
+/// Whether a record's text is selected. It's asked on the chunks' threads.
+pub(crate) type Selector = Arc<dyn Fn(&str) -> bool + Send + Sync>;
+
+/// Split a plain, uncompressed file into up to `count` chunks, each starting
+/// at a record (per the log pattern), so none is split between them. `None`
+/// if the file isn't one which can be read from an offset (e.g., it's UTF-16,
+/// per its byte order mark), or is too small to bother.
+pub(crate) fn chunks(
+    filename: &str,
+    count: usize,
+    log_pattern: &Regex,
+) -> Result<Option<Vec<Range<u64>>>> {
+    let Ok(mut file) = fs::File::open(filename) else {
+        // let the usual open explain it
+        return Ok(None);
+    };
+    let md = file.metadata()?;
+    let count = count.min((md.len() / MIN_CHUNK) as usize);
+    if !md.is_file()
+        || count < 2
+        || Format::of_file(&mut file)?.is_some()
+        || Encoding::has_bom(&mut file)?
+    {
+        return Ok(None);
+    }
+    let starts = seek::record_starts(&mut file, md.len(), count, |line| {
+        log_pattern.is_match(line)
+    })?;
+    let mut chunks = Vec::with_capacity(starts.len() + 1);
+    let mut start = 0;
+    for s in starts {
+        chunks.push(start..s);
+        start = s;
+    }
+    chunks.push(start..md.len());
+    Ok(Some(chunks))
+}
+
+/// The selected records of a file's chunks, which are split into records and
+/// searched at once, each on its own thread. They're in order, numbered as if
+/// the file were read from start to end.
+pub(crate) struct ChunkedRecords {
+    chunks: VecDeque<Receiver<Result<Found>>>,
+    /// Of the chunks already exhausted.
+    records: usize,
+    lines: usize,
+    bytes: u64,
+}
+
+enum Found {
+    Selected(Record),
+    /// The end of a chunk, of this many records, lines, and bytes.
+    End(usize, usize, u64),
+}
+
+impl ChunkedRecords {
+    pub(crate) fn spawn(
+        filename: &str,
+        chunks: Vec<Range<u64>>,
+        log_pattern: &Regex,
+        max_lines: Option<usize>,
+        is_selected: Selector,
+    ) -> ChunkedRecords {
+        let receivers = chunks
+            .into_iter()
+            .map(|chunk| {
+                let (tx, rx) = sync_channel(BACKLOG);
+                let (filename, log_pattern) = (filename.to_owned(), log_pattern.clone());
+                let is_selected = is_selected.clone();
+                thread::spawn(move || {
+                    let records = open(&filename, &chunk).map(|reader| {
+                        Records::new(Lines::chained(reader, Vec::new()), &log_pattern)
+                            .with_max_lines(max_lines)
+                    });
+                    if let Err(e) = records.and_then(|r| search(r, &chunk, &*is_selected, &tx)) {
+                        let _ = tx.send(Err(e));
+                    }
+                });
+                rx
+            })
+            .collect();
+        ChunkedRecords {
+            chunks: receivers,
+            records: 0,
+            lines: 0,
+            bytes: 0,
+        }
+    }
+
+    /// The records, lines, and bytes of the chunks searched so far.
+    pub(crate) fn searched(&self) -> (usize, usize, u64) {
+        (self.records, self.lines, self.bytes)
+    }
+}
+
+fn open(filename: &str, chunk: &Range<u64>) -> Result<Box<dyn BufRead>> {
+    let mut file = fs::File::open(filename)?;
+    file.seek(SeekFrom::Start(chunk.start))?;
+    Ok(Box::new(BufReader::new(file.take(chunk.end - chunk.start))))
+}
+
+/// Search a chunk's records, sending what it selects, and then its end. A
+/// send only fails if the records are no longer wanted, which stops the
+/// search.
+fn search(
+    chunk_records: Records,
+    chunk: &Range<u64>,
+    is_selected: &dyn Fn(&str) -> bool,
+    tx: &SyncSender<Result<Found>>,
+) -> Result<()> {
+    let (mut records, mut lines) = (0, 0);
+    for r in chunk_records {
+        let r = r?;
+        records += 1;
+        lines += r.text.matches('\n').count() + 1;
+        if is_selected(&r.text) {
+            let r = Record {
+                offset: chunk.start + r.offset,
+                ..r
+            };
+            if tx.send(Ok(Found::Selected(r))).is_err() {
+                return Ok(());
+            }
+        }
+    }
+    let _ = tx.send(Ok(Found::End(records, lines, chunk.end - chunk.start)));
+    Ok(())
+}
+
+impl Iterator for ChunkedRecords {
+    type Item = Result<Record>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let found = match self.chunks.front()?.recv() {
+                Ok(f) => f,
+                Err(_) => Err(anyhow!("A chunk's search stopped unexpectedly")),
+            };
+            match found {
+                Ok(Found::Selected(r)) => {
+                    return Some(Ok(Record {
+                        record_num: self.records + r.record_num,
+                        first_line: self.lines + r.first_line,
+                        ..r
+                    }))
+                }
+                Ok(Found::End(records, lines, bytes)) => {
+                    self.records += records;
+                    self.lines += lines;
+                    self.bytes += bytes;
+                    self.chunks.pop_front();
+                }
+                Err(e) => {
+                    self.chunks.clear();
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("chunked.log");
+        let mut log = String::from("preamble\n");
+        for i in 0..1000 {
+            log.push_str(&format!("#{i} record\n  continued {}\n", i % 7));
+        }
+        fs::write(&path, &log).unwrap();
+        let filename = path.to_string_lossy();
+        let log_pattern = Regex::new("^#").unwrap();
+        let len = log.len() as u64;
+        let starts = seek::record_starts(&mut fs::File::open(&path).unwrap(), len, 3, |l| {
+            log_pattern.is_match(l)
+        })
+        .unwrap();
+        assert_eq!(2, starts.len());
+        let chunks = vec![0..starts[0], starts[0]..starts[1], starts[1]..len];
+        let mut records = ChunkedRecords::spawn(
+            &filename,
+            chunks,
+            &log_pattern,
+            None,
+            Arc::new(|text: &str| text.ends_with("continued 3")),
+        );
+        let selected: Vec<_> = records.by_ref().map(Result::unwrap).collect();
+        assert_eq!((1001, 2001, len), records.searched());
+        assert_eq!(143, selected.len());
+        for r in selected {
+            let i: usize = r.text[1..r.text.find(' ').unwrap()].parse().unwrap();
+            assert_eq!(i + 2, r.record_num);
+            assert_eq!(i * 2 + 2, r.first_line);
+            assert!(log[r.offset as usize..].starts_with(&r.text));
+        }
+    }
+}
@@ -0,0 +1,258 @@
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Seek, Write};
+use std::process::{Child, ChildStdout, Command, Stdio};
+use std::thread;
+
+use anyhow::{Context, Result};
+use bzip2::read::MultiBzDecoder;
+use flate2::read::MultiGzDecoder;
+use lz4_flex::frame::FrameDecoder;
+use xz2::read::XzDecoder;
+
+use crate::trace::decompressing;
+
+/// A compression format, recognized by its magic number.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum Format {
+    Gzip,
+    Zstd,
+    Bzip2,
+    Xz,
+    Lz4,
+    /// The ancient `compress` utility's, i.e., `.Z` files.
+    Compress,
+}
+
+/// Long enough for any format's magic number.
+const MAGIC_LEN: usize = 6;
+
+impl Format {
+    pub(crate) const ALL: [Format; 6] = [
+        Format::Gzip,
+        Format::Zstd,
+        Format::Bzip2,
+        Format::Xz,
+        Format::Lz4,
+        Format::Compress,
+    ];
+
+    fn magic(self) -> &'static [u8] {
+        match self {
+            Format::Gzip => &[0x1f, 0x8b],
+            Format::Zstd => &[0x28, 0xb5, 0x2f, 0xfd],
+            Format::Bzip2 => b"BZh",
+            Format::Xz => &[0xfd, 0x37, 0x7a, 0x58, 0x5a, 0x00],
+            Format::Lz4 => &[0x04, 0x22, 0x4d, 0x18],
+            Format::Compress => &[0x1f, 0x9d],
+        }
+    }
+
+    /// The extension of a file in the format.
+    pub(crate) fn extension(self) -> &'static str {
+        match self {
+            Format::Gzip => ".gz",
+            Format::Zstd => ".zst",
+            Format::Bzip2 => ".bz2",
+            Format::Xz => ".xz",
+            Format::Lz4 => ".lz4",
+            Format::Compress => ".Z",
+        }
+    }
+
+    /// The format implied by a file's extension, if any.
+    pub(crate) fn for_filename(filename: &str) -> Option<Format> {
+        Format::ALL
+            .into_iter()
+            .find(|f| filename.ends_with(f.extension()))
+    }
+
+    /// The format of data starting with `head`, if it's compressed.
+    pub(crate) fn detect(head: &[u8]) -> Option<Format> {
+        Format::ALL
+            .into_iter()
+            .find(|f| head.starts_with(f.magic()))
+    }
+
+    /// The format of a file's content, if it's compressed. It's left at its
+    /// start, either way.
+    pub(crate) fn of_file(file: &mut fs::File) -> io::Result<Option<Format>> {
+        let mut head = Vec::with_capacity(MAGIC_LEN);
+        file.take(MAGIC_LEN as u64).read_to_end(&mut head)?;
+        file.rewind()?;
+        Ok(Format::detect(&head))
+    }
+
+    /// The utility (and its arguments) which decompresses the format to
+    /// STDOUT, for those without an in-process decoder.
+    fn utility(self) -> (&'static str, &'static [&'static str]) {
+        match self {
+            // gzip reads compress's format too
+            Format::Gzip | Format::Compress => ("gzip", &["-dc"]),
+            Format::Zstd => ("zstd", &["-dc"]),
+            Format::Bzip2 => ("bzip2", &["-dc"]),
+            Format::Xz => ("xz", &["-dc"]),
+            Format::Lz4 => ("lz4", &["-dc"]),
+        }
+    }
+}
+
+/// Open a stream, which can't be rewound (e.g., STDIN or a FIFO), decompressing
+/// it if its start is a compression format's magic number.
+pub(crate) fn open_stream(input: impl Read + Send + 'static) -> Result<Box<dyn BufRead + Send>> {
+    let mut reader = BufReader::new(input);
+    match Format::detect(reader.fill_buf()?) {
+        Some(f) => decompress(f, reader),
+        None => Ok(Box::new(reader)),
+    }
+}
+
+/// Decompress `input`. Every format is decoded in-process, except
+/// `compress`'s, which is piped through `gzip`, so it must be on your `$PATH`.
+pub(crate) fn decompress(
+    format: Format,
+    input: impl Read + Send + 'static,
+) -> Result<Box<dyn BufRead + Send>> {
+    let name = format.extension();
+    Ok(match format {
+        Format::Gzip => Box::new(BufReader::new(decompressing(
+            name,
+            MultiGzDecoder::new(input),
+        ))),
+        Format::Zstd => Box::new(BufReader::new(decompressing(
+            name,
+            zstd::Decoder::new(input)?,
+        ))),
+        Format::Xz => Box::new(BufReader::new(decompressing(
+            name,
+            XzDecoder::new_multi_decoder(input),
+        ))),
+        Format::Bzip2 => Box::new(BufReader::new(decompressing(
+            name,
+            MultiBzDecoder::new(input),
+        ))),
+        Format::Lz4 => Box::new(BufReader::new(decompressing(
+            name,
+            FrameDecoder::new(input),
+        ))),
+        f => Box::new(BufReader::new(decompressing(name, pipe_through(f, input)?))),
+    })
+}
+
+fn pipe_through(format: Format, mut input: impl Read + Send + 'static) -> Result<ChildOutput> {
+    let (utility, args) = format.utility();
+    let mut child = Command::new(utility)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .with_context(|| {
+            format!("Failed to run '{utility}' to decompress; is it on your $PATH?")
+        })?;
+    let mut stdin = child.stdin.take().unwrap();
+    // a broken pipe means the utility quit, which its exit status explains
+    thread::spawn(move || io::copy(&mut input, &mut stdin).and_then(|_| stdin.flush()));
+    let stdout = child.stdout.take().unwrap();
+    Ok(ChildOutput::new(child, stdout, utility))
+}
+
+/// The output of a child process, which fails at EOF if the process did.
+pub(crate) struct ChildOutput {
+    child: Child,
+    stdout: ChildStdout,
+    name: &'static str,
+}
+
+impl ChildOutput {
+    /// Read a child's output, naming it `name` if it fails.
+    pub(crate) fn new(child: Child, stdout: ChildStdout, name: &'static str) -> ChildOutput {
+        ChildOutput {
+            child,
+            stdout,
+            name,
+        }
+    }
+}
+
+impl Read for ChildOutput {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.stdout.read(buf)?;
+        if n == 0 && !buf.is_empty() {
+            let status = self.child.wait()?;
+            if !status.success() {
+                return Err(io::Error::other(format!(
+                    "'{}' failed ({status})",
+                    self.name
+                )));
+            }
+        }
+        Ok(n)
+    }
+}
+
+impl Drop for ChildOutput {
+    fn drop(&mut self) {
+        // stopped early (e.g., --max-count); don't leave it running
+        if let Ok(None) = self.child.try_wait() {
+            let _ = self.child.kill();
+            let _ = self.child.wait();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use flate2::write::GzEncoder;
+
+    use super::*;
+
+    const TEXT: &str = "one\ntwo\n";
+
+    fn read_all(mut r: Box<dyn BufRead + Send>) -> String {
+        let mut s = String::new();
+        r.read_to_string(&mut s).unwrap();
+        s
+    }
+
+    #[test]
+    fn detection() {
+        assert_eq!(Some(Format::Gzip), Format::detect(&[0x1f, 0x8b, 8]));
+        assert_eq!(Some(Format::Compress), Format::detect(&[0x1f, 0x9d]));
+        assert_eq!(Some(Format::Bzip2), Format::detect(b"BZh91AY"));
+        assert_eq!(None, Format::detect(b"2024-07-01"));
+        assert_eq!(None, Format::detect(&[0xfd, 0x37]));
+    }
+
+    #[test]
+    fn extensions() {
+        assert_eq!(Some(Format::Zstd), Format::for_filename("app.log.3.zst"));
+        assert_eq!(Some(Format::Lz4), Format::for_filename("app.log.lz4"));
+        assert_eq!(Some(Format::Compress), Format::for_filename("app.log.Z"));
+        assert_eq!(None, Format::for_filename("app.log.z"));
+        assert_eq!(None, Format::for_filename("app.log"));
+    }
+
+    #[test]
+    fn streams() {
+        let mut gz = GzEncoder::new(Vec::new(), Default::default());
+        gz.write_all(TEXT.as_bytes()).unwrap();
+        let gz = gz.finish().unwrap();
+        assert_eq!(TEXT, read_all(open_stream(Cursor::new(gz)).unwrap()));
+        let zst = zstd::encode_all(TEXT.as_bytes(), 0).unwrap();
+        assert_eq!(TEXT, read_all(open_stream(Cursor::new(zst)).unwrap()));
+        let mut xz = xz2::write::XzEncoder::new(Vec::new(), 6);
+        xz.write_all(TEXT.as_bytes()).unwrap();
+        let xz = xz.finish().unwrap();
+        assert_eq!(TEXT, read_all(open_stream(Cursor::new(xz)).unwrap()));
+        let mut bz = bzip2::write::BzEncoder::new(Vec::new(), Default::default());
+        bz.write_all(TEXT.as_bytes()).unwrap();
+        let bz = bz.finish().unwrap();
+        assert_eq!(TEXT, read_all(open_stream(Cursor::new(bz)).unwrap()));
+        let mut lz4 = lz4_flex::frame::FrameEncoder::new(Vec::new());
+        lz4.write_all(TEXT.as_bytes()).unwrap();
+        let lz4 = lz4.finish().unwrap();
+        assert_eq!(TEXT, read_all(open_stream(Cursor::new(lz4)).unwrap()));
+        assert_eq!(TEXT, read_all(open_stream(Cursor::new(TEXT)).unwrap()));
+    }
+}
@@ -0,0 +1,173 @@
+use std::io::BufRead;
+
+use anyhow::{bail, Result};
+
+use crate::read::records::Record;
+
+/// Splits a stream into records on an arbitrary byte sequence, rather than on
+/// lines and a log pattern. Record text is decoded lossily, so binary-ish
+/// framed streams can still be searched.
+pub(crate) struct DelimitedRecords {
+    reader: Box<dyn BufRead>,
+    delimiter: Vec<u8>,
+    record_num: usize,
+    line_num: usize,
+    offset: u64,
+    eof: bool,
+}
+
+impl DelimitedRecords {
+    pub(crate) fn new(reader: Box<dyn BufRead>, delimiter: &[u8]) -> DelimitedRecords {
+        debug_assert!(!delimiter.is_empty(), "empty record delimiter");
+        DelimitedRecords {
+            reader,
+            delimiter: delimiter.to_vec(),
+            record_num: 0,
+            line_num: 1,
+            offset: 0,
+            eof: false,
+        }
+    }
+
+    /// Read through the next delimiter (or EOF), returning whether anything
+    /// was read at all.
+    fn read_record(&mut self, buf: &mut Vec<u8>) -> std::io::Result<bool> {
+        let last = *self.delimiter.last().unwrap();
+        loop {
+            let n = self.reader.read_until(last, buf)?;
+            self.offset += n as u64;
+            if n == 0 {
+                self.eof = true;
+                return Ok(!buf.is_empty());
+            }
+            if buf.ends_with(&self.delimiter) {
+                buf.truncate(buf.len() - self.delimiter.len());
+                return Ok(true);
+            }
+        }
+    }
+}
+
+impl Iterator for DelimitedRecords {
+    type Item = Result<Record>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.eof {
+            return None;
+        }
+        let mut buf = Vec::new();
+        let offset = self.offset;
+        match self.read_record(&mut buf) {
+            Err(e) => Some(Err(e.into())),
+            Ok(false) => None,
+            Ok(true) => {
+                let first_line = self.line_num;
+                self.line_num += bytecount(&buf, b'\n') + bytecount(&self.delimiter, b'\n');
+                if buf.ends_with(b"\n") {
+                    buf.pop();
+                }
+                self.record_num += 1;
+                Some(Ok(Record {
+                    text: String::from_utf8_lossy(&buf).into_owned(),
+                    record_num: self.record_num,
+                    first_line,
+                    segment: 0,
+                    offset,
+                    // delimiters aren't written back out, so neither is this
+                    terminated: true,
+                }))
+            }
+        }
+    }
+}
+
+fn bytecount(hay: &[u8], needle: u8) -> usize {
+    hay.iter().filter(|&&b| b == needle).count()
+}
+
+/// Parse a delimiter specification into bytes. Characters stand for their
+/// UTF-8 encoding, and `\xHH` (hex), `\0`, `\n`, `\r`, `\t`, and `\\` escapes
+/// are recognized.
+pub(crate) fn parse_delimiter(spec: &str) -> Result<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(spec.len());
+    let mut chars = spec.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buf = [0; 4];
+            bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            continue;
+        }
+        match chars.next() {
+            Some('x') => {
+                let hex: String = chars.by_ref().take(2).collect();
+                match u8::from_str_radix(&hex, 16) {
+                    Ok(b) if hex.len() == 2 => bytes.push(b),
+                    _ => bail!("Invalid hex escape '\\x{hex}' in delimiter '{spec}'"),
+                }
+            }
+            Some('0') => bytes.push(0),
+            Some('n') => bytes.push(b'\n'),
+            Some('r') => bytes.push(b'\r'),
+            Some('t') => bytes.push(b'\t'),
+            Some('\\') => bytes.push(b'\\'),
+            Some(e) => bail!("Unknown escape '\\{e}' in delimiter '{spec}'"),
+            None => bail!("Trailing backslash in delimiter '{spec}'"),
+        }
+    }
+    if bytes.is_empty() {
+        bail!("Record delimiter may not be empty");
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn to_records(input: &'static [u8], delimiter: &[u8]) -> Vec<Record> {
+        DelimitedRecords::new(Box::new(Cursor::new(input)), delimiter)
+            .map(|r| r.unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn does_it_smoke() {
+        assert_eq!(
+            vec![
+                Record::new("one\ntwo", 1, 1),
+                Record::new("three", 2, 3).at(10),
+                Record::new("f\u{fffd}ur", 3, 3).at(17),
+            ],
+            to_records(b"one\ntwo\n\x1e\x1ethree\x1e\x1ef\xffur", b"\x1e\x1e")
+        )
+    }
+
+    #[test]
+    fn trailing_delimiter() {
+        assert_eq!(
+            vec![Record::new("one", 1, 1), Record::new("two", 2, 1).at(4)],
+            to_records(b"one\0two\0", b"\0")
+        )
+    }
+
+    #[test]
+    fn partial_delimiter() {
+        assert_eq!(
+            vec![Record::new("a-b", 1, 1), Record::new("c", 2, 1).at(5)],
+            to_records(b"a-b--c", b"--")
+        )
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(vec![0x1e], parse_delimiter(r"\x1e").unwrap());
+        assert_eq!(b"--\0\n".to_vec(), parse_delimiter(r"--\0\n").unwrap());
+        assert_eq!(b"\\\xc3\xa9".to_vec(), parse_delimiter(r"\\é").unwrap());
+        assert!(parse_delimiter("").is_err());
+        assert!(parse_delimiter(r"\x1").is_err());
+        assert!(parse_delimiter(r"\xzz").is_err());
+        assert!(parse_delimiter(r"\q").is_err());
+    }
+}
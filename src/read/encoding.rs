@@ -0,0 +1,262 @@
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Seek};
+
+use clap::ValueEnum;
+
+const UTF8_BOM: &[u8] = &[0xef, 0xbb, 0xbf];
+const UTF16LE_BOM: &[u8] = &[0xff, 0xfe];
+const UTF16BE_BOM: &[u8] = &[0xfe, 0xff];
+
+/// How many bytes are transcoded at a time.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// A text encoding, which is transcoded to UTF-8 before records are split.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+pub(crate) enum Encoding {
+    #[value(name = "utf-8", alias = "utf8")]
+    Utf8,
+    #[value(name = "utf-16le", alias = "utf16le")]
+    Utf16le,
+    #[value(name = "utf-16be", alias = "utf16be")]
+    Utf16be,
+    /// ISO-8859-1, where each byte is the Unicode code point of the same value.
+    #[value(name = "latin1", alias = "iso-8859-1")]
+    Latin1,
+    /// Like latin1, but with punctuation (e.g., curly quotes) in 0x80-0x9f.
+    #[value(name = "windows-1252", alias = "cp1252")]
+    Windows1252,
+}
+
+/// What windows-1252 has in 0x80-0x9f, where latin1 has control characters.
+/// The five bytes it leaves undefined are read as latin1's.
+const WINDOWS_1252_HIGH: [char; 32] = [
+    '€', '\u{81}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\u{8d}', 'Ž', '\u{8f}',
+    '\u{90}', '‘', '’', '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\u{9d}', 'ž', 'Ÿ',
+];
+
+impl Encoding {
+    /// The encoding a byte order mark at the start of `head` is for, and the
+    /// mark's length.
+    fn of_bom(head: &[u8]) -> Option<(Encoding, usize)> {
+        [
+            (Encoding::Utf8, UTF8_BOM),
+            (Encoding::Utf16le, UTF16LE_BOM),
+            (Encoding::Utf16be, UTF16BE_BOM),
+        ]
+        .into_iter()
+        .find(|(_, bom)| head.starts_with(bom))
+        .map(|(e, bom)| (e, bom.len()))
+    }
+
+    /// Whether a file starts with a byte order mark. It's left at its start,
+    /// either way.
+    pub(crate) fn has_bom(file: &mut fs::File) -> io::Result<bool> {
+        let mut head = Vec::with_capacity(UTF8_BOM.len());
+        file.take(UTF8_BOM.len() as u64).read_to_end(&mut head)?;
+        file.rewind()?;
+        Ok(Encoding::of_bom(&head).is_some())
+    }
+
+    /// Decode the start of `bytes` onto `out`, returning how many
+    /// were decoded. Any left over are the start of a character, which needs
+    /// more bytes. Invalid characters are replaced with U+FFFD.
+    fn decode(self, bytes: &[u8], out: &mut String) -> usize {
+        match self {
+            Encoding::Utf8 => {
+                let mut rest = bytes;
+                loop {
+                    match std::str::from_utf8(rest) {
+                        Ok(s) => {
+                            out.push_str(s);
+                            return bytes.len();
+                        }
+                        Err(e) => {
+                            let (valid, after) = rest.split_at(e.valid_up_to());
+                            out.push_str(std::str::from_utf8(valid).unwrap());
+                            let Some(n) = e.error_len() else {
+                                // only the start of a character, so far
+                                return bytes.len() - after.len();
+                            };
+                            out.push(char::REPLACEMENT_CHARACTER);
+                            rest = &after[n..];
+                        }
+                    }
+                }
+            }
+            Encoding::Utf16le | Encoding::Utf16be => {
+                let unit = |i: usize| {
+                    let pair = [bytes[i], bytes[i + 1]];
+                    if self == Encoding::Utf16le {
+                        u16::from_le_bytes(pair)
+                    } else {
+                        u16::from_be_bytes(pair)
+                    }
+                };
+                let mut i = 0;
+                while i + 1 < bytes.len() {
+                    let hi = unit(i) as u32;
+                    let (c, len) = if (0xd800..0xdc00).contains(&hi) {
+                        if i + 3 >= bytes.len() {
+                            break; // the low surrogate's yet to come
+                        }
+                        let lo = unit(i + 2) as u32;
+                        if (0xdc00..0xe000).contains(&lo) {
+                            let c = 0x10000 + ((hi - 0xd800) << 10) + (lo - 0xdc00);
+                            (char::from_u32(c), 4)
+                        } else {
+                            (None, 2)
+                        }
+                    } else {
+                        // None for a lone low surrogate
+                        (char::from_u32(hi), 2)
+                    };
+                    out.push(c.unwrap_or(char::REPLACEMENT_CHARACTER));
+                    i += len;
+                }
+                i
+            }
+            Encoding::Latin1 => {
+                out.extend(bytes.iter().map(|&b| b as char));
+                bytes.len()
+            }
+            Encoding::Windows1252 => {
+                out.extend(bytes.iter().map(|&b| match b {
+                    0x80..=0x9f => WINDOWS_1252_HIGH[(b - 0x80) as usize],
+                    _ => b as char,
+                }));
+                bytes.len()
+            }
+        }
+    }
+}
+
+/// Transcode a stream to UTF-8, from `encoding` if it's given, or else per
+/// its byte order mark, which is dropped. Without either, it's read as it is.
+/// Invalid characters, even in UTF-8, are replaced with U+FFFD.
+pub(crate) fn decode(
+    mut reader: Box<dyn BufRead>,
+    encoding: Option<Encoding>,
+) -> io::Result<Box<dyn BufRead>> {
+    let bom = Encoding::of_bom(reader.fill_buf()?);
+    let encoding = match (encoding, bom) {
+        (Some(e), Some((b, len))) if e == b => {
+            reader.consume(len);
+            e
+        }
+        (Some(e), _) => e,
+        (None, Some((b, len))) => {
+            reader.consume(len);
+            b
+        }
+        (None, None) => return Ok(reader),
+    };
+    Ok(Box::new(BufReader::new(Transcoder {
+        reader,
+        encoding,
+        pending: Vec::new(),
+        decoded: String::new(),
+        pos: 0,
+    })))
+}
+
+/// A stream transcoded to UTF-8.
+struct Transcoder {
+    reader: Box<dyn BufRead>,
+    encoding: Encoding,
+    /// Bytes read, but not yet decoded, as they're part of a character.
+    pending: Vec<u8>,
+    decoded: String,
+    pos: usize,
+}
+
+impl Read for Transcoder {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.decoded.len() {
+            self.decoded.clear();
+            self.pos = 0;
+            let bytes = self.reader.fill_buf()?;
+            if bytes.is_empty() {
+                if self.pending.is_empty() {
+                    return Ok(0);
+                }
+                // a truncated character
+                self.pending.clear();
+                self.decoded.push(char::REPLACEMENT_CHARACTER);
+                break;
+            }
+            let n = bytes.len().min(CHUNK_SIZE);
+            self.pending.extend_from_slice(&bytes[..n]);
+            self.reader.consume(n);
+            let used = self.encoding.decode(&self.pending, &mut self.decoded);
+            self.pending.drain(..used);
+        }
+        let n = buf.len().min(self.decoded.len() - self.pos);
+        buf[..n].copy_from_slice(&self.decoded.as_bytes()[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn decoded(bytes: Vec<u8>, encoding: Option<Encoding>) -> String {
+        let mut s = String::new();
+        decode(Box::new(Cursor::new(bytes)), encoding)
+            .unwrap()
+            .read_to_string(&mut s)
+            .unwrap();
+        s
+    }
+
+    fn utf16le(text: &str) -> Vec<u8> {
+        text.encode_utf16().flat_map(u16::to_le_bytes).collect()
+    }
+
+    #[test]
+    fn boms() {
+        let text = "2024-07-01 naïve 😀 queue\nat x\n";
+        let bytes = [UTF16LE_BOM, &utf16le(text)].concat();
+        assert_eq!(text, decoded(bytes.clone(), None));
+        assert_eq!(text, decoded(bytes, Some(Encoding::Utf16le)));
+        let bytes: Vec<_> = text.encode_utf16().flat_map(u16::to_be_bytes).collect();
+        assert_eq!(text, decoded([UTF16BE_BOM, &bytes].concat(), None));
+        assert_eq!(text, decoded([UTF8_BOM, text.as_bytes()].concat(), None));
+        assert_eq!(text, decoded(text.as_bytes().to_vec(), None));
+    }
+
+    #[test]
+    fn legacy() {
+        let bytes = b"caf\xe9 \x93quoted\x94\n".to_vec();
+        assert_eq!(
+            "café \u{93}quoted\u{94}\n",
+            decoded(bytes.clone(), Some(Encoding::Latin1))
+        );
+        assert_eq!(
+            "café “quoted”\n",
+            decoded(bytes.clone(), Some(Encoding::Windows1252))
+        );
+        assert_eq!(
+            "caf\u{fffd} \u{fffd}quoted\u{fffd}\n",
+            decoded(bytes, Some(Encoding::Utf8))
+        );
+    }
+
+    #[test]
+    fn split_characters() {
+        let text = "😀x";
+        let bytes = utf16le(text);
+        let mut out = String::new();
+        // half a surrogate pair
+        assert_eq!(0, Encoding::Utf16le.decode(&bytes[..3], &mut out));
+        assert_eq!(4, Encoding::Utf16le.decode(&bytes[..5], &mut out));
+        assert_eq!("😀", out);
+        // a lone surrogate, and a truncated unit
+        let mut bytes = utf16le("a");
+        bytes.extend_from_slice(&[0x00, 0xdc, b'b']);
+        assert_eq!("a\u{fffd}\u{fffd}", decoded(bytes, Some(Encoding::Utf16le)));
+    }
+}
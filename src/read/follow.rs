@@ -0,0 +1,201 @@
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use regex_automata::meta::Regex;
+
+use crate::read::lines::Line;
+use crate::read::records::{Record, Splitter};
+use crate::read::traverse::{file_id, FileId};
+
+/// How long to wait before checking a followed file for more lines.
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// The records of a file which is still being written, like `tail -f`. At
+/// the end of the file, it waits for more lines, rather than ending. A
+/// record is held until the next one starts, so its continuation lines are
+/// included, unless nothing is written for `flush_after`. A line is only
+/// read once it's complete.
+///
+/// Like `tail -F`, once it's caught up, a file which has been rotated (i.e.,
+/// renamed, and a new file created in its place) or truncated is reopened by
+/// name, and followed from its start.
+pub(crate) struct FollowRecords<'a> {
+    reader: Box<dyn BufRead>,
+    filename: &'a str,
+    /// Which file is being followed, to notice when it's been rotated.
+    id: Option<FileId>,
+    log_pattern: &'a Regex,
+    flush_after: Duration,
+    splitter: Splitter,
+    /// The line being read, which may be written in several pieces.
+    partial: String,
+    line_num: usize,
+    offset: u64,
+    idle_since: Option<Instant>,
+}
+
+impl<'a> FollowRecords<'a> {
+    pub(crate) fn new(
+        reader: Box<dyn BufRead>,
+        filename: &'a str,
+        log_pattern: &'a Regex,
+        flush_after: Duration,
+    ) -> FollowRecords<'a> {
+        FollowRecords {
+            reader,
+            filename,
+            id: file_id(filename),
+            log_pattern,
+            flush_after,
+            splitter: Splitter::default(),
+            partial: String::new(),
+            line_num: 0,
+            offset: 0,
+            idle_since: None,
+        }
+    }
+
+    /// Split records longer than `max_lines`, if set. See [Splitter].
+    pub(crate) fn with_max_lines(self, max_lines: Option<usize>) -> Self {
+        FollowRecords {
+            splitter: Splitter::with_max_lines(max_lines),
+            ..self
+        }
+    }
+
+    /// Reopen the file by name if it's been rotated or truncated, returning
+    /// whether it was. A rotated file which hasn't been replaced yet is left
+    /// be, until it is.
+    fn reopen_if_replaced(&mut self) -> std::io::Result<bool> {
+        let Ok(md) = fs::metadata(self.filename) else {
+            return Ok(false);
+        };
+        let id = file_id(self.filename);
+        let why = if id != self.id {
+            "was rotated"
+        } else if md.len() < self.offset {
+            "was truncated"
+        } else {
+            return Ok(false);
+        };
+        self.reader = Box::new(BufReader::new(fs::File::open(self.filename)?));
+        eprintln!(
+            "lgrep: {}: {why}; following it from its start",
+            self.filename
+        );
+        self.id = id;
+        self.partial.clear();
+        self.line_num = 0;
+        self.offset = 0;
+        Ok(true)
+    }
+}
+
+impl<'a> Iterator for FollowRecords<'a> {
+    type Item = anyhow::Result<Record>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Err(e) = self.reader.read_line(&mut self.partial) {
+                return Some(Err(e.into()));
+            }
+            if let Some(text) = self.partial.strip_suffix('\n') {
+                self.idle_since = None;
+                self.line_num += 1;
+                let line = Line {
+                    text: text.to_owned(),
+                    line_num: self.line_num,
+                    segment: 0,
+                    offset: self.offset,
+                    terminated: true,
+                };
+                self.offset += self.partial.len() as u64;
+                self.partial.clear();
+                if let Some(r) = self.splitter.push(line, self.log_pattern) {
+                    return Some(Ok(r));
+                }
+                continue;
+            }
+            // caught up with the writer
+            match self.reopen_if_replaced() {
+                Err(e) => return Some(Err(e.into())),
+                Ok(true) => {
+                    self.idle_since = None;
+                    // the old file's last record is complete
+                    let last = self.splitter.finish();
+                    self.splitter.reset();
+                    if let Some(r) = last {
+                        return Some(Ok(r));
+                    }
+                    continue;
+                }
+                Ok(false) => {}
+            }
+            let idle_since = *self.idle_since.get_or_insert_with(Instant::now);
+            if idle_since.elapsed() >= self.flush_after {
+                if let Some(r) = self.splitter.finish() {
+                    return Some(Ok(r));
+                }
+            }
+            thread::sleep(POLL_INTERVAL.min(self.flush_after));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs::{self, File, OpenOptions};
+    use std::io::{BufReader, Write};
+
+    use super::*;
+
+    #[test]
+    fn appended() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("follow.log");
+        fs::write(&path, "L one\nmore\nL tw").unwrap();
+        let re = Regex::new("^L").unwrap();
+        let reader = Box::new(BufReader::new(File::open(&path).unwrap()));
+        let filename = path.to_string_lossy();
+        let mut records = FollowRecords::new(reader, &filename, &re, Duration::from_millis(50));
+        // flushed, as nothing more was written
+        assert_eq!("L one\nmore", records.next().unwrap().unwrap().text);
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"o\nstill two\nL three\n").unwrap();
+        let two = records.next().unwrap().unwrap();
+        assert_eq!(Record::new("L two\nstill two", 2, 3).at(11), two);
+        assert_eq!("L three", records.next().unwrap().unwrap().text);
+    }
+
+    #[test]
+    fn rotated_and_truncated() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("rotate.log");
+        let rotated = dir.path().join("rotate.log.1");
+        fs::write(&path, "L one\nL two\n").unwrap();
+        let re = Regex::new("^L").unwrap();
+        let reader = Box::new(BufReader::new(File::open(&path).unwrap()));
+        let filename = path.to_string_lossy();
+        let mut records = FollowRecords::new(reader, &filename, &re, Duration::from_secs(60));
+        assert_eq!("L one", records.next().unwrap().unwrap().text);
+        fs::rename(&path, &rotated).unwrap();
+        fs::write(&path, "L three\n").unwrap();
+        // two isn't held for the flush timeout
+        assert_eq!(
+            Record::new("L two", 2, 2).at(6),
+            records.next().unwrap().unwrap()
+        );
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"L four\n").unwrap();
+        // numbered from the new file's start
+        assert_eq!(
+            Record::new("L three", 1, 1),
+            records.next().unwrap().unwrap()
+        );
+        fs::write(&path, "L six\nL seven\n").unwrap();
+        assert_eq!("L four", records.next().unwrap().unwrap().text);
+        assert_eq!(Record::new("L six", 1, 1), records.next().unwrap().unwrap());
+    }
+}
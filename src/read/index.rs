@@ -0,0 +1,204 @@
+use std::fs;
+use std::io::{BufReader, Read, Seek};
+
+use anyhow::{bail, Context, Result};
+use clap::ValueEnum;
+use jiff::Timestamp;
+use regex_automata::meta::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::read::decompress::Format;
+use crate::read::encoding::Encoding;
+use crate::read::lines::Lines;
+use crate::stable_hash;
+
+/// What's appended to a file's name to name its index.
+const EXTENSION: &str = ".lgrep-idx";
+
+/// The version of the index format, which is rebuilt if it's changed.
+const VERSION: u32 = 1;
+
+/// How far apart checkpoints are, at least, so an index stays small (about a
+/// thousand checkpoints per GiB), while seeking to one leaves little to read.
+const SPACING: u64 = 1024 * 1024;
+
+/// How many of a file's first bytes are hashed, to tell it's the same file.
+const HEAD_LEN: u64 = 4096;
+
+/// What to do with a file's sidecar index, for '--index'.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+pub(crate) enum IndexMode {
+    /// Index each FILE, instead of searching it.
+    Build,
+    /// Seek by each FILE's index, if it has a current one.
+    Use,
+}
+
+/// Where records start in a file, and when they were, every so often, so a
+/// search for a time range can start near it, with the record and line
+/// numbers it'd have had reading from the start. It's kept beside the file,
+/// and stays good while the file only grows.
+#[derive(Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub(crate) struct Index {
+    version: u32,
+    /// The file's size when indexed.
+    len: u64,
+    /// A hash of the file's first bytes.
+    head: u64,
+    /// The log pattern the file's records were split by.
+    log_pattern: String,
+    checkpoints: Vec<Checkpoint>,
+}
+
+/// A record with a timestamp, and where it is.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub(crate) struct Checkpoint {
+    pub(crate) offset: u64,
+    pub(crate) record_num: usize,
+    pub(crate) first_line: usize,
+    /// Milliseconds since the Unix epoch.
+    ts: i64,
+}
+
+/// The name of a file's index.
+pub(crate) fn path_for(filename: &str) -> String {
+    format!("{filename}{EXTENSION}")
+}
+
+/// Whether a file is an index, so isn't searched when recursing.
+pub(crate) fn is_index(path: &str) -> bool {
+    path.ends_with(EXTENSION)
+}
+
+/// A hash of a file's first bytes. It's left at its start.
+fn head_hash(file: &mut fs::File) -> Result<u64> {
+    let mut head = Vec::with_capacity(HEAD_LEN as usize);
+    file.take(HEAD_LEN).read_to_end(&mut head)?;
+    file.rewind()?;
+    Ok(stable_hash(&String::from_utf8_lossy(&head)))
+}
+
+impl Index {
+    /// Index a plain, uncompressed file, split into records by `log_pattern`
+    /// (whose source is `log_pattern_source`), with `timestamp` finding when
+    /// each was.
+    pub(crate) fn build(
+        filename: &str,
+        log_pattern: &Regex,
+        log_pattern_source: &str,
+        timestamp: impl Fn(&str) -> Option<Timestamp>,
+    ) -> Result<Index> {
+        let mut file = fs::File::open(filename)
+            .with_context(|| format!("Failed to open '{filename}' for reading"))?;
+        let md = file.metadata()?;
+        if !md.is_file() || Format::of_file(&mut file)?.is_some() || Encoding::has_bom(&mut file)? {
+            bail!("Can't index '{filename}'; only plain, uncompressed files can be indexed");
+        }
+        let head = head_hash(&mut file)?;
+        let reader = BufReader::new(file.take(md.len()));
+        let mut checkpoints: Vec<Checkpoint> = Vec::new();
+        for r in Lines::chained(Box::new(reader), Vec::new()).records(log_pattern) {
+            let r = r.with_context(|| format!("Failed to read from '{filename}'"))?;
+            if checkpoints
+                .last()
+                .is_some_and(|c| r.offset < c.offset + SPACING)
+            {
+                continue;
+            }
+            if let Some(ts) = timestamp(&r.text) {
+                checkpoints.push(Checkpoint {
+                    offset: r.offset,
+                    record_num: r.record_num,
+                    first_line: r.first_line,
+                    ts: ts.as_millisecond(),
+                });
+            }
+        }
+        Ok(Index {
+            version: VERSION,
+            len: md.len(),
+            head,
+            log_pattern: log_pattern_source.to_owned(),
+            checkpoints,
+        })
+    }
+
+    pub(crate) fn checkpoints(&self) -> usize {
+        self.checkpoints.len()
+    }
+
+    /// Write the index beside its file.
+    pub(crate) fn save(&self, filename: &str) -> Result<()> {
+        let path = path_for(filename);
+        let json = serde_json::to_string(self)?;
+        fs::write(&path, json).with_context(|| format!("Failed to write index '{path}'"))
+    }
+
+    /// A file's index, if it has one which is current: built with the same
+    /// log pattern, and of the file as it was, or an earlier part of it.
+    pub(crate) fn load(filename: &str, log_pattern_source: &str) -> Result<Option<Index>> {
+        let Ok(json) = fs::read_to_string(path_for(filename)) else {
+            return Ok(None);
+        };
+        let Ok(index) = serde_json::from_str::<Index>(&json) else {
+            return Ok(None);
+        };
+        let mut file = fs::File::open(filename)?;
+        let current = index.version == VERSION
+            && index.log_pattern == log_pattern_source
+            && file.metadata()?.len() >= index.len
+            && head_hash(&mut file)? == index.head;
+        Ok(current.then_some(index))
+    }
+
+    /// The last checkpoint before a time, if there is one.
+    pub(crate) fn before(&self, ts: Timestamp) -> Option<Checkpoint> {
+        let ms = ts.as_millisecond();
+        let i = self.checkpoints.partition_point(|c| c.ts < ms);
+        i.checked_sub(1).map(|i| self.checkpoints[i])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn build_and_use() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("index.log");
+        let mut log = String::from("preamble\n");
+        for i in 0..40_000 {
+            log.push_str(&format!(
+                "2024-07-01T{:02}:{:02}:{:02}Z record {i}\n  continued\n",
+                i / 3600,
+                i / 60 % 60,
+                i % 60
+            ));
+        }
+        fs::write(&path, &log).unwrap();
+        let filename = path.to_string_lossy();
+        let log_pattern = Regex::new("^\\d").unwrap();
+        let timestamp = |text: &str| text.get(..20)?.parse().ok();
+        let index = Index::build(&filename, &log_pattern, "^\\d", timestamp).unwrap();
+        index.save(&filename).unwrap();
+        let loaded = Index::load(&filename, "^\\d").unwrap();
+        let stale = Index::load(&filename, "^2").unwrap();
+        assert_eq!(Some(&index), loaded.as_ref());
+        assert_eq!(None, stale);
+        assert!(index.checkpoints() > 1);
+        assert_eq!(None, index.before("2024-07-01T00:00:00Z".parse().unwrap()));
+        let c = index
+            .before("2024-07-01T10:00:00Z".parse().unwrap())
+            .unwrap();
+        assert!(log[c.offset as usize..].starts_with("2024-07-01T0"));
+        let i: usize = log[c.offset as usize..]
+            .split([' ', '\n'])
+            .nth(2)
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert_eq!(i + 2, c.record_num);
+        assert_eq!(i * 2 + 2, c.first_line);
+    }
+}
@@ -0,0 +1,102 @@
+use std::io::{self, BufRead, Read};
+
+type Opener = Box<dyn FnOnce() -> anyhow::Result<Box<dyn BufRead>>>;
+
+enum State {
+    Pending(Opener),
+    Open(Box<dyn BufRead>),
+    Done,
+}
+
+/// A reader which isn't opened until it's first read, and is closed as soon
+/// as it's exhausted. So a long chain of files (and their decompressor
+/// processes) only holds one open at a time.
+pub(crate) struct LazyReader {
+    state: State,
+}
+
+impl LazyReader {
+    pub(crate) fn new<F>(open: F) -> LazyReader
+    where
+        F: FnOnce() -> anyhow::Result<Box<dyn BufRead>> + 'static,
+    {
+        LazyReader {
+            state: State::Pending(Box::new(open)),
+        }
+    }
+}
+
+impl Read for LazyReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = {
+            let available = self.fill_buf()?;
+            let n = available.len().min(buf.len());
+            buf[..n].copy_from_slice(&available[..n]);
+            n
+        };
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl BufRead for LazyReader {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if let State::Pending(_) = self.state {
+            let State::Pending(open) = std::mem::replace(&mut self.state, State::Done) else {
+                unreachable!()
+            };
+            let reader = open().map_err(|e| io::Error::other(format!("{e:#}")))?;
+            self.state = State::Open(reader);
+        }
+        if let State::Open(r) = &mut self.state {
+            if r.fill_buf()?.is_empty() {
+                // exhausted, so close it
+                self.state = State::Done;
+            }
+        }
+        match &mut self.state {
+            State::Open(r) => r.fill_buf(),
+            _ => Ok(&[]),
+        }
+    }
+
+    fn consume(&mut self, amt: usize) {
+        if let State::Open(r) = &mut self.state {
+            r.consume(amt)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::Cell;
+    use std::io::Cursor;
+    use std::rc::Rc;
+
+    use super::*;
+
+    #[test]
+    fn opens_on_first_read() {
+        let opened = Rc::new(Cell::new(false));
+        let flag = opened.clone();
+        let mut r = LazyReader::new(move || {
+            flag.set(true);
+            Ok(Box::new(Cursor::new("one\ntwo\n")) as Box<dyn BufRead>)
+        });
+        assert!(!opened.get());
+        let mut text = String::new();
+        r.read_to_string(&mut text).unwrap();
+        assert!(opened.get());
+        assert_eq!("one\ntwo\n", text);
+        assert!(matches!(r.state, State::Done));
+    }
+
+    #[test]
+    fn open_errors_on_read() {
+        let mut r = LazyReader::new(|| anyhow::bail!("Failed to open 'nope'"));
+        assert_eq!(
+            "Failed to open 'nope'",
+            r.fill_buf().unwrap_err().to_string()
+        );
+    }
+}
@@ -1,3 +1,4 @@
+use std::collections::VecDeque;
 use std::io::BufRead;
 
 use regex_automata::meta::Regex;
@@ -6,24 +7,50 @@ use crate::read::records::Records;
 
 pub(crate) struct Lines {
     reader: Box<dyn BufRead>,
+    continuations: VecDeque<Box<dyn BufRead>>,
+    segment: usize,
     line_num: usize,
+    offset: u64,
     eof: bool,
 }
 
 impl Lines {
-    pub(crate) fn new(reader: Box<dyn BufRead>) -> Lines {
+    /// Lines from several readers, one after another, as if they were a
+    /// single stream. Line numbers and offsets restart with each reader
+    /// (segment).
+    pub(crate) fn chained(reader: Box<dyn BufRead>, continuations: Vec<Box<dyn BufRead>>) -> Lines {
         Lines {
             reader,
+            continuations: continuations.into(),
+            segment: 0,
             line_num: 0,
+            offset: 0,
             eof: false,
         }
     }
+
+    /// Number the lines as if they're read from a file's record, at `offset`,
+    /// which starts on line `line_num`, rather than from the file's start.
+    pub(crate) fn starting_at(self, line_num: usize, offset: u64) -> Lines {
+        Lines {
+            line_num: line_num - 1,
+            offset,
+            ..self
+        }
+    }
 }
 
 #[derive(Debug, Eq, PartialEq)]
 pub(crate) struct Line {
     pub text: String,
     pub line_num: usize,
+    pub segment: usize,
+    /// Byte offset of the line's start in its segment, after any
+    /// decompression.
+    pub offset: u64,
+    /// Whether the line ended with a newline, which only the last line of a
+    /// stream may not.
+    pub terminated: bool,
 }
 
 impl Iterator for Lines {
@@ -38,16 +65,30 @@ impl Iterator for Lines {
             Err(e) => Some(Err(e.into())),
             Ok(n) => {
                 if n == 0 {
+                    if let Some(r) = self.continuations.pop_front() {
+                        self.reader = r;
+                        self.segment += 1;
+                        self.line_num = 0;
+                        self.offset = 0;
+                        return self.next();
+                    }
                     self.eof = true;
                     return None;
                 }
-                if text.ends_with('\n') {
+                // any '\r' of a CRLF stays in the text, so it's written back out
+                let terminated = text.ends_with('\n');
+                if terminated {
                     text.pop();
                 }
                 self.line_num += 1;
+                let offset = self.offset;
+                self.offset += n as u64;
                 Some(Ok(Line {
                     text,
                     line_num: self.line_num,
+                    segment: self.segment,
+                    offset,
+                    terminated,
                 }))
             }
         }
@@ -55,7 +96,7 @@ impl Iterator for Lines {
 }
 
 impl Lines {
-    pub(crate) fn records(self, log_pattern: &Regex) -> Records {
+    pub(crate) fn records(self, log_pattern: &Regex) -> Records<'_> {
         Records::new(self, log_pattern)
     }
 }
@@ -66,13 +107,26 @@ mod test {
 
     use super::*;
 
+    impl Lines {
+        pub(crate) fn new(reader: Box<dyn BufRead>) -> Lines {
+            Self::chained(reader, Vec::new())
+        }
+    }
+
     impl Line {
         pub(crate) fn new(text: &str, line_num: usize) -> Line {
             Line {
                 text: text.to_owned(),
                 line_num,
+                segment: 0,
+                offset: 0,
+                terminated: true,
             }
         }
+
+        pub(crate) fn at(self, offset: u64) -> Line {
+            Line { offset, ..self }
+        }
     }
 
     #[test]
@@ -83,8 +137,52 @@ mod test {
         assert_eq!(
             vec![
                 Line::new("one", 1),
-                Line::new("two", 2),
-                Line::new("three", 3),
+                Line::new("two", 2).at(4),
+                Line {
+                    terminated: false,
+                    ..Line::new("three", 3).at(8)
+                },
+            ],
+            lines
+        )
+    }
+
+    #[test]
+    fn chained() {
+        let lines: Vec<_> = Lines::chained(
+            Box::new(Cursor::new("one\ntwo\n")),
+            vec![Box::new(Cursor::new("")), Box::new(Cursor::new("three"))],
+        )
+        .map(|r| r.unwrap())
+        .collect();
+        assert_eq!(
+            vec![
+                Line::new("one", 1),
+                Line::new("two", 2).at(4),
+                Line {
+                    segment: 2,
+                    terminated: false,
+                    ..Line::new("three", 1)
+                },
+            ],
+            lines
+        )
+    }
+
+    #[test]
+    fn line_endings() {
+        let lines: Vec<_> = Lines::new(Box::new(Cursor::new("one\r\ntwo\n\nthree")))
+            .map(|r| r.unwrap())
+            .collect();
+        assert_eq!(
+            vec![
+                Line::new("one\r", 1),
+                Line::new("two", 2).at(5),
+                Line::new("", 3).at(9),
+                Line {
+                    terminated: false,
+                    ..Line::new("three", 4).at(10)
+                },
             ],
             lines
         )
@@ -0,0 +1,122 @@
+use anyhow::Result;
+use jiff::Timestamp;
+
+use crate::read::records::{Record, Records};
+
+/// Whether, and when, a record has a timestamp.
+pub(crate) type Timestamper<'a> = Box<dyn Fn(&str) -> Option<Timestamp> + 'a>;
+
+/// The records of several files, interleaved in timestamp order, for
+/// '--merge'. Each record's segment is the index of its file. A record
+/// without a timestamp (e.g., before its file's first log record) takes that
+/// of the record before it in its file, so each file's records stay in their
+/// own order. Records with the same timestamp are in file order.
+pub(crate) struct Merged<'a> {
+    files: Vec<Records<'a>>,
+    /// The next record of each file, with its timestamp, once read.
+    heads: Vec<Option<(Timestamp, Record)>>,
+    last: Vec<Timestamp>,
+    timestamp: Timestamper<'a>,
+    /// The file whose head was last taken, so must be read again.
+    taken: Option<usize>,
+    primed: bool,
+}
+
+impl<'a> Merged<'a> {
+    pub(crate) fn new(files: Vec<Records<'a>>, timestamp: Timestamper<'a>) -> Merged<'a> {
+        let count = files.len();
+        Merged {
+            files,
+            heads: (0..count).map(|_| None).collect(),
+            last: vec![Timestamp::MIN; count],
+            timestamp,
+            taken: None,
+            primed: false,
+        }
+    }
+
+    fn advance(&mut self, i: usize) -> Result<()> {
+        self.heads[i] = match self.files[i].next() {
+            None => None,
+            Some(r) => {
+                let r = r?;
+                let ts = (self.timestamp)(&r.text).unwrap_or(self.last[i]);
+                self.last[i] = ts;
+                Some((ts, Record { segment: i, ..r }))
+            }
+        };
+        Ok(())
+    }
+}
+
+impl<'a> Iterator for Merged<'a> {
+    type Item = Result<Record>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if !self.primed {
+            self.primed = true;
+            for i in 0..self.files.len() {
+                if let Err(e) = self.advance(i) {
+                    return Some(Err(e));
+                }
+            }
+        }
+        if let Some(i) = self.taken.take() {
+            if let Err(e) = self.advance(i) {
+                return Some(Err(e));
+            }
+        }
+        let (_, i) = self
+            .heads
+            .iter()
+            .enumerate()
+            .filter_map(|(i, h)| h.as_ref().map(|(ts, _)| (*ts, i)))
+            .min()?;
+        self.taken = Some(i);
+        self.heads[i].take().map(|(_, r)| Ok(r))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use regex_automata::meta::Regex;
+
+    use crate::read::lines::Lines;
+
+    use super::*;
+
+    #[test]
+    fn interleaved() {
+        let log_pattern = Regex::new("^\\d").unwrap();
+        let records = |text: &'static str| {
+            Lines::chained(Box::new(Cursor::new(text)), Vec::new()).records(&log_pattern)
+        };
+        let merged = Merged::new(
+            vec![
+                records("preamble\n2024-07-01T00:00:01Z a1\n2024-07-01T00:00:03Z a2\n  more\n"),
+                records("2024-07-01T00:00:01Z b1\n2024-07-01T00:00:02Z b2\n"),
+                records("2024-07-01T00:00:00Z c1\n"),
+            ],
+            Box::new(|text| text.get(..20)?.parse().ok()),
+        );
+        let merged: Vec<_> = merged
+            .map(|r| {
+                let r = r.unwrap();
+                (r.segment, r.record_num, r.text)
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                (0, 1, "preamble".to_owned()),
+                (2, 1, "2024-07-01T00:00:00Z c1".to_owned()),
+                (0, 2, "2024-07-01T00:00:01Z a1".to_owned()),
+                (1, 1, "2024-07-01T00:00:01Z b1".to_owned()),
+                (1, 2, "2024-07-01T00:00:02Z b2".to_owned()),
+                (0, 3, "2024-07-01T00:00:03Z a2\n  more".to_owned()),
+            ],
+            merged
+        );
+    }
+}
@@ -0,0 +1,116 @@
+use std::io::{self, BufRead, ErrorKind, Read};
+use std::sync::mpsc::{sync_channel, Receiver};
+use std::thread;
+
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// How many chunks may be read ahead of the consumer.
+const CHANNEL_DEPTH: usize = 4;
+
+/// Reads a stream on its own thread, passing chunks over a bounded channel,
+/// so that (de)compression can overlap with matching. Dropping it stops the
+/// thread once its current read completes.
+pub(crate) struct ReadAhead {
+    chunks: Receiver<io::Result<Vec<u8>>>,
+    chunk: Vec<u8>,
+    pos: usize,
+}
+
+impl ReadAhead {
+    pub(crate) fn spawn<R: Read + Send + 'static>(mut reader: R) -> ReadAhead {
+        let (tx, rx) = sync_channel(CHANNEL_DEPTH);
+        thread::spawn(move || loop {
+            let mut buf = vec![0; CHUNK_SIZE];
+            let result = match reader.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => {
+                    buf.truncate(n);
+                    Ok(buf)
+                }
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => Err(e),
+            };
+            let failed = result.is_err();
+            // a closed channel means the consumer is gone
+            if tx.send(result).is_err() || failed {
+                break;
+            }
+        });
+        ReadAhead {
+            chunks: rx,
+            chunk: Vec::new(),
+            pos: 0,
+        }
+    }
+}
+
+impl Read for ReadAhead {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = {
+            let available = self.fill_buf()?;
+            let n = available.len().min(buf.len());
+            buf[..n].copy_from_slice(&available[..n]);
+            n
+        };
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl BufRead for ReadAhead {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.pos >= self.chunk.len() {
+            match self.chunks.recv() {
+                Ok(Ok(chunk)) => {
+                    self.chunk = chunk;
+                    self.pos = 0;
+                }
+                Ok(Err(e)) => return Err(e),
+                // the reader thread finished
+                Err(_) => return Ok(&[]),
+            }
+        }
+        Ok(&self.chunk[self.pos..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos = (self.pos + amt).min(self.chunk.len());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn reads_everything() {
+        let text: String = (0..50_000).map(|i| format!("line {i}\n")).collect();
+        assert!(text.len() > CHUNK_SIZE * CHANNEL_DEPTH);
+        let mut result = String::new();
+        ReadAhead::spawn(Cursor::new(text.clone().into_bytes()))
+            .read_to_string(&mut result)
+            .unwrap();
+        assert_eq!(text, result);
+        assert_eq!(
+            50_000,
+            ReadAhead::spawn(Cursor::new(text.into_bytes()))
+                .lines()
+                .count()
+        );
+    }
+
+    #[test]
+    fn passes_errors() {
+        struct Broken;
+        impl Read for Broken {
+            fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+                Err(io::Error::other("corrupt"))
+            }
+        }
+        let mut ra = ReadAhead::spawn(Broken);
+        assert_eq!("corrupt", ra.fill_buf().unwrap_err().to_string());
+        assert!(ra.fill_buf().unwrap().is_empty());
+    }
+}
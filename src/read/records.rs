@@ -1,46 +1,128 @@
 use regex_automata::meta::Regex;
 
 use crate::read::lines::{Line, Lines};
+use crate::trace::span;
 
 pub(crate) struct Records<'a> {
     lines: Lines,
     log_pattern: &'a Regex,
-    before_first_record: bool,
-    record_num: usize,
-    curr_line: Option<Line>,
+    splitter: Splitter,
 }
 
 impl<'a> Records<'a> {
-    pub(crate) fn new(lines: Lines, log_pattern: &Regex) -> Records {
+    pub(crate) fn new(lines: Lines, log_pattern: &Regex) -> Records<'_> {
         Records {
             lines,
             log_pattern,
-            before_first_record: true,
-            record_num: 0,
-            curr_line: None,
+            splitter: Splitter::default(),
         }
     }
 
-    fn advance(&mut self) -> Option<anyhow::Result<Line>> {
-        if let Some(l) = self.curr_line.take() {
-            Some(Ok(l))
-        } else {
-            self.lines.next()
-        }
+    /// Number the records from `record_num`, as the lines start with that
+    /// record of the file. See [Lines::starting_at].
+    pub(crate) fn starting_at(mut self, record_num: usize) -> Self {
+        self.splitter.record_num = record_num - 1;
+        self
+    }
+
+    /// Split records longer than `max_lines`, if set. See [Splitter].
+    pub(crate) fn with_max_lines(mut self, max_lines: Option<usize>) -> Self {
+        self.splitter.max_lines = max_lines;
+        self
     }
 }
 
-#[derive(Debug, Eq, PartialEq)]
-pub(crate) struct Record {
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Record {
     pub text: String,
     pub record_num: usize,
     pub first_line: usize,
+    /// Which of a chained source's readers the record started in. Record and
+    /// line numbers, and offsets, are all within that segment.
+    pub segment: usize,
+    /// Byte offset of the record's start in its segment, after any
+    /// decompression.
+    pub offset: u64,
+    /// Whether the record's last line ended with a newline. Lines within the
+    /// text are joined with '\n', and keep any '\r' of a CRLF.
+    pub terminated: bool,
 }
 
 impl Record {
     pub(crate) fn push_line(&mut self, line: &Line) {
+        self.push_line_text(&line.text);
+        self.terminated = line.terminated;
+    }
+
+    pub(crate) fn push_line_text(&mut self, text: &str) {
         self.text.push('\n');
-        self.text.push_str(&line.text);
+        self.text.push_str(text);
+    }
+}
+
+/// Assembles lines into records, one line at a time, so it works the same
+/// whether the lines are read synchronously or not. A line matching the log
+/// pattern starts a record, and others are added to the current one, except
+/// before the first match, where every line is its own record. With a
+/// maximum, a line which would make a record longer starts another instead.
+#[derive(Debug, Default)]
+pub(crate) struct Splitter {
+    after_first_record: bool,
+    record_num: usize,
+    segment: usize,
+    curr: Option<Record>,
+    /// Lines in the current record.
+    lines: usize,
+    max_lines: Option<usize>,
+}
+
+impl Splitter {
+    pub(crate) fn with_max_lines(max_lines: Option<usize>) -> Splitter {
+        Splitter {
+            max_lines,
+            ..Splitter::default()
+        }
+    }
+
+    /// Start over, as for another file, keeping any maximum.
+    pub(crate) fn reset(&mut self) {
+        *self = Splitter::with_max_lines(self.max_lines);
+    }
+
+    /// Add a line, returning the record it completed, if any.
+    pub(crate) fn push(&mut self, line: Line, log_pattern: &Regex) -> Option<Record> {
+        let starts = log_pattern.is_match(&line.text);
+        let full = self.max_lines.is_some_and(|n| self.lines >= n);
+        if let Some(r) = &mut self.curr {
+            if !starts && self.after_first_record && !full {
+                r.push_line(&line);
+                self.lines += 1;
+                return None;
+            }
+        }
+        if starts {
+            self.after_first_record = true;
+        }
+        if line.segment != self.segment {
+            // like line numbers, record numbers restart with each file
+            self.segment = line.segment;
+            self.record_num = 0;
+        }
+        self.record_num += 1;
+        self.lines = 1;
+        self.curr.replace(Record {
+            record_num: self.record_num,
+            first_line: line.line_num,
+            segment: line.segment,
+            offset: line.offset,
+            terminated: line.terminated,
+            text: line.text,
+        })
+    }
+
+    /// The last record, once there are no more lines.
+    pub(crate) fn finish(&mut self) -> Option<Record> {
+        self.curr.take()
     }
 }
 
@@ -48,46 +130,20 @@ impl<'a> Iterator for Records<'a> {
     type Item = anyhow::Result<Record>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let mut record = match self.advance() {
-            None => {
-                return None;
-            }
-            Some(Err(e)) => {
-                return Some(Err(e));
-            }
-            Some(Ok(l)) => {
-                self.record_num += 1;
-                if self.log_pattern.is_match(&l.text) {
-                    self.before_first_record = false;
-                }
-                Record {
-                    record_num: self.record_num,
-                    first_line: l.line_num,
-                    text: l.text,
-                }
-            }
-        };
+        let _span = span!(TRACE, "record");
         for line in self.lines.by_ref() {
             match line {
                 Err(e) => {
                     return Some(Err(e));
                 }
                 Ok(l) => {
-                    if self.log_pattern.is_match(&l.text) {
-                        self.before_first_record = false;
-                        let _ = self.curr_line.insert(l);
-                        break;
-                    } else if self.before_first_record {
-                        let _ = self.curr_line.insert(l);
-                        break;
-                    } else {
-                        // add line to the current record
-                        record.push_line(&l);
+                    if let Some(r) = self.splitter.push(l, self.log_pattern) {
+                        return Some(Ok(r));
                     }
                 }
             }
         }
-        Some(Ok(record))
+        self.splitter.finish().map(Ok)
     }
 }
 
@@ -103,8 +159,15 @@ mod test {
                 text: text.to_owned(),
                 record_num,
                 first_line,
+                segment: 0,
+                offset: 0,
+                terminated: true,
             }
         }
+
+        pub(crate) fn at(self, offset: u64) -> Record {
+            Record { offset, ..self }
+        }
     }
 
     fn to_records(text: &'static str, re: &Regex) -> Vec<Record> {
@@ -120,8 +183,11 @@ mod test {
         assert_eq!(
             vec![
                 Record::new("one\nzzzz", 1, 1),
-                Record::new("two\nthree", 2, 3),
-                Record::new("four\nfive", 3, 5),
+                Record::new("two\nthree", 2, 3).at(9),
+                Record {
+                    terminated: false,
+                    ..Record::new("four\nfive", 3, 5).at(19)
+                },
             ],
             to_records(
                 "one\nzzzz
@@ -132,6 +198,62 @@ four\nfive",
         )
     }
 
+    #[test]
+    fn max_lines() {
+        let re = Regex::new("o").unwrap();
+        let records = Lines::new(Box::new(Cursor::new("one\na\nb\nc\nd\ntwo\ne\n")))
+            .records(&re)
+            .with_max_lines(Some(2))
+            .map(|r| r.unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                Record::new("one\na", 1, 1),
+                Record::new("b\nc", 2, 3).at(6),
+                Record::new("d", 3, 5).at(10),
+                Record::new("two\ne", 4, 6).at(12),
+            ],
+            records
+        )
+    }
+
+    #[test]
+    fn chained_record_spans_segments() {
+        let re = Regex::new(r"LOG").unwrap();
+        let records = Lines::chained(
+            Box::new(Cursor::new("LOG: one\nmore")),
+            vec![Box::new(Cursor::new("yet more\nLOG: two\n"))],
+        )
+        .records(&re)
+        .map(|r| r.unwrap())
+        .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                Record::new("LOG: one\nmore\nyet more", 1, 1),
+                Record {
+                    segment: 1,
+                    ..Record::new("LOG: two", 1, 2).at(9)
+                },
+            ],
+            records
+        )
+    }
+
+    #[test]
+    fn line_endings() {
+        let re = Regex::new(r"LOG").unwrap();
+        assert_eq!(
+            vec![
+                Record::new("LOG: one\r\nmore\r", 1, 1),
+                Record {
+                    terminated: false,
+                    ..Record::new("LOG: two\n\nlast", 2, 3).at(16)
+                },
+            ],
+            to_records("LOG: one\r\nmore\r\nLOG: two\n\nlast", &re)
+        )
+    }
+
     #[test]
     fn before_first_log_record() {
         // before the first log record boundary, treat every line as its own record
@@ -139,9 +261,9 @@ four\nfive",
         assert_eq!(
             vec![
                 Record::new("one, thee father", 1, 1),
-                Record::new("two, thee mother", 2, 2),
-                Record::new("LOG: three\nfour", 3, 3),
-                Record::new("LOG: five\nsix", 4, 5),
+                Record::new("two, thee mother", 2, 2).at(17),
+                Record::new("LOG: three\nfour", 3, 3).at(34),
+                Record::new("LOG: five\nsix", 4, 5).at(50),
             ],
             to_records(
                 "one, thee father
@@ -0,0 +1,24 @@
+use std::io::BufRead;
+use std::process::{Command, Stdio};
+
+use anyhow::{Context, Result};
+
+use crate::read::decompress::{decompress, open_stream, ChildOutput, Format};
+
+/// Stream an S3 object, via the AWS CLI, so the standard AWS environment
+/// (credentials, profile, region, etc.) applies. Compressed objects are
+/// decompressed, per their key's extension, or else their content.
+pub(crate) fn open_object(url: &str) -> Result<Box<dyn BufRead + Send>> {
+    let mut child = Command::new("aws")
+        .args(["s3", "cp", "--quiet", url, "-"])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .spawn()
+        .context("Failed to run the AWS CLI ('aws'); is it on your $PATH?")?;
+    let stdout = child.stdout.take().unwrap();
+    let object = ChildOutput::new(child, stdout, "aws s3 cp");
+    match Format::for_filename(url) {
+        Some(f) => decompress(f, object),
+        None => open_stream(object),
+    }
+}
@@ -0,0 +1,112 @@
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom};
+
+/// Below this many bytes, it's cheaper to read through than to keep probing.
+const LINEAR_SCAN: u64 = 64 * 1024;
+
+/// Binary search a sorted log of `len` bytes for where to start reading to
+/// find its records from some point on, instead of reading its whole head.
+///
+/// `is_before` is asked about lines, and says whether one starts a record
+/// before the point, or `None` if it doesn't start a record with a position
+/// (e.g., it's a stack trace frame, or has no timestamp). The result is the
+/// offset of the last record found to be before the point, or zero, so every
+/// record from the point on is after it, as long as the log is in order. A
+/// few records before the point may still be read.
+pub(crate) fn find_start<R: Read + Seek>(
+    reader: &mut R,
+    len: u64,
+    is_before: impl Fn(&str) -> Option<bool>,
+) -> io::Result<u64> {
+    let (mut lo, mut hi) = (0, len);
+    while hi - lo > LINEAR_SCAN {
+        let mid = lo + (hi - lo) / 2;
+        match first_record_after(reader, mid, hi, &is_before)? {
+            Some((offset, true)) => lo = offset,
+            // either way, the point is before mid, or after lo
+            Some((_, false)) | None => hi = mid,
+        }
+    }
+    Ok(lo)
+}
+
+/// The offsets of records about evenly spaced through a file of `len` bytes,
+/// which split it into up to `count` parts. `starts_record` says whether a
+/// line starts one.
+pub(crate) fn record_starts<R: Read + Seek>(
+    reader: &mut R,
+    len: u64,
+    count: usize,
+    starts_record: impl Fn(&str) -> bool,
+) -> io::Result<Vec<u64>> {
+    let is_start = |line: &str| starts_record(line).then_some(true);
+    let mut starts: Vec<u64> = Vec::with_capacity(count.saturating_sub(1));
+    for i in 1..count as u64 {
+        let from = (len / count as u64 * i).max(starts.last().map_or(0, |&s| s));
+        match first_record_after(reader, from, len, &is_start)? {
+            Some((offset, _)) => starts.push(offset),
+            None => break,
+        }
+    }
+    Ok(starts)
+}
+
+/// The offset of the first record starting after `from` (and before `to`),
+/// and whether it's before the point.
+fn first_record_after<R: Read + Seek>(
+    reader: &mut R,
+    from: u64,
+    to: u64,
+    is_before: &impl Fn(&str) -> Option<bool>,
+) -> io::Result<Option<(u64, bool)>> {
+    reader.seek(SeekFrom::Start(from))?;
+    let mut reader = BufReader::new(reader);
+    let mut line = Vec::new();
+    // the rest of the line `from` is within
+    let mut offset = from + reader.read_until(b'\n', &mut line)? as u64;
+    while offset < to {
+        line.clear();
+        let n = reader.read_until(b'\n', &mut line)?;
+        if n == 0 {
+            break;
+        }
+        if let Some(before) = is_before(&String::from_utf8_lossy(&line)) {
+            return Ok(Some((offset, before)));
+        }
+        offset += n as u64;
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn binary_search() {
+        let mut log = String::new();
+        for i in 0..20_000 {
+            log.push_str(&format!("#{i:05} record\n  continued\n"));
+        }
+        let is_before = |n: usize| {
+            move |line: &str| {
+                line.strip_prefix('#')
+                    .and_then(|l| l.get(..5))
+                    .map(|i| i.parse::<usize>().unwrap() < n)
+            }
+        };
+        let len = log.len() as u64;
+        let mut reader = Cursor::new(log.as_bytes());
+        for n in [0, 1, 5_000, 12_345, 19_999, 20_000] {
+            let offset = find_start(&mut reader, len, is_before(n)).unwrap();
+            let rest = &log[offset as usize..];
+            // at a record before the point, or the start
+            assert!(offset == 0 || is_before(n)(rest) == Some(true), "{n}");
+            // not too far before it
+            let needle = format!("#{n:05}");
+            let to_point = rest.find(&needle).unwrap_or(rest.len());
+            assert!(to_point as u64 <= LINEAR_SCAN, "{n}: {to_point}");
+        }
+    }
+}
@@ -0,0 +1,159 @@
+use std::fs::File;
+use std::io::{self, BufRead, Read, Seek, SeekFrom};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// A file as it was when opened, even if it's an active log that grows while
+/// it's read. Reading stops at the size it had when opened. If it has grown by
+/// then, any partial line at that point is a torn write, and is dropped,
+/// rather than being searched as if it were complete.
+pub(crate) struct Snapshot {
+    file: File,
+    size: u64,
+    /// Bytes of the snapshot not yet read from the file.
+    remaining: u64,
+    buf: Vec<u8>,
+    pos: usize,
+    /// Whether the snapshot is exhausted, so what's buffered is all there is.
+    done: bool,
+    grew: Arc<AtomicBool>,
+}
+
+impl Snapshot {
+    /// Read `file`, which is `size` bytes now, and set `grew` if it turns out
+    /// to be longer by the time that's all been read.
+    pub(crate) fn new(file: File, size: u64, grew: Arc<AtomicBool>) -> Snapshot {
+        Snapshot {
+            file,
+            size,
+            remaining: size,
+            buf: Vec::new(),
+            pos: 0,
+            done: false,
+            grew,
+        }
+    }
+
+    /// Read `file`, which is `size` bytes now, from `offset` on.
+    pub(crate) fn at(
+        mut file: File,
+        offset: u64,
+        size: u64,
+        grew: Arc<AtomicBool>,
+    ) -> io::Result<Snapshot> {
+        let offset = file.seek(SeekFrom::Start(offset.min(size)))?;
+        Ok(Snapshot {
+            remaining: size - offset,
+            ..Snapshot::new(file, size, grew)
+        })
+    }
+
+    /// Read another chunk, returning whether anything was read.
+    fn read_chunk(&mut self) -> io::Result<bool> {
+        if self.pos > 0 {
+            self.buf.drain(..self.pos);
+            self.pos = 0;
+        }
+        let want = CHUNK_SIZE.min(self.remaining as usize);
+        let start = self.buf.len();
+        self.buf.resize(start + want, 0);
+        let n = self.file.read(&mut self.buf[start..])?;
+        self.buf.truncate(start + n);
+        self.remaining -= n as u64;
+        Ok(n > 0)
+    }
+}
+
+impl BufRead for Snapshot {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        while !self.done {
+            // only hand out whole lines, until it's known whether a trailing
+            // partial line is the file's last line, or a torn one.
+            if let Some(i) = self.buf[self.pos..].iter().rposition(|&b| b == b'\n') {
+                return Ok(&self.buf[self.pos..self.pos + i + 1]);
+            }
+            if self.remaining == 0 || !self.read_chunk()? {
+                self.done = true;
+                if self.file.metadata()?.len() > self.size {
+                    self.grew.store(true, Ordering::Relaxed);
+                    // torn
+                    self.pos = self.buf.len();
+                }
+            }
+        }
+        Ok(&self.buf[self.pos..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos += amt;
+    }
+}
+
+impl Read for Snapshot {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = {
+            let available = self.fill_buf()?;
+            let n = available.len().min(buf.len());
+            buf[..n].copy_from_slice(&available[..n]);
+            n
+        };
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs::{self, OpenOptions};
+    use std::io::Write;
+
+    use super::*;
+
+    fn snapshot_of(name: &str, text: &str, appended: &str) -> (String, bool) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(format!("{name}.log"));
+        fs::write(&path, text).unwrap();
+        let file = File::open(&path).unwrap();
+        let size = file.metadata().unwrap().len();
+        let grew = Arc::new(AtomicBool::new(false));
+        let mut snapshot = Snapshot::new(file, size, grew.clone());
+        // a writer appends, after it was opened
+        let mut first = String::new();
+        snapshot.read_line(&mut first).unwrap();
+        OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(appended.as_bytes())
+            .unwrap();
+        let mut rest = String::new();
+        snapshot.read_to_string(&mut rest).unwrap();
+        (first + &rest, grew.load(Ordering::Relaxed))
+    }
+
+    #[test]
+    fn unchanged() {
+        assert_eq!(
+            ("one\ntwo".to_owned(), false),
+            snapshot_of("unchanged", "one\ntwo", "")
+        );
+    }
+
+    #[test]
+    fn grown() {
+        assert_eq!(
+            ("one\ntwo\n".to_owned(), true),
+            snapshot_of("grown", "one\ntwo\n", "three\n")
+        );
+    }
+
+    #[test]
+    fn torn() {
+        assert_eq!(
+            ("one\n".to_owned(), true),
+            snapshot_of("torn", "one\ntw", "o\nthree\n")
+        );
+    }
+}
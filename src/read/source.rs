@@ -1,25 +1,129 @@
-use std::io::BufRead;
+use std::io::{BufRead, Read};
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 
 use regex_automata::meta::Regex;
 
+use crate::read::delimited::DelimitedRecords;
+use crate::read::follow::FollowRecords;
+use crate::read::index::Checkpoint;
 use crate::read::lines::Lines;
+use crate::read::merge::{Merged, Timestamper};
 use crate::read::records::Records;
 
 pub(crate) struct Source<'a> {
     pub filename: &'a str,
+    pub modified: Option<SystemTime>,
+    /// Set if any of the source's files grew while being read.
+    pub grew: Arc<AtomicBool>,
     reader: Box<dyn BufRead>,
+    continuations: Vec<(&'a str, Box<dyn BufRead>)>,
+    /// The record the reader starts at, if not the start of the file.
+    start: Option<Checkpoint>,
 }
 
 impl<'a> Source<'a> {
-    pub(crate) fn new(filename: &str, reader: Box<dyn BufRead>) -> Source {
-        Source { filename, reader }
+    pub(crate) fn new(filename: &str, reader: Box<dyn BufRead>) -> Source<'_> {
+        Source {
+            filename,
+            modified: None,
+            grew: Arc::default(),
+            reader,
+            continuations: Vec::new(),
+            start: None,
+        }
+    }
+
+    /// Continue this source with another file, as if it were appended. A
+    /// record which starts in one file can continue into the next.
+    pub(crate) fn continued_by(mut self, filename: &'a str, reader: Box<dyn BufRead>) -> Self {
+        self.continuations.push((filename, reader));
+        self
+    }
+
+    /// The name of each file in the source, indexed by [Record] segment.
+    ///
+    /// [Record]: crate::read::records::Record
+    pub(crate) fn filenames(&self) -> Vec<&'a str> {
+        let mut names = vec![self.filename];
+        names.extend(self.continuations.iter().map(|(n, _)| *n));
+        names
+    }
+
+    pub(crate) fn with_growth(self, grew: Arc<AtomicBool>) -> Self {
+        Source { grew, ..self }
+    }
+
+    pub(crate) fn with_modified(self, modified: Option<SystemTime>) -> Self {
+        Source { modified, ..self }
+    }
+
+    /// Start numbering records and lines from an index's checkpoint, which
+    /// the reader starts at.
+    pub(crate) fn starting_at(self, start: Checkpoint) -> Self {
+        Source {
+            start: Some(start),
+            ..self
+        }
     }
 
     pub(crate) fn lines(self) -> Lines {
-        Lines::new(self.reader)
+        Lines::chained(
+            self.reader,
+            self.continuations.into_iter().map(|(_, r)| r).collect(),
+        )
+    }
+
+    pub(crate) fn records(self, log_pattern: &Regex) -> Records<'_> {
+        match self.start {
+            Some(c) => self
+                .lines()
+                .starting_at(c.first_line, c.offset)
+                .records(log_pattern)
+                .starting_at(c.record_num),
+            None => self.lines().records(log_pattern),
+        }
+    }
+
+    /// Records of each of the source's files, interleaved by timestamp,
+    /// rather than one file after the next. See [Merged].
+    pub(crate) fn merged_records(
+        self,
+        log_pattern: &'a Regex,
+        max_lines: Option<usize>,
+        timestamp: Timestamper<'a>,
+    ) -> Merged<'a> {
+        let files = std::iter::once(self.reader)
+            .chain(self.continuations.into_iter().map(|(_, r)| r))
+            .map(|r| {
+                Lines::chained(r, Vec::new())
+                    .records(log_pattern)
+                    .with_max_lines(max_lines)
+            })
+            .collect();
+        Merged::new(files, timestamp)
+    }
+
+    /// Records of a file which is still being written, which never end. See
+    /// [FollowRecords]. The filename must be the file's path, as it may be
+    /// reopened by it. Continuations aren't followed.
+    pub(crate) fn follow_records(
+        self,
+        log_pattern: &'a Regex,
+        flush_after: Duration,
+    ) -> FollowRecords<'a> {
+        FollowRecords::new(self.reader, self.filename, log_pattern, flush_after)
     }
 
-    pub(crate) fn records(self, log_pattern: &Regex) -> Records {
-        self.lines().records(log_pattern)
+    /// Records separated by a byte sequence, instead of by log pattern. Any
+    /// continuations are simply appended, so all records are in the first
+    /// segment.
+    pub(crate) fn delimited_records(self, delimiter: &[u8]) -> DelimitedRecords {
+        let reader = self
+            .continuations
+            .into_iter()
+            .fold(self.reader, |r, (_, c)| Box::new(r.chain(c)));
+        DelimitedRecords::new(reader, delimiter)
     }
 }
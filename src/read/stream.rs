@@ -0,0 +1,134 @@
+use std::io;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+
+use futures_core::Stream;
+use futures_io::AsyncBufRead;
+use regex_automata::meta::Regex;
+
+use crate::read::lines::Line;
+use crate::read::records::{Record, Splitter};
+
+/// The records of an async reader, split by a log pattern exactly as lgrep
+/// splits them, without blocking a thread while waiting for input.
+pub struct RecordStream<R> {
+    reader: R,
+    log_pattern: Regex,
+    splitter: Splitter,
+    /// The line being read, which may take several polls.
+    buf: Vec<u8>,
+    line_num: usize,
+    offset: u64,
+    eof: bool,
+}
+
+impl<R: AsyncBufRead + Unpin> RecordStream<R> {
+    /// Stream records from `reader`, each starting with a line which matches
+    /// `log_pattern`.
+    pub fn new(reader: R, log_pattern: &str) -> anyhow::Result<RecordStream<R>> {
+        Ok(RecordStream {
+            reader,
+            log_pattern: Regex::new(log_pattern)?,
+            splitter: Splitter::default(),
+            buf: Vec::new(),
+            line_num: 0,
+            offset: 0,
+            eof: false,
+        })
+    }
+
+    /// Read the rest of the current line, returning `None` at EOF.
+    fn poll_line(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<Option<Line>>> {
+        loop {
+            let available = ready!(Pin::new(&mut self.reader).poll_fill_buf(cx))?;
+            if available.is_empty() {
+                break;
+            }
+            let (n, done) = match available.iter().position(|&b| b == b'\n') {
+                Some(i) => (i + 1, true),
+                None => (available.len(), false),
+            };
+            self.buf.extend_from_slice(&available[..n]);
+            Pin::new(&mut self.reader).consume(n);
+            if done {
+                break;
+            }
+        }
+        if self.buf.is_empty() {
+            return Poll::Ready(Ok(None));
+        }
+        let n = self.buf.len();
+        let terminated = self.buf.ends_with(b"\n");
+        if terminated {
+            self.buf.pop();
+        }
+        let text = String::from_utf8(std::mem::take(&mut self.buf))
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        self.line_num += 1;
+        let line = Line {
+            text,
+            line_num: self.line_num,
+            segment: 0,
+            offset: self.offset,
+            terminated,
+        };
+        self.offset += n as u64;
+        Poll::Ready(Ok(Some(line)))
+    }
+}
+
+impl<R: AsyncBufRead + Unpin> Stream for RecordStream<R> {
+    type Item = anyhow::Result<Record>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        while !this.eof {
+            match ready!(this.poll_line(cx)) {
+                Err(e) => return Poll::Ready(Some(Err(e.into()))),
+                Ok(None) => this.eof = true,
+                Ok(Some(l)) => {
+                    if let Some(r) = this.splitter.push(l, &this.log_pattern) {
+                        return Poll::Ready(Some(Ok(r)));
+                    }
+                }
+            }
+        }
+        Poll::Ready(this.splitter.finish().map(Ok))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::task::Waker;
+
+    use super::*;
+
+    fn to_records(text: &'static str, log_pattern: &str) -> Vec<Record> {
+        let mut stream = RecordStream::new(text.as_bytes(), log_pattern).unwrap();
+        let mut cx = Context::from_waker(Waker::noop());
+        let mut records = Vec::new();
+        // a slice is always ready, so this never spins
+        while let Poll::Ready(Some(r)) = Pin::new(&mut stream).poll_next(&mut cx) {
+            records.push(r.unwrap());
+        }
+        records
+    }
+
+    #[test]
+    fn like_records() {
+        assert_eq!(
+            vec![
+                Record::new("one, thee father", 1, 1),
+                Record::new("LOG: two\nthree", 2, 2).at(17),
+                Record {
+                    terminated: false,
+                    ..Record::new("LOG: four\r\nfive", 3, 4).at(32)
+                },
+            ],
+            to_records(
+                "one, thee father\nLOG: two\nthree\nLOG: four\r\nfive",
+                "LOG"
+            )
+        );
+    }
+}
@@ -0,0 +1,241 @@
+use std::cell::{Cell, RefCell};
+use std::io::{self, BufRead, BufReader, Read};
+use std::rc::Rc;
+
+use anyhow::{bail, Context, Result};
+
+use crate::read::decompress::Format;
+
+const BLOCK: usize = 512;
+
+/// Whether a file is a tar archive, per its name, compressed or not. E.g.,
+/// `bundle.tar`, `bundle.tar.gz`, or `bundle.tgz`.
+pub(crate) fn is_archive(filename: &str) -> bool {
+    let name = Format::for_filename(filename)
+        .and_then(|f| filename.strip_suffix(f.extension()))
+        .unwrap_or(filename);
+    name.ends_with(".tar") || filename.ends_with(".tgz")
+}
+
+/// The regular files of a tar archive, read one after another from its
+/// (decompressed) stream. Directories, links, and such are skipped.
+pub(crate) struct Archive {
+    reader: Rc<RefCell<Box<dyn BufRead>>>,
+    /// Bytes left of the current member, which are skipped (along with its
+    /// padding) before the next member's header.
+    remaining: Rc<Cell<u64>>,
+    padding: u64,
+}
+
+impl Archive {
+    pub(crate) fn new(reader: Box<dyn BufRead>) -> Archive {
+        Archive {
+            reader: Rc::new(RefCell::new(reader)),
+            remaining: Rc::default(),
+            padding: 0,
+        }
+    }
+
+    /// The next regular file's name and content, if there is one. Its
+    /// content must be read (or dropped) before the next is asked for.
+    pub(crate) fn next_member(&mut self) -> Result<Option<(String, Box<dyn BufRead>)>> {
+        let mut long_name = None;
+        loop {
+            self.skip(self.remaining.get() + self.padding)?;
+            self.remaining.set(0);
+            let mut header = [0; BLOCK];
+            match self.reader.borrow_mut().read_exact(&mut header) {
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+                r => r.context("Failed to read a tar header")?,
+            }
+            if header.iter().all(|&b| b == 0) {
+                return Ok(None);
+            }
+            if Some(checksum(&header)) != octal(&header[148..156]) {
+                bail!("Not a tar archive (or a corrupt one)");
+            }
+            let size = size(&header[124..136]).context("Invalid tar member size")?;
+            self.padding = (BLOCK as u64 - size % BLOCK as u64) % BLOCK as u64;
+            match header[156] {
+                b'0' | 0 => {
+                    let name = long_name.take().unwrap_or_else(|| header_name(&header));
+                    self.remaining.set(size);
+                    let member = Member {
+                        reader: self.reader.clone(),
+                        remaining: self.remaining.clone(),
+                    };
+                    return Ok(Some((name, Box::new(BufReader::new(member)))));
+                }
+                // GNU long name, for the next member
+                b'L' => long_name = Some(self.read_string(size)?),
+                // pax extended header, for the next member
+                b'x' => {
+                    let pax = self.read_string(size)?;
+                    if let Some(path) = pax_path(&pax) {
+                        long_name = Some(path.to_owned());
+                    }
+                }
+                _ => self.remaining.set(size),
+            }
+        }
+    }
+
+    fn read_string(&mut self, size: u64) -> Result<String> {
+        let mut bytes = Vec::with_capacity(size as usize);
+        (&mut *self.reader.borrow_mut())
+            .take(size)
+            .read_to_end(&mut bytes)?;
+        let s = String::from_utf8_lossy(&bytes);
+        Ok(s.trim_end_matches('\0').to_owned())
+    }
+
+    fn skip(&mut self, n: u64) -> Result<()> {
+        let skipped = io::copy(
+            &mut (&mut *self.reader.borrow_mut()).take(n),
+            &mut io::sink(),
+        )?;
+        if skipped < n {
+            bail!("Truncated tar archive");
+        }
+        Ok(())
+    }
+}
+
+/// A member's content, read from its archive's stream.
+struct Member {
+    reader: Rc<RefCell<Box<dyn BufRead>>>,
+    remaining: Rc<Cell<u64>>,
+}
+
+impl Read for Member {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let max = buf.len().min(self.remaining.get() as usize);
+        if max == 0 {
+            return Ok(0);
+        }
+        let n = self.reader.borrow_mut().read(&mut buf[..max])?;
+        if n == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        self.remaining.set(self.remaining.get() - n as u64);
+        Ok(n)
+    }
+}
+
+/// The name of a member, with a ustar header's prefix, if it has one.
+fn header_name(header: &[u8; BLOCK]) -> String {
+    let name = c_string(&header[..100]);
+    let is_ustar = &header[257..262] == b"ustar";
+    match c_string(&header[345..500]) {
+        prefix if is_ustar && !prefix.is_empty() => format!("{prefix}/{name}"),
+        _ => name,
+    }
+}
+
+fn c_string(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).into_owned()
+}
+
+fn octal(field: &[u8]) -> Option<u64> {
+    let s = c_string(field);
+    u64::from_str_radix(s.trim(), 8).ok()
+}
+
+/// A size field, which is octal, or base-256 (for huge members) if its high
+/// bit is set.
+fn size(field: &[u8]) -> Option<u64> {
+    if field[0] & 0x80 == 0 {
+        return octal(field);
+    }
+    let mut n = (field[0] & 0x7f) as u64;
+    for &b in &field[1..] {
+        n = n.checked_mul(256)? + b as u64;
+    }
+    Some(n)
+}
+
+/// The sum of a header's bytes, with its checksum field as spaces.
+fn checksum(header: &[u8; BLOCK]) -> u64 {
+    header
+        .iter()
+        .enumerate()
+        .map(|(i, &b)| if (148..156).contains(&i) { b' ' } else { b } as u64)
+        .sum()
+}
+
+/// The `path` of pax extended header records, like `27 path=some/long/name\n`.
+fn pax_path(pax: &str) -> Option<&str> {
+    pax.lines()
+        .filter_map(|l| l.split_once(' ').map(|(_, kv)| kv))
+        .find_map(|kv| kv.strip_prefix("path="))
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use std::io::Cursor;
+
+    use super::*;
+
+    /// A tar archive of regular files, ending in two zero blocks.
+    pub(crate) fn tar(members: &[(&str, &str)]) -> Vec<u8> {
+        let mut tar = Vec::new();
+        for (name, content) in members {
+            let mut header = [0; BLOCK];
+            header[..name.len()].copy_from_slice(name.as_bytes());
+            header[100..107].copy_from_slice(b"0000644");
+            let size = format!("{:011o}", content.len());
+            header[124..135].copy_from_slice(size.as_bytes());
+            header[156] = b'0';
+            header[257..263].copy_from_slice(b"ustar\0");
+            let sum = format!("{:06o}\0 ", checksum(&header));
+            header[148..156].copy_from_slice(sum.as_bytes());
+            tar.extend_from_slice(&header);
+            tar.extend_from_slice(content.as_bytes());
+            tar.resize(tar.len().div_ceil(BLOCK) * BLOCK, 0);
+        }
+        tar.resize(tar.len() + 2 * BLOCK, 0);
+        tar
+    }
+
+    #[test]
+    fn archives() {
+        assert!(is_archive("bundle.tar"));
+        assert!(is_archive("bundle.tar.gz"));
+        assert!(is_archive("bundle.tgz"));
+        assert!(!is_archive("app.log.gz"));
+    }
+
+    #[test]
+    fn members() {
+        let bytes = tar(&[("a.log", "one\ntwo\n"), ("logs/b.log", "three\n")]);
+        let mut archive = Archive::new(Box::new(Cursor::new(bytes)));
+        let (name, mut content) = archive.next_member().unwrap().unwrap();
+        assert_eq!("a.log", name);
+        let mut first = String::new();
+        content.read_line(&mut first).unwrap();
+        assert_eq!("one\n", first);
+        // the rest of a.log is skipped
+        let (name, mut content) = archive.next_member().unwrap().unwrap();
+        assert_eq!("logs/b.log", name);
+        let mut text = String::new();
+        content.read_to_string(&mut text).unwrap();
+        assert_eq!("three\n", text);
+        assert!(archive.next_member().unwrap().is_none());
+    }
+
+    #[test]
+    fn not_tar() {
+        let mut archive = Archive::new(Box::new(Cursor::new(vec![b'x'; BLOCK])));
+        assert!(archive.next_member().is_err());
+    }
+
+    #[test]
+    fn pax_paths() {
+        assert_eq!(
+            Some("some/long/name"),
+            pax_path("20 mtime=1720000000\n27 path=some/long/name\n")
+        );
+        assert_eq!(None, pax_path("20 mtime=1720000000\n"));
+    }
+}
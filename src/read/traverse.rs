@@ -0,0 +1,469 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::SystemTime;
+
+use anyhow::{Context, Result};
+use jiff::Timestamp;
+use regex_automata::meta::Regex;
+
+use crate::read::index;
+use crate::read::{is_dir, Directories};
+
+/// How directories among the files to search are expanded.
+#[derive(Debug)]
+pub(crate) struct Traversal {
+    pub(crate) action: Directories,
+    /// When recursing, only search a file reached by several paths once.
+    pub(crate) dedupe: bool,
+    pub(crate) filter: FileFilter,
+}
+
+impl Traversal {
+    /// Replace any directories among the filenames, per `action`. Recursion
+    /// yields the files within that pass the filter, in name order, without
+    /// following symlinks. With `dedupe`, recursion also drops files already
+    /// reached by another path.
+    pub(crate) fn expand(&self, filenames: Vec<String>) -> Result<Vec<String>> {
+        let mut expanded = self.expand_dirs(filenames)?;
+        expanded.retain(|f| self.filter.modified_within(f));
+        Ok(expanded)
+    }
+
+    fn expand_dirs(&self, filenames: Vec<String>) -> Result<Vec<String>> {
+        if self.action == Directories::Read {
+            return Ok(filenames);
+        }
+        let mut expanded = Vec::with_capacity(filenames.len());
+        for f in filenames {
+            if !is_dir(&f) {
+                expanded.push(f);
+            } else if self.action == Directories::Recurse {
+                self.walk(Path::new(&f), &mut expanded)?;
+            } else {
+                eprintln!("lgrep: '{f}': Is a directory; skipping");
+            }
+        }
+        if self.dedupe && self.action == Directories::Recurse {
+            let mut seen = HashSet::new();
+            expanded.retain(|f| file_id(f).is_none_or(|id| seen.insert(id)));
+        }
+        Ok(expanded)
+    }
+
+    fn walk(&self, dir: &Path, filenames: &mut Vec<String>) -> Result<()> {
+        let mut entries = fs::read_dir(dir)
+            .and_then(|rd| rd.collect::<std::io::Result<Vec<_>>>())
+            .with_context(|| format!("Failed to list '{}'", dir.display()))?;
+        entries.sort_by_key(|e| e.file_name());
+        for e in entries {
+            let file_type = e.file_type()?;
+            // skip the "./" of the implicit current directory
+            let path = e.path();
+            let path = path.strip_prefix(".").unwrap_or(&path);
+            if file_type.is_dir() {
+                if self.filter.descends(&path.to_string_lossy()) {
+                    self.walk(path, filenames)?;
+                }
+            } else if file_type.is_file() {
+                let path = path.to_string_lossy();
+                if self.filter.accepts(&path) && !index::is_index(&path) {
+                    filenames.push(path.into_owned());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// What files are sorted by, for '--sort-files'.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum SortKey {
+    Name,
+    Mtime,
+    Size,
+}
+
+/// The order files are searched (and reported) in, for '--sort-files'.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) struct FileOrder {
+    pub(crate) key: SortKey,
+    pub(crate) descending: bool,
+}
+
+impl FromStr for FileOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (key, descending) = match s.split_once(':') {
+            Some((k, "desc")) => (k, true),
+            Some((k, "asc")) => (k, false),
+            Some(_) => {
+                return Err(format!(
+                    "'{s}' is not an order, like 'mtime' or 'size:desc'"
+                ))
+            }
+            None => (s, false),
+        };
+        let key = match key {
+            "name" => SortKey::Name,
+            "mtime" => SortKey::Mtime,
+            "size" => SortKey::Size,
+            _ => return Err(format!("'{key}' is not a sort key: name, mtime, or size")),
+        };
+        Ok(FileOrder { key, descending })
+    }
+}
+
+impl FileOrder {
+    /// Sort files, keeping the order of those which tie. Those with no
+    /// modification time or size (e.g., STDIN) go last, either way.
+    pub(crate) fn sort(&self, filenames: Vec<String>) -> Vec<String> {
+        let mut keyed: Vec<_> = filenames
+            .into_iter()
+            .map(|f| (self.key_of(&f), f))
+            .collect();
+        keyed.sort_by(|(a, _), (b, _)| match (a, b) {
+            (Some(a), Some(b)) if self.descending => b.cmp(a),
+            (Some(a), Some(b)) => a.cmp(b),
+            (a, b) => b.is_some().cmp(&a.is_some()),
+        });
+        keyed.into_iter().map(|(_, f)| f).collect()
+    }
+
+    fn key_of(&self, filename: &str) -> Option<FileKey> {
+        if self.key == SortKey::Name {
+            return Some(FileKey::Name(filename.to_owned()));
+        }
+        let md = fs::metadata(filename).ok()?;
+        Some(match self.key {
+            SortKey::Mtime => FileKey::Mtime(md.modified().ok()?),
+            _ => FileKey::Size(md.len()),
+        })
+    }
+}
+
+/// A file's value for a [SortKey]. Only values of the same key are compared.
+#[derive(Debug, Eq, Ord, PartialEq, PartialOrd)]
+enum FileKey {
+    Name(String),
+    Mtime(SystemTime),
+    Size(u64),
+}
+
+/// Which files found while recursing are searched, per `--include` and
+/// `--exclude` globs, and which directories are descended into, per
+/// `--exclude-dir` globs. A glob with a `/` is matched against the path,
+/// otherwise just the name. Any file, recursing or not, must also have been
+/// modified within the `--newer-than` and `--older-than` window.
+#[derive(Debug, Default)]
+pub(crate) struct FileFilter {
+    include: Vec<Glob>,
+    exclude: Vec<Glob>,
+    exclude_dir: Vec<Glob>,
+    newer_than: Option<Timestamp>,
+    older_than: Option<Timestamp>,
+}
+
+impl FileFilter {
+    pub(crate) fn new(
+        include: &[String],
+        exclude: &[String],
+        exclude_dir: &[String],
+    ) -> Result<FileFilter> {
+        let globs = |gs: &[String]| gs.iter().map(|g| Glob::new(g)).collect::<Result<_>>();
+        // 'archive/' means the directory, which a glob for its name matches
+        let exclude_dir: Vec<_> = exclude_dir
+            .iter()
+            .map(|g| g.strip_suffix('/').unwrap_or(g).to_owned())
+            .collect();
+        Ok(FileFilter {
+            include: globs(include)?,
+            exclude: globs(exclude)?,
+            exclude_dir: globs(&exclude_dir)?,
+            newer_than: None,
+            older_than: None,
+        })
+    }
+
+    /// Only accept files last modified at or after `newer_than` (if any),
+    /// and before `older_than` (if any).
+    pub(crate) fn with_modified(
+        self,
+        newer_than: Option<Timestamp>,
+        older_than: Option<Timestamp>,
+    ) -> FileFilter {
+        FileFilter {
+            newer_than,
+            older_than,
+            ..self
+        }
+    }
+
+    /// Whether a file was modified within the window, if there is one. One
+    /// whose modification time can't be had (e.g., STDIN) is accepted, and
+    /// left to fail when opened, if it's going to.
+    pub(crate) fn modified_within(&self, path: &str) -> bool {
+        if self.newer_than.is_none() && self.older_than.is_none() {
+            return true;
+        }
+        let Some(modified) = fs::metadata(path)
+            .and_then(|md| md.modified())
+            .ok()
+            .and_then(|t| Timestamp::try_from(t).ok())
+        else {
+            return true;
+        };
+        self.newer_than.is_none_or(|t| modified >= t)
+            && self.older_than.is_none_or(|t| modified < t)
+    }
+
+    /// Whether a directory found while recursing is searched.
+    pub(crate) fn descends(&self, path: &str) -> bool {
+        !self.exclude_dir.iter().any(|g| g.is_match(path))
+    }
+
+    /// Whether a file is searched: included (if there are any includes), and
+    /// not excluded.
+    pub(crate) fn accepts(&self, path: &str) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|g| g.is_match(path)))
+            && !self.exclude.iter().any(|g| g.is_match(path))
+    }
+}
+
+#[derive(Debug)]
+struct Glob {
+    re: Regex,
+    whole_path: bool,
+}
+
+impl Glob {
+    fn new(glob: &str) -> Result<Glob> {
+        Ok(Glob {
+            re: Regex::new(&glob_to_regex(glob))
+                .with_context(|| format!("Invalid glob '{glob}'"))?,
+            whole_path: glob.contains('/'),
+        })
+    }
+
+    fn is_match(&self, path: &str) -> bool {
+        if self.whole_path {
+            self.re.is_match(path)
+        } else {
+            self.re.is_match(path.rsplit('/').next().unwrap_or(path))
+        }
+    }
+}
+
+/// Translate a glob, with `*`, `?`, and `[...]` wildcards, to an anchored
+/// regex. Wildcards don't match `/`.
+fn glob_to_regex(glob: &str) -> String {
+    let mut re = String::with_capacity(glob.len() * 2);
+    re.push('^');
+    let mut chars = glob.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' => re.push_str("[^/]*"),
+            '?' => re.push_str("[^/]"),
+            '[' => {
+                let class: String = chars.by_ref().take_while(|&c| c != ']').collect();
+                re.push('[');
+                re.push_str(&match class.strip_prefix('!') {
+                    Some(rest) => format!("^{}", rest.replace('\\', "\\\\")),
+                    None => class.replace('\\', "\\\\"),
+                });
+                re.push(']');
+            }
+            c => re.push_str(&regex_syntax::escape(c.encode_utf8(&mut [0; 4]))),
+        }
+    }
+    re.push('$');
+    re
+}
+
+/// What identifies a file, regardless of the path it was reached by: its
+/// device and inode where there are such things, else its canonical path.
+#[cfg(unix)]
+pub(crate) type FileId = (u64, u64);
+
+#[cfg(not(unix))]
+pub(crate) type FileId = std::path::PathBuf;
+
+/// Identify a file, regardless of the path it was reached by.
+#[cfg(unix)]
+pub(crate) fn file_id(filename: &str) -> Option<FileId> {
+    use std::os::unix::fs::MetadataExt;
+    fs::metadata(filename).ok().map(|md| (md.dev(), md.ino()))
+}
+
+#[cfg(not(unix))]
+pub(crate) fn file_id(filename: &str) -> Option<FileId> {
+    fs::canonicalize(filename).ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn traversal(action: Directories, dedupe: bool) -> Traversal {
+        Traversal {
+            action,
+            dedupe,
+            filter: FileFilter::default(),
+        }
+    }
+
+    #[test]
+    fn directories() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path().to_string_lossy().into_owned();
+        fs::create_dir(temp.path().join("sub")).unwrap();
+        for f in ["b.log", "a.log", "sub/c.log"] {
+            fs::write(temp.path().join(f), "").unwrap();
+        }
+        let files = || vec!["app.log".to_owned(), dir.clone()];
+        assert_eq!(
+            files(),
+            traversal(Directories::Read, true).expand(files()).unwrap()
+        );
+        assert_eq!(
+            vec!["app.log"],
+            traversal(Directories::Skip, true).expand(files()).unwrap()
+        );
+        assert_eq!(
+            vec![
+                "app.log".to_owned(),
+                format!("{dir}/a.log"),
+                format!("{dir}/b.log"),
+                format!("{dir}/sub/c.log"),
+            ],
+            traversal(Directories::Recurse, true)
+                .expand(files())
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn modified() {
+        let files = || vec!["app.log".to_owned(), "-".to_owned()];
+        let expand = |newer_than, older_than| {
+            Traversal {
+                filter: FileFilter::default().with_modified(newer_than, older_than),
+                ..traversal(Directories::Skip, true)
+            }
+            .expand(files())
+            .unwrap()
+        };
+        let tomorrow = Timestamp::now() + jiff::SignedDuration::from_hours(24);
+        assert_eq!(files(), expand(None, Some(tomorrow)));
+        // STDIN has no modification time
+        assert_eq!(vec!["-"], expand(Some(tomorrow), None));
+        assert_eq!(
+            vec!["-"],
+            expand(Some(Timestamp::UNIX_EPOCH), Some(Timestamp::UNIX_EPOCH))
+        );
+    }
+
+    #[test]
+    fn sort_files() {
+        let files = || {
+            ["src/read.rs", "-", "Cargo.toml", "app.log"]
+                .map(String::from)
+                .to_vec()
+        };
+        let sorted = |order: &str| order.parse::<FileOrder>().unwrap().sort(files());
+        assert_eq!(
+            vec!["-", "Cargo.toml", "app.log", "src/read.rs"],
+            sorted("name")
+        );
+        assert_eq!(
+            vec!["src/read.rs", "app.log", "Cargo.toml", "-"],
+            sorted("name:desc")
+        );
+        let by_size = sorted("size");
+        assert_eq!("-", by_size[3], "{by_size:?}");
+        let mut by_size_desc = sorted("size:desc");
+        assert_eq!("-", by_size_desc.pop().unwrap());
+        by_size_desc.reverse();
+        assert_eq!(by_size[..3], by_size_desc);
+        assert!("bogus".parse::<FileOrder>().is_err());
+        assert!("size:up".parse::<FileOrder>().is_err());
+    }
+
+    #[test]
+    fn dedupe() {
+        let files = || {
+            vec![
+                "src/read".to_owned(),
+                "src/read/lines.rs".to_owned(),
+                "./src/read".to_owned(),
+            ]
+        };
+        let all = traversal(Directories::Recurse, false)
+            .expand(files())
+            .unwrap();
+        let deduped = traversal(Directories::Recurse, true)
+            .expand(files())
+            .unwrap();
+        assert_eq!(all.len(), deduped.len() * 2 + 1);
+        assert!(deduped.iter().all(|f| f.starts_with("src/read/")));
+        assert!(deduped.is_sorted());
+    }
+
+    #[test]
+    fn filtered() {
+        let t = Traversal {
+            filter: FileFilter::new(&["l*.rs".to_owned()], &["*s.rs".to_owned()], &[]).unwrap(),
+            ..traversal(Directories::Recurse, true)
+        };
+        // given files aren't filtered
+        assert_eq!(
+            vec!["app.log", "src/read/lazy.rs"],
+            t.expand(vec!["app.log".to_owned(), "src/read".to_owned()])
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn globs() {
+        let filter = |include: &str| FileFilter::new(&[include.to_owned()], &[], &[]).unwrap();
+        assert!(filter("*.log").accepts("logs/app.log"));
+        assert!(!filter("*.log").accepts("logs/app.log.1"));
+        assert!(filter("app.log.[0-9]").accepts("app.log.3"));
+        assert!(!filter("app.log.[!0-9]").accepts("app.log.3"));
+        assert!(filter("app.?.log").accepts("app.1.log"));
+        assert!(filter("logs/*.log").accepts("logs/app.log"));
+        assert!(!filter("logs/*.log").accepts("old/logs/app.log"));
+        assert!(filter("a+b(c).log").accepts("a+b(c).log"));
+    }
+
+    #[test]
+    fn excluded_dirs() {
+        let excluding = |dirs: &[&str]| Traversal {
+            filter: FileFilter::new(
+                &[],
+                &[],
+                &dirs.iter().map(|d| d.to_string()).collect::<Vec<_>>(),
+            )
+            .unwrap(),
+            ..traversal(Directories::Recurse, true)
+        };
+        let files = || vec!["src".to_owned()];
+        let all = excluding(&[]).expand(files()).unwrap();
+        assert!(all.contains(&"src/read/lines.rs".to_owned()));
+        for dirs in [&["read"][..], &["read/"], &["src/r*"]] {
+            let searched = excluding(dirs).expand(files()).unwrap();
+            assert!(searched.contains(&"src/read.rs".to_owned()), "{dirs:?}");
+            assert!(
+                !searched.iter().any(|f| f.starts_with("src/read/")),
+                "{dirs:?}"
+            );
+        }
+        // only directories found while recursing are excluded
+        assert_eq!(
+            all.len(),
+            excluding(&["src"]).expand(files()).unwrap().len()
+        );
+    }
+}
@@ -0,0 +1,45 @@
+/// The last record written, less its timestamp, and how many selected
+/// records since were the same, so weren't, for '--squeeze-repeats'.
+#[derive(Debug, Default)]
+pub(crate) struct Repeats {
+    /// The record's segment, and its text, less its timestamp.
+    last: Option<(usize, String)>,
+    count: usize,
+}
+
+impl Repeats {
+    /// Whether a record repeats the last one written, counting it if so. If
+    /// not, it's the one those after it are compared to, so must be written.
+    pub(crate) fn is_repeat(&mut self, segment: usize, text: &str) -> bool {
+        if let Some((s, last)) = &self.last {
+            if *s == segment && last == text {
+                self.count += 1;
+                return true;
+            }
+        }
+        self.last = Some((segment, text.to_owned()));
+        false
+    }
+
+    /// How many repeats weren't written since the count was last taken.
+    pub(crate) fn take_count(&mut self) -> usize {
+        std::mem::take(&mut self.count)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn runs() {
+        let mut repeats = Repeats::default();
+        assert!(!repeats.is_repeat(0, "boom"));
+        assert!(repeats.is_repeat(0, "boom"));
+        assert!(repeats.is_repeat(0, "boom"));
+        assert!(!repeats.is_repeat(1, "boom"));
+        assert_eq!(2, repeats.take_count());
+        assert!(!repeats.is_repeat(1, "bang"));
+        assert_eq!(0, repeats.take_count());
+    }
+}
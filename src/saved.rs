@@ -0,0 +1,179 @@
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+
+const SAVE: &str = "--save";
+const REPLAY: &str = "--replay";
+
+/// Save an invocation's arguments (after the program's name) as `name`, for
+/// '--save', less the '--save' itself.
+pub(crate) fn save(name: &str, args: &[OsString]) -> Result<()> {
+    save_in(&saved_dir()?, name, args)
+}
+
+/// Replace a '--replay NAME' in an invocation with the arguments saved as
+/// `NAME`, so any arguments after it add to, or override, them. Returns
+/// whether there was one to replace.
+pub(crate) fn expand(args: Vec<OsString>) -> Result<(Vec<OsString>, bool)> {
+    if !args.iter().any(|a| is_option(a, REPLAY)) {
+        return Ok((args, false));
+    }
+    expand_from(&saved_dir()?, args)
+}
+
+/// Where invocations are saved: `lgrep/saved` in `$XDG_CONFIG_HOME`, or else
+/// `~/.config` (`%APPDATA%` on Windows).
+fn saved_dir() -> Result<PathBuf> {
+    let config = if let Some(d) = std::env::var_os("XDG_CONFIG_HOME") {
+        PathBuf::from(d)
+    } else if cfg!(target_os = "windows") {
+        PathBuf::from(std::env::var_os("APPDATA").context("APPDATA isn't set")?)
+    } else {
+        PathBuf::from(std::env::var_os("HOME").context("HOME isn't set")?).join(".config")
+    };
+    Ok(config.join("lgrep").join("saved"))
+}
+
+fn save_in(dir: &Path, name: &str, args: &[OsString]) -> Result<()> {
+    let path = saved_path(dir, name)?;
+    let args = without_option(args, SAVE)?;
+    fs::create_dir_all(dir).with_context(|| format!("Failed to create '{}'", dir.display()))?;
+    fs::write(&path, serde_json::to_string_pretty(&args)?)
+        .with_context(|| format!("Failed to save '{name}' to '{}'", path.display()))
+}
+
+fn expand_from(dir: &Path, args: Vec<OsString>) -> Result<(Vec<OsString>, bool)> {
+    let mut expanded = Vec::with_capacity(args.len());
+    let mut args = args.into_iter();
+    let mut replayed = false;
+    while let Some(a) = args.next() {
+        if a == "--" {
+            expanded.push(a);
+            break;
+        }
+        if replayed || !is_option(&a, REPLAY) {
+            expanded.push(a);
+            continue;
+        }
+        let name = option_value(&a, REPLAY, &mut args)?;
+        let path = saved_path(dir, &name)?;
+        let json = fs::read_to_string(&path)
+            .with_context(|| format!("No invocation saved as '{name}' (in '{}')", dir.display()))?;
+        let saved: Vec<String> = serde_json::from_str(&json)
+            .with_context(|| format!("Failed to read '{}'", path.display()))?;
+        expanded.extend(saved.into_iter().map(OsString::from));
+        replayed = true;
+    }
+    expanded.extend(args);
+    Ok((expanded, replayed))
+}
+
+/// The arguments, as strings, without an option (and its value).
+fn without_option(args: &[OsString], option: &str) -> Result<Vec<String>> {
+    let mut result = Vec::with_capacity(args.len());
+    let mut args = args.iter().cloned();
+    while let Some(a) = args.next() {
+        if is_option(&a, option) {
+            option_value(&a, option, &mut args)?;
+            continue;
+        }
+        match a.into_string() {
+            Ok(s) => result.push(s),
+            Err(a) => bail!("Can't save a non-UTF-8 argument ({a:?})"),
+        }
+    }
+    Ok(result)
+}
+
+/// Whether an argument is `option`, with its value either attached (with an
+/// `=`) or following.
+fn is_option(arg: &OsString, option: &str) -> bool {
+    arg.to_str().is_some_and(|a| {
+        a == option
+            || a.strip_prefix(option)
+                .is_some_and(|tail| tail.starts_with('='))
+    })
+}
+
+fn option_value(
+    arg: &OsString,
+    option: &str,
+    rest: &mut impl Iterator<Item = OsString>,
+) -> Result<String> {
+    let value = match arg.to_str().and_then(|a| a.strip_prefix(option)) {
+        Some(tail) if !tail.is_empty() => Some(tail[1..].to_owned()),
+        _ => rest.next().and_then(|v| v.into_string().ok()),
+    };
+    value.with_context(|| format!("'{option}' needs a NAME"))
+}
+
+fn saved_path(dir: &Path, name: &str) -> Result<PathBuf> {
+    if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\']) {
+        bail!("Can't save an invocation as '{name}'; use a plain name");
+    }
+    Ok(dir.join(format!("{name}.json")))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<OsString> {
+        args.iter().map(OsString::from).collect()
+    }
+
+    #[test]
+    fn save_and_replay() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path().join("saved");
+        save_in(
+            &dir,
+            "npe",
+            &args(&[
+                "-i",
+                "--save",
+                "npe",
+                "--max-count=3",
+                "nullpointer",
+                "app.log",
+            ]),
+        )
+        .unwrap();
+        let (expanded, replayed) = expand_from(
+            &dir,
+            args(&["lgrep", "--replay=npe", "--max-count", "5", "other.log"]),
+        )
+        .unwrap();
+        assert!(replayed);
+        assert_eq!(
+            args(&[
+                "lgrep",
+                "-i",
+                "--max-count=3",
+                "nullpointer",
+                "app.log",
+                "--max-count",
+                "5",
+                "other.log"
+            ]),
+            expanded
+        );
+        let msg = expand_from(&dir, args(&["lgrep", "--replay", "nope"]))
+            .unwrap_err()
+            .to_string();
+        assert!(msg.starts_with("No invocation saved as 'nope'"), "{msg}");
+    }
+
+    #[test]
+    fn not_replayed() {
+        let dir = Path::new("/nonexistent");
+        let (expanded, replayed) =
+            expand_from(dir, args(&["lgrep", "--", "--replay", "x"])).unwrap();
+        assert!(!replayed);
+        assert_eq!(args(&["lgrep", "--", "--replay", "x"]), expanded);
+        assert!(save_in(dir, "../up", &[]).is_err());
+        assert!(expand_from(dir, args(&["lgrep", "--replay"])).is_err());
+    }
+}
@@ -0,0 +1,365 @@
+use std::fmt::{self, Display, Formatter};
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::read::records::Record;
+
+/// Where in a file a record is.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+pub(crate) struct Position {
+    pub(crate) record: usize,
+    pub(crate) line: usize,
+}
+
+impl From<&Record> for Position {
+    fn from(r: &Record) -> Self {
+        Position {
+            record: r.record_num,
+            line: r.first_line,
+        }
+    }
+}
+
+impl Display for Position {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "record {} (line {})", self.record, self.line)
+    }
+}
+
+/// How a file's start pattern fared, if there was one.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Start {
+    #[default]
+    Unused,
+    Found(Position),
+    NotFound,
+    /// The `--start-within` budget ran out, after this many records.
+    GaveUp(usize),
+}
+
+/// What happened while searching a file, for `--stats`.
+#[derive(Debug, Default, Eq, PartialEq, Serialize)]
+pub(crate) struct FileStats {
+    pub(crate) filename: String,
+    pub(crate) records: usize,
+    pub(crate) selected: usize,
+    pub(crate) lines: usize,
+    /// Bytes read, after any decompression.
+    pub(crate) bytes: u64,
+    #[serde(skip)]
+    pub(crate) elapsed: Duration,
+    pub(crate) start: Start,
+    /// Where the end pattern stopped the search, if it did.
+    pub(crate) end: Option<Position>,
+    /// Records skipped for exceeding `--match-timeout`.
+    pub(crate) timed_out: usize,
+    /// Whether the file grew while it was searched, so the search stopped at
+    /// its size when opened.
+    pub(crate) grew: bool,
+    /// The file's size, if it was skipped for exceeding `--max-filesize`.
+    pub(crate) skipped: Option<u64>,
+}
+
+impl Display for FileStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if let Some(n) = self.skipped {
+            return write!(
+                f,
+                "{}: skipped, as its {} is over --max-filesize",
+                self.filename,
+                size(n)
+            );
+        }
+        write!(
+            f,
+            "{}: {} records, {} selected",
+            self.filename, self.records, self.selected
+        )?;
+        if self.lines > 0 {
+            let secs = self.elapsed.as_secs_f64();
+            write!(
+                f,
+                "; {} lines, {} in {secs:.1}s",
+                self.lines,
+                size(self.bytes)
+            )?;
+            if secs > 0.0 {
+                write!(f, " ({}/s)", size((self.bytes as f64 / secs) as u64))?;
+            }
+        }
+        match self.start {
+            Start::Unused => {}
+            Start::Found(p) => write!(f, "; started at {p}")?,
+            Start::NotFound => write!(f, "; start pattern never matched")?,
+            Start::GaveUp(n) => write!(f, "; gave up on the start pattern after {n} records")?,
+        }
+        if let Some(p) = self.end {
+            write!(f, "; ended early at {p}")?;
+        }
+        if self.timed_out > 0 {
+            write!(f, "; {} timed out matching", self.timed_out)?;
+        }
+        if self.grew {
+            write!(
+                f,
+                "; grew while searched, so stopped at its size when opened"
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl FileStats {
+    /// The sums of several files' stats, named "total".
+    pub(crate) fn total<'a>(stats: impl IntoIterator<Item = &'a FileStats>) -> FileStats {
+        stats.into_iter().fold(
+            FileStats {
+                filename: "total".to_owned(),
+                ..FileStats::default()
+            },
+            |t, s| FileStats {
+                records: t.records + s.records,
+                selected: t.selected + s.selected,
+                lines: t.lines + s.lines,
+                bytes: t.bytes + s.bytes,
+                elapsed: t.elapsed + s.elapsed,
+                timed_out: t.timed_out + s.timed_out,
+                ..t
+            },
+        )
+    }
+}
+
+/// A count of bytes, in the largest (binary) unit that keeps it at least one.
+fn size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut n = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while n >= 1024.0 && unit < UNITS.len() - 1 {
+        n /= 1024.0;
+        unit += 1;
+    }
+    format!("{n:.1} {}", UNITS[unit])
+}
+
+/// The verdict of a whole run, for `--summary`.
+#[derive(Debug)]
+pub(crate) struct Summary {
+    pub(crate) selected: usize,
+    /// Files (or rotation sets) with a selected record.
+    pub(crate) matched_files: usize,
+    pub(crate) files: usize,
+    pub(crate) elapsed: Duration,
+    /// Files whose search failed, which ends the run.
+    pub(crate) errors: usize,
+}
+
+impl Display for Summary {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "matched {} record{} across {}/{} files in {:.1}s",
+            self.selected,
+            if self.selected == 1 { "" } else { "s" },
+            self.matched_files,
+            self.files,
+            self.elapsed.as_secs_f64()
+        )?;
+        match self.errors {
+            0 => Ok(()),
+            1 => write!(f, " (1 file had an error)"),
+            n => write!(f, " ({n} files had errors)"),
+        }
+    }
+}
+
+/// How a pattern fared on its own, for `--pattern-stats`.
+#[derive(Debug, Default)]
+pub(crate) struct PatternStats {
+    pub(crate) pattern: String,
+    /// Whether the regex engine can skip ahead to a literal the pattern
+    /// starts with, rather than trying it at every position.
+    pub(crate) prefiltered: bool,
+    pub(crate) searched: usize,
+    pub(crate) matched: usize,
+    pub(crate) elapsed: Duration,
+}
+
+impl Display for PatternStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: matched {} of {} records",
+            self.pattern, self.matched, self.searched
+        )?;
+        if self.searched > 0 {
+            let micros = self.elapsed.as_secs_f64() * 1e6 / self.searched as f64;
+            write!(f, ", {micros:.1}µs per record")?;
+        }
+        if self.prefiltered {
+            write!(f, "; literal prefilter")
+        } else {
+            write!(f, "; no literal prefilter")
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn display() {
+        let stats = FileStats {
+            filename: "app.log".to_owned(),
+            records: 4,
+            selected: 1,
+            ..FileStats::default()
+        };
+        assert_eq!("app.log: 4 records, 1 selected", stats.to_string());
+        let stats = FileStats {
+            start: Start::Found(Position { record: 2, line: 2 }),
+            end: Some(Position {
+                record: 4,
+                line: 10,
+            }),
+            ..stats
+        };
+        assert_eq!(
+            "app.log: 4 records, 1 selected; started at record 2 (line 2); \
+             ended early at record 4 (line 10)",
+            stats.to_string()
+        );
+        let stats = FileStats {
+            start: Start::NotFound,
+            end: None,
+            ..stats
+        };
+        assert_eq!(
+            "app.log: 4 records, 1 selected; start pattern never matched",
+            stats.to_string()
+        );
+        let stats = FileStats {
+            timed_out: 2,
+            ..stats
+        };
+        assert_eq!(
+            "app.log: 4 records, 1 selected; start pattern never matched; 2 timed out matching",
+            stats.to_string()
+        );
+        let stats = FileStats {
+            start: Start::Unused,
+            timed_out: 0,
+            grew: true,
+            ..stats
+        };
+        assert_eq!(
+            "app.log: 4 records, 1 selected; grew while searched, so stopped at its size when opened",
+            stats.to_string()
+        );
+    }
+
+    #[test]
+    fn skipped() {
+        let stats = FileStats {
+            filename: "core".to_owned(),
+            skipped: Some(3 * 1024 * 1024 * 1024),
+            ..FileStats::default()
+        };
+        assert_eq!(
+            "core: skipped, as its 3.0 GiB is over --max-filesize",
+            stats.to_string()
+        );
+    }
+
+    #[test]
+    fn volume() {
+        let stats = FileStats {
+            filename: "app.log".to_owned(),
+            records: 4,
+            selected: 1,
+            lines: 10,
+            bytes: 3 * 1024 * 1024,
+            elapsed: Duration::from_millis(1500),
+            ..FileStats::default()
+        };
+        assert_eq!(
+            "app.log: 4 records, 1 selected; 10 lines, 3.0 MiB in 1.5s (2.0 MiB/s)",
+            stats.to_string()
+        );
+        let other = FileStats {
+            filename: "other.log".to_owned(),
+            records: 2,
+            selected: 0,
+            lines: 2,
+            bytes: 100,
+            elapsed: Duration::ZERO,
+            ..FileStats::default()
+        };
+        assert_eq!(
+            "other.log: 2 records, 0 selected; 2 lines, 100 B in 0.0s",
+            other.to_string()
+        );
+        let total = FileStats::total([&stats, &other]);
+        assert_eq!(6, total.records);
+        assert_eq!(1, total.selected);
+        assert_eq!(12, total.lines);
+        assert_eq!(3 * 1024 * 1024 + 100, total.bytes);
+        assert!(total
+            .to_string()
+            .starts_with("total: 6 records, 1 selected; 12 lines"));
+    }
+
+    #[test]
+    fn summary() {
+        let summary = Summary {
+            selected: 42,
+            matched_files: 3,
+            files: 5,
+            elapsed: Duration::from_millis(1840),
+            errors: 0,
+        };
+        assert_eq!(
+            "matched 42 records across 3/5 files in 1.8s",
+            summary.to_string()
+        );
+        let summary = Summary {
+            selected: 1,
+            matched_files: 1,
+            errors: 1,
+            ..summary
+        };
+        assert_eq!(
+            "matched 1 record across 1/5 files in 1.8s (1 file had an error)",
+            summary.to_string()
+        );
+    }
+
+    #[test]
+    fn patterns() {
+        let stats = PatternStats {
+            pattern: "fail(ed|ure)".to_owned(),
+            prefiltered: true,
+            searched: 4,
+            matched: 1,
+            elapsed: Duration::from_micros(10),
+        };
+        assert_eq!(
+            "fail(ed|ure): matched 1 of 4 records, 2.5µs per record; literal prefilter",
+            stats.to_string()
+        );
+        let stats = PatternStats {
+            pattern: r"\w+ed".to_owned(),
+            ..PatternStats::default()
+        };
+        assert_eq!(
+            r"\w+ed: matched 0 of 0 records; no literal prefilter",
+            stats.to_string()
+        );
+    }
+}
@@ -0,0 +1,611 @@
+use std::borrow::Cow;
+use std::sync::OnceLock;
+use std::time::SystemTime;
+
+use anyhow::{anyhow, Context, Result};
+use jiff::civil::{Date, DateTime, Time};
+use jiff::tz::{Offset, TimeZone};
+use jiff::{SignedDuration, Span, Timestamp};
+use regex_automata::meta::Regex;
+use regex_automata::util::captures::Captures;
+
+const TIMESTAMP_PATTERN: &str = r"(\d{4})-(\d{2})-(\d{2})[ T](\d{2}):(\d{2}):(\d{2})(?:[.,](\d{1,9}))?(?:(Z)|([+-])(\d{2}):?(\d{2}))?";
+
+/// Day, month name, and year, like Apache's `01/Jul/2024:01:25:47 +0000`, or
+/// `01-Jul-2024 01:25:47.755`. Its offset groups are numbered like
+/// [TIMESTAMP_PATTERN]'s.
+const DAY_MONTH_YEAR_PATTERN: &str = r"(?:^|[^\d])(\d{1,2})[-/ ]([A-Za-zÀ-ÿ]{3,9})\.?[-/ ](\d{4})[ :T](\d{2}):(\d{2}):(\d{2})(?:[.,](\d{1,9}))?(?:(Z)| ?([+-])(\d{2}):?(\d{2}))?";
+
+/// Month name and day, but no year, like syslog's `Jul  1 01:25:47`.
+const SYSLOG_PATTERN: &str = r"(?:^|[^A-Za-zÀ-ÿ0-9_])([A-Za-zÀ-ÿ]{3,9})\.? +(\d{1,2}) +(\d{2}):(\d{2}):(\d{2})(?:[.,](\d{1,9}))?";
+
+/// Month names and abbreviations, lowercase, in English, German, French, and
+/// Spanish, by month.
+const MONTH_NAMES: [&[&str]; 12] = [
+    &[
+        "jan", "january", "januar", "janv", "janvier", "ene", "enero",
+    ],
+    &[
+        "feb", "february", "februar", "févr", "fevr", "février", "febrero",
+    ],
+    &["mar", "march", "mär", "märz", "mrz", "mars", "marzo"],
+    &["apr", "april", "avr", "avril", "abr", "abril"],
+    &["may", "mai", "mayo"],
+    &["jun", "june", "juni", "juin", "junio"],
+    &["jul", "july", "juli", "juil", "juillet", "julio"],
+    &["aug", "august", "août", "aout", "ago", "agosto"],
+    &["sep", "sept", "september", "septembre", "septiembre"],
+    &["oct", "october", "okt", "oktober", "octobre", "octubre"],
+    &["nov", "november", "novembre", "noviembre"],
+    &[
+        "dec",
+        "december",
+        "dez",
+        "dezember",
+        "déc",
+        "décembre",
+        "dic",
+        "diciembre",
+    ],
+];
+
+const TIME_ONLY_PATTERN: &str = r"(?:^|[^\d:.])(\d{2}):(\d{2}):(\d{2})(?:[.,](\d{1,9}))?";
+
+/// How far backward a time-only timestamp must jump to be considered the next
+/// day, rather than slightly out-of-order records from concurrent threads.
+const ROLLOVER_THRESHOLD: SignedDuration = SignedDuration::from_hours(12);
+
+/// Extracts the leading timestamp from log records. Only the first line of a
+/// record is considered, so timestamps buried in a stack trace or payload
+/// don't confuse things.
+///
+/// Besides ISO-like datetimes, dates with a month name are understood, like
+/// Apache's `01/Jul/2024:01:25:47 +0000` and syslog's `Jul  1 01:25:47`. Month
+/// names may be English, German, French, or Spanish. Syslog timestamps have no
+/// year, so they're assumed to be within the last year.
+///
+/// Timestamps with an explicit offset (`Z` or `+02:00`) are honored. Naive
+/// ones are interpreted in the parser's time zone, so DST transitions are
+/// accounted for.
+pub(crate) struct TimestampParser {
+    re: Regex,
+    /// Day-month-year and syslog patterns, compiled when first needed, as
+    /// most logs' timestamps are ISO-like.
+    month_name_res: OnceLock<(Regex, Regex)>,
+    time_only_re: Regex,
+    tz: TimeZone,
+}
+
+impl TimestampParser {
+    pub(crate) fn new() -> TimestampParser {
+        Self::with_time_zone(TimeZone::system())
+    }
+
+    pub(crate) fn with_time_zone(tz: TimeZone) -> TimestampParser {
+        TimestampParser {
+            re: Regex::new(TIMESTAMP_PATTERN).unwrap(),
+            month_name_res: OnceLock::new(),
+            time_only_re: Regex::new(TIME_ONLY_PATTERN).unwrap(),
+            tz,
+        }
+    }
+
+    /// Build a parser for the named IANA time zone (e.g. `Europe/Berlin`).
+    pub(crate) fn for_time_zone_name(name: &str) -> Result<TimestampParser> {
+        let tz = TimeZone::get(name).with_context(|| format!("Unknown time zone '{name}'"))?;
+        Ok(Self::with_time_zone(tz))
+    }
+
+    pub(crate) fn parse(&self, record_text: &str) -> Option<Timestamp> {
+        let first_line = record_text.split('\n').next().unwrap_or_default();
+        let mut caps = self.re.create_captures();
+        self.re.captures(first_line, &mut caps);
+        if caps.is_match() {
+            let dt = parse_datetime(&caps, first_line)?;
+            return self.with_offset(&caps, first_line, dt);
+        }
+        self.parse_day_month_year(first_line)
+            .or_else(|| self.parse_syslog(first_line))
+    }
+
+    /// A record's text less the timestamp of its first line, if it has one,
+    /// so records can be compared but for when they were written. Time-only
+    /// timestamps are removed too.
+    pub(crate) fn strip<'t>(&self, record_text: &'t str) -> Cow<'t, str> {
+        let first_line = record_text.split('\n').next().unwrap_or_default();
+        let (day_month_year, syslog) = self.month_name_res();
+        // from the first group, as some patterns start with the character
+        // before the timestamp
+        let Some((start, end)) = [&self.re, day_month_year, syslog, &self.time_only_re]
+            .into_iter()
+            .find_map(|re| {
+                let caps = re.captures_iter(first_line).next()?;
+                Some((caps.get_group(1)?.start, caps.get_match()?.end()))
+            })
+        else {
+            return record_text.into();
+        };
+        let mut text = String::with_capacity(record_text.len() - (end - start));
+        text.push_str(&record_text[..start]);
+        text.push_str(&record_text[end..]);
+        text.into()
+    }
+
+    fn month_name_res(&self) -> &(Regex, Regex) {
+        self.month_name_res.get_or_init(|| {
+            (
+                Regex::new(DAY_MONTH_YEAR_PATTERN).unwrap(),
+                Regex::new(SYSLOG_PATTERN).unwrap(),
+            )
+        })
+    }
+
+    /// Parse a day, month name, and year (e.g., Apache's timestamps).
+    fn parse_day_month_year(&self, first_line: &str) -> Option<Timestamp> {
+        // the first with a month for a name
+        let caps = self
+            .month_name_res()
+            .0
+            .captures_iter(first_line)
+            .find(|caps| {
+                group_str(caps, first_line, 2)
+                    .and_then(month_number)
+                    .is_some()
+            })?;
+        let field = |i| group_str(&caps, first_line, i).and_then(|s| s.parse::<i32>().ok());
+        let dt = DateTime::new(
+            field(3)? as i16,
+            month_number(group_str(&caps, first_line, 2)?)?,
+            field(1)? as i8,
+            field(4)? as i8,
+            field(5)? as i8,
+            field(6)? as i8,
+            parse_nanos(group_str(&caps, first_line, 7)),
+        )
+        .ok()?;
+        self.with_offset(&caps, first_line, dt)
+    }
+
+    /// Parse a month name and day, without a year (e.g., syslog's
+    /// timestamps). The year is the latest which doesn't put it more than a
+    /// day in the future, to allow for clock skew.
+    fn parse_syslog(&self, first_line: &str) -> Option<Timestamp> {
+        let caps = self
+            .month_name_res()
+            .1
+            .captures_iter(first_line)
+            .find(|caps| {
+                group_str(caps, first_line, 1)
+                    .and_then(month_number)
+                    .is_some()
+            })?;
+        let field = |i| group_str(&caps, first_line, i).and_then(|s| s.parse::<i8>().ok());
+        let now = self.tz.to_datetime(Timestamp::now());
+        let at_year = |year| {
+            DateTime::new(
+                year,
+                month_number(group_str(&caps, first_line, 1)?)?,
+                field(2)?,
+                field(3)?,
+                field(4)?,
+                field(5)?,
+                parse_nanos(group_str(&caps, first_line, 6)),
+            )
+            .ok()
+        };
+        let dt = match at_year(now.year()) {
+            Some(dt) if dt <= now.checked_add(SignedDuration::from_hours(24)).ok()? => dt,
+            // e.g., Feb 29th of a leap year
+            _ => at_year(now.year() - 1)?,
+        };
+        self.to_timestamp(dt)
+    }
+
+    /// Convert a parsed datetime to a timestamp, honoring any offset in the
+    /// `Z` or sign, hours, and minutes groups (8 through 11).
+    fn with_offset(&self, caps: &Captures, first_line: &str, dt: DateTime) -> Option<Timestamp> {
+        if caps.get_group(8).is_some() {
+            return Offset::UTC.to_timestamp(dt).ok();
+        }
+        if let Some(sign) = group_str(caps, first_line, 9) {
+            let hours: i32 = group_str(caps, first_line, 10)?.parse().ok()?;
+            let minutes: i32 = group_str(caps, first_line, 11)?.parse().ok()?;
+            let seconds = (hours * 60 + minutes) * 60;
+            let seconds = if sign == "-" { -seconds } else { seconds };
+            return Offset::from_seconds(seconds).ok()?.to_timestamp(dt).ok();
+        }
+        self.to_timestamp(dt)
+    }
+
+    /// Parse a time given as an option, like a record's timestamp, or just a
+    /// date, meaning its start. Either is in the parser's time zone, unless it
+    /// has an explicit offset.
+    pub(crate) fn parse_arg(&self, arg: &str) -> Result<Timestamp> {
+        self.parse(arg)
+            .or_else(|| {
+                let date: Date = arg.trim().parse().ok()?;
+                self.to_timestamp(date.to_datetime(Time::midnight()))
+            })
+            .ok_or_else(|| {
+                anyhow!("Can't parse '{arg}' as a time, like '2024-07-01 01:25:47' or '2024-07-01'")
+            })
+    }
+
+    /// Parse a cutoff given as an option: an age before `now`, like '2d' or
+    /// '36h', or else a time, as for [TimestampParser::parse_arg].
+    pub(crate) fn parse_cutoff(&self, arg: &str, now: Timestamp) -> Result<Timestamp> {
+        let Ok(age) = arg.trim().parse::<Span>() else {
+            return self
+                .parse_arg(arg)
+                .context("Use an age, like '2d' or '36h', or a time");
+        };
+        Ok(now
+            .to_zoned(self.tz.clone())
+            .checked_sub(age)
+            .with_context(|| format!("'{arg}' is too long ago"))?
+            .timestamp())
+    }
+
+    /// Parse a time of day (`HH:MM:SS`, with optional fraction) from the
+    /// first line of a record which has no date.
+    fn parse_time(&self, record_text: &str) -> Option<Time> {
+        let first_line = record_text.split('\n').next().unwrap_or_default();
+        let mut caps = self.time_only_re.create_captures();
+        self.time_only_re.captures(first_line, &mut caps);
+        if !caps.is_match() {
+            return None;
+        }
+        let field = |i| group_str(&caps, first_line, i).and_then(|s| s.parse::<i8>().ok());
+        Time::new(
+            field(1)?,
+            field(2)?,
+            field(3)?,
+            parse_nanos(group_str(&caps, first_line, 4)),
+        )
+        .ok()
+    }
+
+    /// The civil date and time of a timestamp, in the parser's time zone.
+    pub(crate) fn civil(&self, ts: Timestamp) -> DateTime {
+        self.tz.to_datetime(ts)
+    }
+
+    fn to_timestamp(&self, dt: DateTime) -> Option<Timestamp> {
+        self.tz.to_ambiguous_timestamp(dt).compatible().ok()
+    }
+
+    /// Start a [Timeline] for a single file. If `anchor` is supplied, records
+    /// with only a time of day will be dated, starting from the anchor.
+    pub(crate) fn timeline(&self, anchor: Option<Anchor>) -> Timeline<'_> {
+        Timeline {
+            parser: self,
+            anchor,
+            date: None,
+            last_time: None,
+        }
+    }
+}
+
+/// Where a [Timeline] gets the date for time-only records.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Anchor {
+    /// The first record is on this date.
+    Date(Date),
+    /// The file was last modified at this time, so the first record is
+    /// assumed to be within the preceding day.
+    Modified(SystemTime),
+}
+
+/// Parses the timestamps of a single file's records, in order. Records with a
+/// full datetime are parsed as-is. If the timeline has an [Anchor], records
+/// with only a time of day are dated, advancing to the next day each time the
+/// time goes backward (by more than a few hours).
+pub(crate) struct Timeline<'a> {
+    parser: &'a TimestampParser,
+    anchor: Option<Anchor>,
+    date: Option<Date>,
+    last_time: Option<Time>,
+}
+
+impl Timeline<'_> {
+    pub(crate) fn parse(&mut self, record_text: &str) -> Option<Timestamp> {
+        if let Some(ts) = self.parser.parse(record_text) {
+            return Some(ts);
+        }
+        let anchor = self.anchor?;
+        let time = self.parser.parse_time(record_text)?;
+        let date = match (self.date, self.last_time) {
+            (Some(d), Some(last)) if last.duration_since(time) > ROLLOVER_THRESHOLD => {
+                d.tomorrow().ok()?
+            }
+            (Some(d), _) => d,
+            (None, _) => self.anchor_date(anchor, time)?,
+        };
+        self.date = Some(date);
+        self.last_time = Some(time);
+        self.parser.to_timestamp(date.to_datetime(time))
+    }
+
+    fn anchor_date(&self, anchor: Anchor, first_time: Time) -> Option<Date> {
+        match anchor {
+            Anchor::Date(d) => Some(d),
+            Anchor::Modified(st) => {
+                let modified = Timestamp::try_from(st)
+                    .ok()?
+                    .to_zoned(self.parser.tz.clone());
+                if first_time > modified.time() {
+                    modified.date().yesterday().ok()
+                } else {
+                    Some(modified.date())
+                }
+            }
+        }
+    }
+}
+
+fn parse_datetime(caps: &Captures, first_line: &str) -> Option<DateTime> {
+    let field = |i| group_str(caps, first_line, i).and_then(|s| s.parse::<i32>().ok());
+    DateTime::new(
+        field(1)? as i16,
+        field(2)? as i8,
+        field(3)? as i8,
+        field(4)? as i8,
+        field(5)? as i8,
+        field(6)? as i8,
+        parse_nanos(group_str(caps, first_line, 7)),
+    )
+    .ok()
+}
+
+/// The number (1-12) of a month, by name or abbreviation, in any case.
+fn month_number(name: &str) -> Option<i8> {
+    let name = name.to_lowercase();
+    MONTH_NAMES
+        .iter()
+        .position(|names| names.contains(&name.as_str()))
+        .map(|i| i as i8 + 1)
+}
+
+/// Fractional seconds of arbitrary precision, as nanoseconds.
+fn parse_nanos(fraction: Option<&str>) -> i32 {
+    fraction
+        .map(|s| format!("{s:0<9}").parse().unwrap_or_default())
+        .unwrap_or_default()
+}
+
+fn group_str<'h>(caps: &Captures, hay: &'h str, i: usize) -> Option<&'h str> {
+    caps.get_group(i).map(|span| &hay[span])
+}
+
+/// Format an offset as `[+HH:MM:SS.f]`, with tenths of a second. Hours are not
+/// rolled into days, so long-running files just get big hours.
+pub(crate) fn format_offset(offset: SignedDuration) -> String {
+    let sign = if offset.is_negative() { '-' } else { '+' };
+    let millis = offset.as_millis().unsigned_abs();
+    format!(
+        "[{sign}{:02}:{:02}:{:02}.{}]",
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        millis % 1000 / 100
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use jiff::civil::date;
+
+    use super::*;
+
+    fn utc(dt: DateTime) -> Option<Timestamp> {
+        Some(dt.to_zoned(TimeZone::UTC).unwrap().timestamp())
+    }
+
+    #[test]
+    fn parse_default_format() {
+        let p = TimestampParser::with_time_zone(TimeZone::UTC);
+        assert_eq!(
+            utc(date(2024, 7, 1).at(1, 25, 47, 755_000_000)),
+            p.parse("2024-07-01 01:25:47.755 Unexpected error occurred")
+        );
+        assert_eq!(
+            utc(date(2024, 7, 1).at(1, 25, 47, 750_000_000)),
+            p.parse("app.log:2024-07-01T01:25:47,75 comma and T")
+        );
+        assert_eq!(
+            utc(date(2024, 7, 1).at(1, 25, 47, 0)),
+            p.parse("2024-07-01 01:25:47 no fraction")
+        );
+    }
+
+    #[test]
+    fn parse_month_names() {
+        let p = TimestampParser::with_time_zone(TimeZone::UTC);
+        assert_eq!(
+            utc(date(2024, 6, 30).at(23, 25, 47, 0)),
+            p.parse(r#"10.0.0.1 - - [01/Jul/2024:01:25:47 +0200] "GET / HTTP/1.1" 200"#)
+        );
+        assert_eq!(
+            utc(date(2024, 7, 1).at(1, 25, 47, 755_000_000)),
+            p.parse("01-Jul-2024 01:25:47.755 INFO [main] Server startup")
+        );
+        assert_eq!(
+            utc(date(2024, 3, 5).at(1, 25, 47, 0)),
+            p.parse("5 März 2024 01:25:47 Dienst gestartet")
+        );
+        assert_eq!(
+            utc(date(2024, 12, 1).at(1, 25, 47, 0)),
+            p.parse("01/déc./2024:01:25:47 démarré")
+        );
+        assert_eq!(None, p.parse("01/Foo/2024:01:25:47 not a month"));
+    }
+
+    #[test]
+    fn parse_syslog() {
+        let p = TimestampParser::with_time_zone(TimeZone::UTC);
+        let ts = p
+            .parse("Jul  1 01:25:47 myhost sshd[123]: Accepted publickey")
+            .unwrap();
+        let dt = p.civil(ts);
+        assert_eq!((7, 1), (dt.month(), dt.day()));
+        assert_eq!(Time::new(1, 25, 47, 0).unwrap(), dt.time());
+        // within the last year
+        let now = Timestamp::now();
+        assert!(ts <= now + SignedDuration::from_hours(24));
+        assert!(ts > now - SignedDuration::from_hours(366 * 24));
+        assert_eq!(None, p.parse("Reading 12 01:25:47 things"));
+    }
+
+    #[test]
+    fn parse_args() {
+        let p = TimestampParser::with_time_zone(TimeZone::UTC);
+        assert_eq!(
+            utc(date(2024, 7, 1).at(1, 25, 47, 0)),
+            p.parse_arg("2024-07-01T01:25:47").ok()
+        );
+        assert_eq!(
+            utc(date(2024, 7, 1).at(0, 0, 0, 0)),
+            p.parse_arg("2024-07-01").ok()
+        );
+        assert!(p.parse_arg("yesterday").is_err());
+    }
+
+    #[test]
+    fn parse_cutoffs() {
+        let p = TimestampParser::with_time_zone(TimeZone::UTC);
+        let now = utc(date(2024, 7, 3).at(12, 0, 0, 0)).unwrap();
+        assert_eq!(
+            utc(date(2024, 7, 1).at(12, 0, 0, 0)),
+            p.parse_cutoff("2d", now).ok()
+        );
+        assert_eq!(
+            utc(date(2024, 7, 2).at(0, 0, 0, 0)),
+            p.parse_cutoff("36h", now).ok()
+        );
+        assert_eq!(
+            utc(date(2024, 7, 1).at(0, 0, 0, 0)),
+            p.parse_cutoff("2024-07-01", now).ok()
+        );
+        assert!(p.parse_cutoff("yesterday", now).is_err());
+    }
+
+    #[test]
+    fn parse_explicit_offset() {
+        let p = TimestampParser::for_time_zone_name("Europe/Berlin").unwrap();
+        assert_eq!(
+            utc(date(2024, 7, 1).at(1, 25, 47, 0)),
+            p.parse("2024-07-01T01:25:47Z zulu")
+        );
+        assert_eq!(
+            utc(date(2024, 6, 30).at(23, 25, 47, 0)),
+            p.parse("2024-07-01T01:25:47+02:00 offset")
+        );
+        assert_eq!(
+            utc(date(2024, 7, 1).at(6, 25, 47, 0)),
+            p.parse("2024-07-01T01:25:47-0500 compact offset")
+        );
+    }
+
+    #[test]
+    fn parse_assumed_zone_across_dst() {
+        let p = TimestampParser::for_time_zone_name("Europe/Berlin").unwrap();
+        // clocks sprang forward from 02:00 to 03:00 on 2024-03-31
+        let before = p.parse("2024-03-31 01:30:00.0 before").unwrap();
+        let after = p.parse("2024-03-31 03:30:00.0 after").unwrap();
+        assert_eq!(SignedDuration::from_hours(1), after.duration_since(before));
+    }
+
+    #[test]
+    fn time_only_needs_anchor() {
+        let p = TimestampParser::with_time_zone(TimeZone::UTC);
+        assert_eq!(None, p.parse("01:25:47.755 no date"));
+        assert_eq!(None, p.timeline(None).parse("01:25:47.755 no date"));
+    }
+
+    #[test]
+    fn time_only_rollover() {
+        let p = TimestampParser::with_time_zone(TimeZone::UTC);
+        let mut tl = p.timeline(Some(Anchor::Date(date(2024, 7, 1))));
+        assert_eq!(
+            utc(date(2024, 7, 1).at(23, 59, 58, 0)),
+            tl.parse("[23:59:58] late")
+        );
+        // slightly out of order isn't a new day
+        assert_eq!(
+            utc(date(2024, 7, 1).at(23, 59, 57, 500_000_000)),
+            tl.parse("[23:59:57.5] straggler")
+        );
+        assert_eq!(
+            utc(date(2024, 7, 2).at(0, 0, 1, 0)),
+            tl.parse("[00:00:01] early")
+        );
+        // a full datetime is still honored
+        assert_eq!(
+            utc(date(2024, 7, 5).at(0, 0, 2, 0)),
+            tl.parse("2024-07-05 00:00:02.0 whole")
+        );
+    }
+
+    #[test]
+    fn time_only_modified_anchor() {
+        let p = TimestampParser::with_time_zone(TimeZone::UTC);
+        let modified: SystemTime = date(2024, 7, 2)
+            .at(6, 0, 0, 0)
+            .to_zoned(TimeZone::UTC)
+            .unwrap()
+            .timestamp()
+            .into();
+        let mut tl = p.timeline(Some(Anchor::Modified(modified)));
+        // after the mtime's time of day, so must be the day before
+        assert_eq!(
+            utc(date(2024, 7, 1).at(8, 0, 0, 0)),
+            tl.parse("08:00:00 first")
+        );
+        let mut tl = p.timeline(Some(Anchor::Modified(modified)));
+        assert_eq!(
+            utc(date(2024, 7, 2).at(1, 0, 0, 0)),
+            tl.parse("01:00:00 first")
+        );
+    }
+
+    #[test]
+    fn unknown_zone() {
+        assert!(TimestampParser::for_time_zone_name("Mars/Olympus_Mons").is_err());
+    }
+
+    #[test]
+    fn parse_first_line_only() {
+        let p = TimestampParser::new();
+        assert_eq!(None, p.parse("no stamp\n2024-07-01 01:25:47.755"));
+        assert_eq!(None, p.parse("2024-13-01 01:25:47.755 bad month"));
+    }
+
+    #[test]
+    fn strip_timestamps() {
+        let p = TimestampParser::new();
+        assert_eq!(
+            " [main] ERROR boom\n  at 2024-07-01 01:25:48",
+            p.strip("2024-07-01 01:25:47.755 [main] ERROR boom\n  at 2024-07-01 01:25:48")
+        );
+        assert_eq!(" host app: boom", p.strip("Jul  1 01:25:47 host app: boom"));
+        assert_eq!("[] boom", p.strip("[01:25:47.7] boom"));
+        assert!(matches!(p.strip("no stamp"), Cow::Borrowed("no stamp")));
+    }
+
+    #[test]
+    fn format_offsets() {
+        assert_eq!("[+00:00:00.0]", format_offset(SignedDuration::ZERO));
+        assert_eq!(
+            "[+00:14:03.2]",
+            format_offset(SignedDuration::from_millis(843_250))
+        );
+        assert_eq!(
+            "[+27:00:00.0]",
+            format_offset(SignedDuration::from_hours(27))
+        );
+        assert_eq!(
+            "[-00:00:01.5]",
+            format_offset(SignedDuration::from_millis(-1_500))
+        );
+    }
+}
@@ -0,0 +1,92 @@
+use std::io::Read;
+
+use anyhow::Result;
+
+/// The environment variable with the '--log-level' filter, if it's not given.
+#[cfg(feature = "trace")]
+const ENV_LOG: &str = "LGREP_LOG";
+
+/// Enter a span (e.g., `span!(DEBUG, "search", file = filename)`), which is
+/// exited, and with '--log-level' at or below its level, logged with how long
+/// it took, when the returned guard is dropped. Without the `trace` feature,
+/// it's nothing at all, and its fields aren't evaluated.
+#[cfg(feature = "trace")]
+macro_rules! span {
+    ($level:ident, $($args:tt)+) => {
+        tracing::span!(tracing::Level::$level, $($args)+).entered()
+    };
+}
+
+#[cfg(not(feature = "trace"))]
+macro_rules! span {
+    ($level:ident, $($args:tt)+) => {
+        $crate::trace::NoSpan
+    };
+}
+
+pub(crate) use span;
+
+/// What [span] is without the `trace` feature.
+#[cfg(not(feature = "trace"))]
+pub(crate) struct NoSpan;
+
+/// Log spans to STDERR, per a filter like `RUST_LOG`'s (e.g., `debug`, or
+/// `lgrep::read=trace`), which is '--log-level' if given, or else
+/// `LGREP_LOG`. Neither, and nothing is logged.
+#[cfg(feature = "trace")]
+pub(crate) fn init(log_level: Option<&str>) -> Result<()> {
+    use anyhow::Context;
+    use tracing_subscriber::fmt::format::FmtSpan;
+    use tracing_subscriber::EnvFilter;
+
+    let filter = match log_level {
+        Some(level) => {
+            EnvFilter::try_new(level).with_context(|| format!("Invalid log level '{level}'"))?
+        }
+        None => match EnvFilter::try_from_env(ENV_LOG) {
+            Ok(filter) => filter,
+            Err(_) => return Ok(()),
+        },
+    };
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_span_events(FmtSpan::CLOSE)
+        .with_writer(std::io::stderr)
+        .init();
+    Ok(())
+}
+
+#[cfg(not(feature = "trace"))]
+pub(crate) fn init(log_level: Option<&str>) -> Result<()> {
+    if log_level.is_some() {
+        anyhow::bail!("Tracing isn't enabled in this build (the 'trace' feature)");
+    }
+    Ok(())
+}
+
+/// A decompressor, in a span for each read, as that's when the work's done.
+pub(crate) fn decompressing(format: &'static str, reader: impl Read + Send) -> impl Read + Send {
+    #[cfg(feature = "trace")]
+    {
+        Decompressing { format, reader }
+    }
+    #[cfg(not(feature = "trace"))]
+    {
+        let _ = format;
+        reader
+    }
+}
+
+#[cfg(feature = "trace")]
+struct Decompressing<R> {
+    format: &'static str,
+    reader: R,
+}
+
+#[cfg(feature = "trace")]
+impl<R: Read> Read for Decompressing<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let _span = span!(TRACE, "decompress", format = self.format);
+        self.reader.read(buf)
+    }
+}
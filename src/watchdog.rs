@@ -0,0 +1,89 @@
+use std::cell::RefCell;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::Duration;
+
+use crate::read::chunked::Selector;
+
+/// Matches records on a worker thread, for '--match-timeout', so one which
+/// takes too long is given up on at the deadline, rather than waited out.
+/// Matching can't be interrupted, so an over-budget worker is abandoned to
+/// finish on its own, and a new one takes its place.
+pub(crate) struct Watchdog {
+    timeout: Duration,
+    matcher: Selector,
+    worker: RefCell<Option<Worker>>,
+}
+
+struct Worker {
+    texts: Sender<String>,
+    results: Receiver<bool>,
+}
+
+impl Worker {
+    fn spawn(matcher: Selector) -> Worker {
+        let (texts, inbox) = mpsc::channel::<String>();
+        let (outbox, results) = mpsc::channel();
+        thread::spawn(move || {
+            for text in inbox {
+                // if abandoned, nobody's waiting
+                if outbox.send(matcher(&text)).is_err() {
+                    break;
+                }
+            }
+        });
+        Worker { texts, results }
+    }
+}
+
+impl Watchdog {
+    pub(crate) fn new(timeout: Duration, matcher: Selector) -> Watchdog {
+        Watchdog {
+            timeout,
+            matcher,
+            worker: RefCell::default(),
+        }
+    }
+
+    /// Whether a record matches, or `None` if finding out took longer than
+    /// the timeout.
+    pub(crate) fn is_match(&self, text: &str) -> Option<bool> {
+        let mut worker = self.worker.borrow_mut();
+        let w = worker.get_or_insert_with(|| Worker::spawn(self.matcher.clone()));
+        // if it's gone, there's no result to receive either
+        let _ = w.texts.send(text.to_owned());
+        let result = w.results.recv_timeout(self.timeout).ok();
+        if result.is_none() {
+            *worker = None;
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+    use std::time::Instant;
+
+    use super::*;
+
+    #[test]
+    fn gives_up_at_the_deadline() {
+        let watchdog = Watchdog::new(
+            Duration::from_millis(250),
+            Arc::new(|text: &str| {
+                if text == "slow" {
+                    thread::sleep(Duration::from_secs(5));
+                }
+                text.contains('o')
+            }),
+        );
+        assert_eq!(Some(true), watchdog.is_match("one"));
+        assert_eq!(Some(true), watchdog.is_match("two"));
+        let started = Instant::now();
+        assert_eq!(None, watchdog.is_match("slow"));
+        assert!(started.elapsed() < Duration::from_secs(2));
+        // a new worker carries on
+        assert_eq!(Some(false), watchdog.is_match("three"));
+    }
+}
@@ -1,16 +1,39 @@
+use std::borrow::Cow;
 use std::io::{BufWriter, ErrorKind, Write};
+use std::ops::Range;
 
 use anyhow::{Context, Error, Result};
-use regex_automata::meta::FindMatches;
+use clap::builder::styling::Style;
+use regex_automata::meta::{FindMatches, Regex};
+use regex_automata::Match;
+use serde::Serialize;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 
+use crate::distinct::Distinct;
+use crate::level::LevelDetector;
 use crate::read::records::Record;
-use crate::write::capabilities::Capabilities;
+use crate::write::capabilities::{level_style, Capabilities};
+use crate::write::json::{JsonCount, JsonDistinct, JsonFile, JsonRecord, JsonTally, Versioned};
+use crate::write::similar::Similar;
+use crate::write::tags::Tags;
 use crate::Exit;
 
 pub(crate) mod capabilities;
+pub(crate) mod compress;
+pub(crate) mod json;
+pub(crate) mod similar;
+pub(crate) mod tags;
+pub(crate) mod template;
 
 const FLUSH_BUFFER_AT: usize = 8192;
 
+/// Marks each row of a wrapped line after its first.
+const WRAP_MARKER: &str = "↪ ";
+
+/// Below this many columns for the text, wrapping would be less readable
+/// than not, so lines are left to the terminal.
+const MIN_WRAP_WIDTH: usize = 20;
+
 type Sink = BufWriter<dyn Write>;
 
 macro_rules! styled {
@@ -23,12 +46,40 @@ macro_rules! styled {
     };
 }
 
+/// Whether to hard-wrap long lines, for '--wrap'.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum Wrap {
+    Never,
+    /// At the terminal's width, if writing to one.
+    Terminal,
+    Width(usize),
+}
+
 // todo: split this up based on the style of output
 pub(crate) struct LgrepWrite<'a> {
     capabilities: Option<Capabilities>,
     filenames: bool,
+    record_numbers: bool,
     line_numbers: bool,
+    null: bool,
+    /// Hard-wrap lines at this many columns.
+    wrap: Option<usize>,
+    /// Lines of records not to write.
+    omit_lines: Option<Regex>,
+    /// The only lines of records to write.
+    keep_lines: Option<Regex>,
+    /// Write records much like the last one written in full as just their
+    /// differing lines.
+    similar: Option<Similar>,
+    /// Set while writing a context record, so all its lines are prefixed as
+    /// context ('-'), like its first line's continuations.
+    as_context: bool,
+    json: bool,
+    tags: Option<Tags>,
+    levels: Option<LevelDetector>,
+    pattern_labels: &'a [String],
     sink: &'a mut Sink,
+    tee: Option<Box<LgrepWrite<'a>>>,
 }
 
 impl<'a> LgrepWrite<'a> {
@@ -45,14 +96,130 @@ impl<'a> LgrepWrite<'a> {
                 None
             },
             filenames,
+            record_numbers: false,
             line_numbers,
+            null: false,
+            wrap: None,
+            omit_lines: None,
+            keep_lines: None,
+            similar: None,
+            as_context: false,
+            json: false,
+            tags: None,
+            levels: None,
+            pattern_labels: &[],
             sink,
+            tee: None,
+        }
+    }
+
+    /// Highlight each pattern's matches in its own color, if colored.
+    pub(crate) fn with_pattern_colors(self) -> Self {
+        LgrepWrite {
+            capabilities: self.capabilities.map(Capabilities::with_pattern_palette),
+            ..self
+        }
+    }
+
+    /// Prefix lines with a file's tag, instead of its name.
+    pub(crate) fn with_tags(self, tags: Tags) -> Self {
+        LgrepWrite {
+            tags: Some(tags),
+            ..self
+        }
+    }
+
+    /// Prefix lines with their record's number in the file, before any line
+    /// number.
+    pub(crate) fn with_record_numbers(self) -> Self {
+        LgrepWrite {
+            record_numbers: true,
+            ..self
+        }
+    }
+
+    /// Follow file names with a NUL, instead of a separator or newline.
+    pub(crate) fn with_null(self) -> Self {
+        LgrepWrite { null: true, ..self }
+    }
+
+    /// Hard-wrap long lines at `width` columns, with the rows after the
+    /// first aligned after the line's prefix, and marked.
+    pub(crate) fn with_wrap(self, width: usize) -> Self {
+        LgrepWrite {
+            wrap: Some(width),
+            ..self
+        }
+    }
+
+    /// Leave lines matching `pattern` out of written records, except their
+    /// first. The rest of the lines keep their numbers.
+    pub(crate) fn with_omit_lines(self, pattern: Regex) -> Self {
+        LgrepWrite {
+            omit_lines: Some(pattern),
+            ..self
+        }
+    }
+
+    /// Only write lines of records which match `pattern`, and their first.
+    /// The written lines keep their numbers.
+    pub(crate) fn with_keep_lines(self, pattern: Regex) -> Self {
+        LgrepWrite {
+            keep_lines: Some(pattern),
+            ..self
+        }
+    }
+
+    /// Write a record which is much like the last one written in full as
+    /// its first line and the lines which differ, with a marker in place of
+    /// each run of those which don't. See [Similar].
+    pub(crate) fn with_diff_similar(self) -> Self {
+        LgrepWrite {
+            similar: Some(Similar::default()),
+            ..self
+        }
+    }
+
+    /// Write a line of JSON per record (or count), instead of text.
+    pub(crate) fn with_json(self) -> Self {
+        LgrepWrite { json: true, ..self }
+    }
+
+    /// Tint each record (if colored) according to its level.
+    pub(crate) fn with_level_colors(self, levels: LevelDetector) -> Self {
+        LgrepWrite {
+            levels: Some(levels),
+            ..self
+        }
+    }
+
+    /// Label JSON records with the labels of their matching patterns, by
+    /// pattern ID.
+    pub(crate) fn with_pattern_labels(self, pattern_labels: &'a [String]) -> Self {
+        LgrepWrite {
+            pattern_labels,
+            ..self
+        }
+    }
+
+    /// Duplicate everything written to another writer, which is written
+    /// first, so it's complete even if this one terminates. If it terminates
+    /// (e.g., it's a closed pipe), so does this.
+    pub(crate) fn with_tee(self, tee: LgrepWrite<'a>) -> Self {
+        LgrepWrite {
+            tee: Some(Box::new(tee)),
+            ..self
         }
     }
 
     pub(crate) fn needs_match_locations(&self) -> bool {
-        if let Some(cs) = &self.capabilities {
+        if self.json {
+            true
+        } else if let Some(cs) = &self.capabilities {
             cs.match_text.is_some()
+                || !cs.pattern_matches.is_empty()
+                || cs.selected_line.is_some()
+                || cs.context_line.is_some()
         } else {
             false
         }
@@ -60,10 +227,119 @@ impl<'a> LgrepWrite<'a> {
 
     pub(crate) fn write_count(&mut self, filename: &str, count: usize) -> Result<Exit> {
         debug_assert!(
-            !self.line_numbers,
+            !self.line_numbers && !self.record_numbers,
             "line numbers and counts together makes no sense"
         );
-        self.spew(filename, &count.to_string(), 0)
+        if let Some(t) = &mut self.tee {
+            if t.write_count(filename, count)? == Exit::Terminate {
+                return Ok(Exit::Terminate);
+            }
+        }
+        if self.json {
+            return self.write_json(&JsonCount {
+                file: filename,
+                count,
+            });
+        }
+        self.spew(filename, 0, vec![(0, count.to_string().into())], true, None)
+    }
+
+    /// Write just a file's name, for '--files-with(out)-match(es)'.
+    pub(crate) fn write_filename(&mut self, filename: &str) -> Result<Exit> {
+        if let Some(t) = &mut self.tee {
+            if t.write_filename(filename)? == Exit::Terminate {
+                return Ok(Exit::Terminate);
+            }
+        }
+        if self.json {
+            return self.write_json(&JsonFile { file: filename });
+        }
+        let style = self.capabilities.as_ref().and_then(|cs| cs.filename);
+        let end = if self.null { '\0' } else { '\n' };
+        let r = styled!(self.sink, style, filename).and_then(|_| write!(self.sink, "{end}"));
+        self.complete(r)
+    }
+
+    /// Write a `# `-prefixed comment line, styled like a separator.
+    pub(crate) fn write_comment(&mut self, comment: &str) -> Result<Exit> {
+        if let Some(t) = &mut self.tee {
+            if t.write_comment(comment)? == Exit::Terminate {
+                return Ok(Exit::Terminate);
+            }
+        }
+        debug_assert!(!self.json, "comments in JSON make no sense");
+        let style = self.capabilities.as_ref().and_then(|cs| cs.separator);
+        let r = styled!(self.sink, style, format_args!("# {comment}"))
+            .and_then(|_| writeln!(self.sink));
+        self.complete(r)
+    }
+
+    /// Write a marker in place of a run of records which repeated the last
+    /// one written, for '--squeeze-repeats', styled like a separator.
+    pub(crate) fn write_repeated(&mut self, count: usize) -> Result<Exit> {
+        if let Some(t) = &mut self.tee {
+            if t.write_repeated(count)? == Exit::Terminate {
+                return Ok(Exit::Terminate);
+            }
+        }
+        debug_assert!(!self.json, "markers in JSON make no sense");
+        let s = if count == 1 { "" } else { "s" };
+        let style = self.capabilities.as_ref().and_then(|cs| cs.separator);
+        let r = styled!(
+            self.sink,
+            style,
+            format_args!("... repeated {count} time{s}")
+        )
+        .and_then(|_| writeln!(self.sink));
+        self.complete(r)
+    }
+
+    /// Write a record rendered by a '--format' template, as is.
+    pub(crate) fn write_formatted(&mut self, text: &str) -> Result<Exit> {
+        if let Some(t) = &mut self.tee {
+            if t.write_formatted(text)? == Exit::Terminate {
+                return Ok(Exit::Terminate);
+            }
+        }
+        debug_assert!(!self.json, "formatted JSON makes no sense");
+        let r = writeln!(self.sink, "{text}");
+        self.complete(r)
+    }
+
+    /// Write a `key<TAB>count` line, with no file name or line number.
+    pub(crate) fn write_tally(&mut self, key: &str, count: usize) -> Result<Exit> {
+        if let Some(t) = &mut self.tee {
+            if t.write_tally(key, count)? == Exit::Terminate {
+                return Ok(Exit::Terminate);
+            }
+        }
+        if self.json {
+            return self.write_json(&JsonTally { value: key, count });
+        }
+        let r = writeln!(self.sink, "{key}\t{count}");
+        self.complete(r)
+    }
+
+    /// Write a count of distinct values, alone on its line. An approximate
+    /// count is followed by its margin of error.
+    pub(crate) fn write_distinct(&mut self, distinct: &Distinct) -> Result<Exit> {
+        if let Some(t) = &mut self.tee {
+            if t.write_distinct(distinct)? == Exit::Terminate {
+                return Ok(Exit::Terminate);
+            }
+        }
+        let (count, error) = (distinct.count(), distinct.error());
+        if self.json {
+            return self.write_json(&JsonDistinct {
+                distinct: count,
+                error,
+            });
+        }
+        let r = match error {
+            Some(e) => writeln!(self.sink, "{count}\t±{:.1}%", e * 100.0),
+            None => writeln!(self.sink, "{count}"),
+        };
+        self.complete(r)
     }
 
     pub(crate) fn write_record_with_matches(
@@ -71,67 +347,304 @@ impl<'a> LgrepWrite<'a> {
         filename: &str,
         record: &Record,
         matches: FindMatches,
+        annotation: Option<&str>,
     ) -> Result<Exit> {
-        if let Some(cs) = &self.capabilities {
-            if let Some(s) = cs.match_text {
-                // allocate a little extra space, so a single match probably won't reallocate.
-                let mut text = String::with_capacity(record.text.len() + 20);
-                let mut thru = 0;
-                for m in matches {
-                    if m.start() > thru {
-                        text.push_str(&record.text[thru..m.start()]);
-                    }
-                    for line in record.text[m.start()..m.end()].split_inclusive('\n') {
-                        if let Some(bare_line) = line.strip_suffix('\n') {
-                            text.push_str(&format!("{}{}{0:#}\n", s, bare_line));
-                        } else {
-                            text.push_str(&format!("{}{}{0:#}", s, line));
-                        }
+        let matches: Vec<_> = matches.collect();
+        self.write_matched(filename, record, &matches, annotation)
+    }
+
+    /// Write a record with its matches, which are collected once, so the tee
+    /// (if any) gets them too.
+    fn write_matched(
+        &mut self,
+        filename: &str,
+        record: &Record,
+        matches: &[Match],
+        annotation: Option<&str>,
+    ) -> Result<Exit> {
+        if let Some(t) = &mut self.tee {
+            let exit = if t.needs_match_locations() {
+                t.write_matched(filename, record, matches, annotation)?
+            } else {
+                t.write_record(filename, record, annotation)?
+            };
+            if exit == Exit::Terminate {
+                return Ok(Exit::Terminate);
+            }
+        }
+        if self.json {
+            let spans = match_spans(&record.text, matches.iter().copied());
+            let pattern_labels = self.pattern_labels;
+            let mut labels = Vec::new();
+            for m in matches {
+                if let Some(l) = pattern_labels.get(m.pattern().as_usize()) {
+                    if !l.is_empty() && !labels.contains(&l.as_str()) {
+                        labels.push(l.as_str());
                     }
-                    thru = m.end();
-                }
-                if thru < record.text.len() {
-                    text.push_str(&record.text[thru..])
                 }
-                return self.spew(filename, &text, record.first_line);
             }
+            let json = JsonRecord::new(
+                filename,
+                record,
+                &spans,
+                self.filenames,
+                self.record_numbers,
+                self.line_numbers,
+                annotation,
+            )
+            .with_patterns(labels);
+            return self.write_json(&json);
+        }
+        if let Some(cs) = &self.capabilities {
+            let text = highlight(&record.text, matches.iter().copied(), cs, self.tint(record));
+            return self.spew_record(filename, record, &text, annotation);
         }
         debug_assert!(false, "write_record_with_matches invoked w/ no styling?!");
-        self.write_record(filename, record)
+        self.spew_record(filename, record, &record.text, annotation)
     }
 
-    pub(crate) fn write_record(&mut self, filename: &str, record: &Record) -> Result<Exit> {
-        self.spew(filename, &record.text, record.first_line)
+    /// Write a record, with an optional annotation between the first line's
+    /// prefix (if any) and its text.
+    pub(crate) fn write_record(
+        &mut self,
+        filename: &str,
+        record: &Record,
+        annotation: Option<&str>,
+    ) -> Result<Exit> {
+        if let Some(t) = &mut self.tee {
+            if t.write_record(filename, record, annotation)? == Exit::Terminate {
+                return Ok(Exit::Terminate);
+            }
+        }
+        if self.json {
+            return self.write_json_record(filename, record, &[], annotation);
+        }
+        if let Some(cs) = &self.capabilities {
+            if let Some(tint) = self.tint(record) {
+                let text = highlight(&record.text, [], cs, Some(tint));
+                return self.spew_record(filename, record, &text, annotation);
+            }
+        }
+        self.spew_record(filename, record, &record.text, annotation)
     }
 
-    fn spew(&mut self, filename: &str, text: &str, first_line: usize) -> Result<Exit> {
-        let r = self
-            .spew_internal(filename, text, first_line)
-            .and_then(|_| self.sink.flush());
-        if let Err(e) = r {
-            return if e.kind() == ErrorKind::BrokenPipe {
-                // nothing is listening anymore
-                Ok(Exit::Terminate)
-            } else {
-                Err(Error::from(e)).context("Failed to write")
-            };
+    /// Write a record as context for a nearby selected record: unhighlighted,
+    /// in the context style (if colored), and with every line prefixed as
+    /// context ('-').
+    pub(crate) fn write_context_record(&mut self, filename: &str, record: &Record) -> Result<Exit> {
+        if let Some(t) = &mut self.tee {
+            if t.write_context_record(filename, record)? == Exit::Terminate {
+                return Ok(Exit::Terminate);
+            }
         }
-        Ok(Exit::Match)
+        debug_assert!(!self.json, "context records aren't JSON");
+        let text = match &self.capabilities {
+            Some(cs) => Cow::Owned(highlight(&record.text, [], cs, None)),
+            None => Cow::Borrowed(record.text.as_str()),
+        };
+        self.as_context = true;
+        let exit = self.spew_record(filename, record, &text, None);
+        self.as_context = false;
+        exit
     }
 
-    fn spew_internal(
+    /// The style of a record's whole text, per its level, if coloring by
+    /// level.
+    fn tint(&self, record: &Record) -> Option<Style> {
+        self.levels
+            .as_ref()
+            .and_then(|l| l.detect(&record.text))
+            .and_then(level_style)
+    }
+
+    fn write_json_record(
+        &mut self,
+        filename: &str,
+        record: &Record,
+        matches: &[Range<usize>],
+        annotation: Option<&str>,
+    ) -> Result<Exit> {
+        let json = JsonRecord::new(
+            filename,
+            record,
+            matches,
+            self.filenames,
+            self.record_numbers,
+            self.line_numbers,
+            annotation,
+        );
+        self.write_json(&json)
+    }
+
+    fn write_json<T: Serialize>(&mut self, value: &T) -> Result<Exit> {
+        let r = serde_json::to_writer(&mut *self.sink, &Versioned::new(value))
+            .map_err(std::io::Error::from)
+            .and_then(|_| writeln!(self.sink));
+        self.complete(r)
+    }
+
+    /// Write a record's `text`, which is its own, or it highlighted, line for
+    /// line, less any omitted lines.
+    fn spew_record(
         &mut self,
         filename: &str,
+        record: &Record,
         text: &str,
-        first_line: usize,
+        annotation: Option<&str>,
+    ) -> Result<Exit> {
+        let base = self
+            .similar
+            .as_mut()
+            .and_then(|s| s.base_for(filename, &record.text));
+        let base: Vec<_> = base.iter().flat_map(|b| b.split('\n')).collect();
+        let mut lines = Vec::new();
+        // a run of lines the same as the base's, and its first line number
+        let mut same = (0, 0);
+        for (i, (l, raw)) in text.split('\n').zip(record.text.split('\n')).enumerate() {
+            let line_num = record.first_line + i;
+            if i > 0 && self.omits(raw) {
+                continue;
+            }
+            if i > 0 && base.get(i) == Some(&raw) {
+                if same.0 == 0 {
+                    same.1 = line_num;
+                }
+                same.0 += 1;
+                continue;
+            }
+            if same.0 > 0 {
+                lines.push((same.1, self.same_marker(same.0)));
+                same.0 = 0;
+            }
+            lines.push((line_num, Cow::Borrowed(l)));
+        }
+        if same.0 > 0 {
+            lines.push((same.1, self.same_marker(same.0)));
+        }
+        self.spew(
+            filename,
+            record.record_num,
+            lines,
+            record.terminated,
+            annotation,
+        )
+    }
+
+    /// Stands in for `n` lines of a similar record which are the same as the
+    /// last record written in full.
+    fn same_marker(&self, n: usize) -> Cow<'static, str> {
+        let s = if n == 1 { "" } else { "s" };
+        let marker = format!("≈ {n} line{s} as in the last full record");
+        match self.capabilities.as_ref().and_then(|cs| cs.separator) {
+            Some(style) => format!("{style}{marker}{style:#}").into(),
+            None => marker.into(),
+        }
+    }
+
+    fn omits(&self, line: &str) -> bool {
+        self.omit_lines.as_ref().is_some_and(|re| re.is_match(line))
+            || self
+                .keep_lines
+                .as_ref()
+                .is_some_and(|re| !re.is_match(line))
+    }
+
+    /// Write numbered lines, each with its prefix.
+    fn spew(
+        &mut self,
+        filename: &str,
+        record_num: usize,
+        lines: Vec<(usize, Cow<str>)>,
+        terminated: bool,
+        annotation: Option<&str>,
+    ) -> Result<Exit> {
+        let r = self.spew_internal(filename, record_num, lines, terminated, annotation);
+        self.complete(r)
+    }
+
+    /// Every write path funnels through here, to flush and to translate a
+    /// closed output (e.g., `lgrep ... | head`) into [Exit::Terminate], as
+    /// there's no point in continuing.
+    fn complete(&mut self, r: std::io::Result<()>) -> Result<Exit> {
+        match r.and_then(|_| self.sink.flush()) {
+            Ok(()) => Ok(Exit::Match),
+            // nothing is listening anymore
+            Err(e) if e.kind() == ErrorKind::BrokenPipe => Ok(Exit::Terminate),
+            Err(e) => Err(Error::from(e)).context("Failed to write"),
+        }
+    }
+
+    /// How many columns a line's prefix takes, so wrapped rows can be aligned
+    /// after it.
+    fn prefix_width(
+        &self,
+        filename: &str,
+        record_num: usize,
+        line_num: usize,
+        annotation: Option<&str>,
+    ) -> usize {
+        let digits = |n: usize| n.to_string().len();
+        let mut width = 0;
+        if let Some(tags) = &self.tags {
+            width += tags.get(filename).0.width() + " | ".len();
+        } else if self.filenames {
+            width += filename.width() + 1;
+        }
+        if self.record_numbers {
+            width += digits(record_num) + 1;
+        }
+        if self.line_numbers {
+            width += digits(line_num) + 1;
+        }
+        if let Some(a) = annotation {
+            width += a.width() + 1;
+        }
+        width
+    }
+
+    fn spew_internal(
+        &mut self,
+        filename: &str,
+        record_num: usize,
+        lines: Vec<(usize, Cow<str>)>,
+        terminated: bool,
+        mut annotation: Option<&str>,
     ) -> std::io::Result<()> {
-        let lines = text.split('\n');
-        let mut separator = ':';
-        let mut line_num = first_line;
-        for l in lines {
+        // unprefixed output is the input, byte for byte, so a missing final
+        // newline stays missing; prefixed output is always whole lines.
+        let unterminated = !terminated
+            && !self.filenames
+            && !self.record_numbers
+            && !self.line_numbers
+            && self.tags.is_none()
+            && annotation.is_none();
+        let mut lines = lines.into_iter().peekable();
+        let mut separator = if self.as_context { '-' } else { ':' };
+        while let Some((line_num, l)) = lines.next() {
+            let prefix_width = self
+                .wrap
+                .map(|_| self.prefix_width(filename, record_num, line_num, annotation))
+                .unwrap_or_default();
+            if let Some(tags) = &self.tags {
+                let (tag, style) = tags.get(filename);
+                if self.capabilities.is_some() {
+                    styled!(self.sink, style, tag)?;
+                } else {
+                    write!(self.sink, "{tag}")?;
+                }
+                write!(self.sink, " | ")?;
+            }
             if let Some(cs) = &self.capabilities {
-                if self.filenames {
+                if self.filenames && self.tags.is_none() {
                     styled!(self.sink, cs.filename, filename)?;
+                    if self.null {
+                        write!(self.sink, "\0")?;
+                    } else {
+                        styled!(self.sink, cs.separator, separator)?;
+                    }
+                }
+                if self.record_numbers {
+                    styled!(self.sink, cs.record_number, record_num)?;
                     styled!(self.sink, cs.separator, separator)?;
                 }
                 if self.line_numbers {
@@ -139,8 +652,16 @@ impl<'a> LgrepWrite<'a> {
                     styled!(self.sink, cs.separator, separator)?;
                 }
             } else {
-                if self.filenames {
+                if self.filenames && self.tags.is_none() {
                     write!(self.sink, "{filename}")?;
+                    if self.null {
+                        write!(self.sink, "\0")?;
+                    } else {
+                        write!(self.sink, "{separator}")?;
+                    }
+                }
+                if self.record_numbers {
+                    write!(self.sink, "{record_num}")?;
                     write!(self.sink, "{separator}")?;
                 }
                 if self.line_numbers {
@@ -148,13 +669,229 @@ impl<'a> LgrepWrite<'a> {
                     write!(self.sink, "{separator}")?;
                 }
             }
-            writeln!(self.sink, "{l}")?;
+            if let Some(a) = annotation.take() {
+                write!(self.sink, "{a} ")?;
+            }
+            let rows = match self.wrap.map(|w| w.saturating_sub(prefix_width)) {
+                Some(w) if w >= MIN_WRAP_WIDTH => wrap_rows(&l, w, w - WRAP_MARKER.width()),
+                _ => vec![&*l],
+            };
+            for (i, row) in rows.into_iter().enumerate() {
+                if i > 0 {
+                    writeln!(self.sink)?;
+                    write!(self.sink, "{:prefix_width$}", "")?;
+                    let style = self.capabilities.as_ref().and_then(|cs| cs.separator);
+                    styled!(self.sink, style, WRAP_MARKER)?;
+                }
+                write!(self.sink, "{row}")?;
+            }
+            if !unterminated || lines.peek().is_some() {
+                writeln!(self.sink)?;
+            }
             if self.sink.buffer().len() >= FLUSH_BUFFER_AT {
                 self.sink.flush()?
             }
             separator = '-';
-            line_num += 1;
         }
         Ok(())
     }
 }
+
+/// Split a line into rows of at most `first` columns, then `rest` columns
+/// each. Wide characters (e.g., CJK, most emoji) take two columns, so one
+/// which won't fit at the end of a row starts the next. Escape sequences
+/// (i.e., colors) take no room.
+fn wrap_rows(line: &str, first: usize, rest: usize) -> Vec<&str> {
+    let mut rows = Vec::new();
+    let (mut start, mut width, mut limit) = (0, 0, first);
+    let mut chars = line.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        if c == '\x1b' {
+            // through the sequence's final byte, e.g., the 'm' of "\x1b[1;31m"
+            chars.next_if(|&(_, c)| c == '[');
+            for (_, c) in chars.by_ref() {
+                if ('@'..='~').contains(&c) {
+                    break;
+                }
+            }
+            continue;
+        }
+        let w = c.width().unwrap_or(1);
+        if width + w > limit && width > 0 {
+            rows.push(&line[start..i]);
+            (start, width, limit) = (i, 0, rest);
+        }
+        width += w;
+    }
+    rows.push(&line[start..]);
+    rows
+}
+
+/// The non-empty, non-overlapping byte ranges of a record's matches.
+fn match_spans(text: &str, matches: impl IntoIterator<Item = Match>) -> Vec<Range<usize>> {
+    pattern_spans(text, matches)
+        .into_iter()
+        .map(|(r, _)| r)
+        .collect()
+}
+
+/// The non-empty, non-overlapping byte ranges of a record's matches, each
+/// with the ID of the pattern which matched it.
+fn pattern_spans(
+    text: &str,
+    matches: impl IntoIterator<Item = Match>,
+) -> Vec<(Range<usize>, usize)> {
+    let mut spans = Vec::new();
+    let mut thru = 0;
+    for m in matches {
+        // without Unicode, a match may split a multibyte char; widen it.
+        let start = floor_char_boundary(text, m.start()).max(thru);
+        let end = ceil_char_boundary(text, m.end());
+        if start < end {
+            spans.push((start..end, m.pattern().as_usize()));
+            thru = end;
+        }
+    }
+    spans
+}
+
+/// Style a record's matches, and its lines according to whether they contain
+/// a match, or all with `tint`. After a match, the line's style is restarted,
+/// as ending the match style resets everything.
+fn highlight(
+    text: &str,
+    matches: impl IntoIterator<Item = Match>,
+    cs: &Capabilities,
+    tint: Option<Style>,
+) -> String {
+    let spans = pattern_spans(text, matches);
+    // allocate a little extra space, so a single match probably won't reallocate.
+    let mut result = String::with_capacity(text.len() + 20);
+    let mut spans = spans.into_iter().peekable();
+    let mut line_start = 0;
+    for line in text.split('\n') {
+        let line_end = line_start + line.len();
+        if line_start > 0 {
+            result.push('\n');
+        }
+        let is_selected = spans.peek().is_some_and(|(m, _)| m.start <= line_end);
+        let line_style = if tint.is_some() {
+            tint
+        } else if is_selected {
+            cs.selected_line
+        } else {
+            cs.context_line
+        };
+        if let Some(ls) = line_style {
+            result.push_str(&format!("{ls}"));
+        }
+        let mut pos = line_start;
+        while let Some((m, pattern)) = spans.peek() {
+            if m.start > line_end {
+                break;
+            }
+            let start = m.start.max(pos);
+            let end = m.end.min(line_end);
+            result.push_str(&text[pos..start]);
+            if let Some(s) = cs.pattern_match(*pattern) {
+                if start < end {
+                    result.push_str(&format!("{s}{}{s:#}", &text[start..end]));
+                    if let Some(ls) = line_style {
+                        result.push_str(&format!("{ls}"));
+                    }
+                }
+            } else {
+                result.push_str(&text[start..end]);
+            }
+            pos = end;
+            if m.end > line_end {
+                break; // continues on the next line
+            }
+            spans.next();
+        }
+        result.push_str(&text[pos..line_end]);
+        if let Some(ls) = line_style {
+            result.push_str(&format!("{ls:#}"));
+        }
+        line_start = line_end + 1;
+    }
+    result
+}
+
+pub(crate) fn floor_char_boundary(s: &str, mut i: usize) -> usize {
+    while !s.is_char_boundary(i) {
+        i -= 1;
+    }
+    i
+}
+
+pub(crate) fn ceil_char_boundary(s: &str, mut i: usize) -> usize {
+    while !s.is_char_boundary(i) {
+        i += 1;
+    }
+    i
+}
+
+#[cfg(test)]
+mod test {
+    use clap::builder::styling::AnsiColor;
+    use regex_automata::meta::Regex;
+
+    use super::*;
+
+    #[test]
+    fn highlight_lines() {
+        let cs: Capabilities = "mt=31:sl=44:cx=2".parse().unwrap();
+        let re = Regex::new(r"b\nc|e").unwrap();
+        let text = "a b\nc d\nfoo\ne";
+        assert_eq!(
+            "\u{1b}[44ma \u{1b}[31mb\u{1b}[0m\u{1b}[44m\u{1b}[0m\n\
+             \u{1b}[44m\u{1b}[31mc\u{1b}[0m\u{1b}[44m d\u{1b}[0m\n\
+             \u{1b}[2mfoo\u{1b}[0m\n\
+             \u{1b}[44m\u{1b}[31me\u{1b}[0m\u{1b}[44m\u{1b}[0m",
+            highlight(text, re.find_iter(text), &cs, None)
+        );
+    }
+
+    #[test]
+    fn highlight_tinted() {
+        let cs: Capabilities = "mt=31:sl=44:cx=2".parse().unwrap();
+        let re = Regex::new(r"b").unwrap();
+        let text = "a b
+c";
+        let tint = Style::new().fg_color(Some(AnsiColor::Yellow.into()));
+        assert_eq!(
+            "\u{1b}[33ma \u{1b}[31mb\u{1b}[0m\u{1b}[33m\u{1b}[0m\n\
+             \u{1b}[33mc\u{1b}[0m",
+            highlight(text, re.find_iter(text), &cs, Some(tint))
+        );
+    }
+
+    #[test]
+    fn highlight_patterns() {
+        let cs = "mt=31"
+            .parse::<Capabilities>()
+            .unwrap()
+            .with_pattern_palette();
+        let re = Regex::new_many(&["a", "b"]).unwrap();
+        let text = "a b";
+        assert_eq!(
+            "\u{1b}[1m\u{1b}[31ma\u{1b}[0m \u{1b}[1m\u{1b}[32mb\u{1b}[0m",
+            highlight(text, re.find_iter(text), &cs, None)
+        );
+    }
+
+    #[test]
+    fn wrapping() {
+        assert_eq!(vec!["abcd"], wrap_rows("abcd", 4, 2));
+        assert_eq!(vec!["abc", "de", "fg", "h"], wrap_rows("abcdefgh", 3, 2));
+        assert_eq!(vec!["aé", "ü"], wrap_rows("aéü", 2, 2));
+        // wide characters take two columns
+        assert_eq!(vec!["日本", "語x"], wrap_rows("日本語x", 5, 3));
+        assert_eq!(vec!["a", "😀", "b"], wrap_rows("a😀b", 2, 2));
+        assert_eq!(
+            vec!["a\u{1b}[1;31mb", "c\u{1b}[0m"],
+            wrap_rows("a\u{1b}[1;31mbc\u{1b}[0m", 2, 2)
+        );
+    }
+}
@@ -3,14 +3,35 @@ use std::str::FromStr;
 
 use clap::builder::styling::{AnsiColor, Style};
 
+use crate::level::Level;
+
 const ENV_COLORS: &str = "GREP_COLORS";
 
+/// The match styles cycled through for '--color-by-pattern', starting with
+/// the default match style.
+const PATTERN_PALETTE: [AnsiColor; 6] = [
+    AnsiColor::Red,
+    AnsiColor::Green,
+    AnsiColor::Yellow,
+    AnsiColor::Blue,
+    AnsiColor::Magenta,
+    AnsiColor::Cyan,
+];
+
 #[derive(Debug, Eq, PartialEq)]
 pub(crate) struct Capabilities {
     pub(super) match_text: Option<Style>,
     pub(super) filename: Option<Style>,
     pub(super) line_number: Option<Style>,
+    pub(super) record_number: Option<Style>,
     pub(super) separator: Option<Style>,
+    /// Lines of a selected record which contain a match.
+    pub(super) selected_line: Option<Style>,
+    /// Lines of a selected record which don't contain a match.
+    pub(super) context_line: Option<Style>,
+    /// Match styles by pattern ID, cycled through; if empty, every pattern's
+    /// matches use `match_text`.
+    pub(super) pattern_matches: Vec<Style>,
 }
 
 impl Capabilities {
@@ -22,6 +43,26 @@ impl Capabilities {
         }
         Capabilities::default()
     }
+
+    /// Style each pattern's matches in its own color.
+    pub(crate) fn with_pattern_palette(self) -> Capabilities {
+        Capabilities {
+            pattern_matches: PATTERN_PALETTE
+                .iter()
+                .map(|&c| Style::new().bold().fg_color(Some(c.into())))
+                .collect(),
+            ..self
+        }
+    }
+
+    /// The style of a pattern's matches.
+    pub(super) fn pattern_match(&self, pattern: usize) -> Option<Style> {
+        if self.pattern_matches.is_empty() {
+            self.match_text
+        } else {
+            Some(self.pattern_matches[pattern % self.pattern_matches.len()])
+        }
+    }
 }
 
 impl Default for Capabilities {
@@ -30,7 +71,11 @@ impl Default for Capabilities {
             match_text: Some(Style::new().bold().fg_color(Some(AnsiColor::Red.into()))),
             filename: Some(Style::new().fg_color(Some(AnsiColor::Magenta.into()))),
             line_number: Some(Style::new().fg_color(Some(AnsiColor::Green.into()))),
+            record_number: Some(Style::new().fg_color(Some(AnsiColor::Green.into()))),
             separator: Some(Style::new().fg_color(Some(AnsiColor::Cyan.into()))),
+            selected_line: None,
+            context_line: None,
+            pattern_matches: Vec::new(),
         }
     }
 }
@@ -53,20 +98,38 @@ impl FromStr for Capabilities {
                 caps.filename = parse_style(tail)
             } else if let Some(tail) = part.strip_prefix("ln=") {
                 caps.line_number = parse_style(tail)
+            } else if let Some(tail) = part.strip_prefix("rn=") {
+                caps.record_number = parse_style(tail)
             } else if let Some(tail) = part.strip_prefix("se=") {
                 caps.separator = parse_style(tail)
+            } else if let Some(tail) = part.strip_prefix("sl=") {
+                caps.selected_line = parse_style(tail)
+            } else if let Some(tail) = part.strip_prefix("cx=") {
+                caps.context_line = parse_style(tail)
             }
         }
         Ok(caps)
     }
 }
 
+/// The tint of a record at a level, for '--color-by-level': dim for the
+/// chatty levels, yellow for warnings, and red for errors.
+pub(crate) fn level_style(level: Level) -> Option<Style> {
+    match level {
+        Level::Trace | Level::Debug => Some(Style::new().dimmed()),
+        Level::Info => None,
+        Level::Warn => Some(Style::new().fg_color(Some(AnsiColor::Yellow.into()))),
+        Level::Error | Level::Fatal => Some(Style::new().fg_color(Some(AnsiColor::Red.into()))),
+    }
+}
+
 fn parse_style(str: &str) -> Option<Style> {
     let mut result = Style::new();
     for part in str.split(';') {
         if let Ok(i) = part.parse::<u8>() {
             result = match i {
                 1 => result.bold(),
+                2 => result.dimmed(),
                 4 => result.underline(),
                 5 => result.blink(),
                 7 => result.invert(),
@@ -121,6 +184,20 @@ mod test {
 
     use super::*;
 
+    #[test]
+    fn pattern_palette() {
+        let cs: Capabilities = "mt=35".parse().unwrap();
+        assert_eq!(cs.match_text, cs.pattern_match(3));
+        let cs = cs.with_pattern_palette();
+        let red = Style::new().bold().fg_color(Some(AnsiColor::Red.into()));
+        assert_eq!(Some(red), cs.pattern_match(0));
+        assert_eq!(
+            Some(Style::new().bold().fg_color(Some(Green.into()))),
+            cs.pattern_match(1)
+        );
+        assert_eq!(Some(red), cs.pattern_match(PATTERN_PALETTE.len()));
+    }
+
     #[test]
     fn parse_empty() {
         assert_eq!(Ok(Capabilities::default()), "".parse())
@@ -183,6 +260,14 @@ mod test {
         );
     }
 
+    #[test]
+    fn parse_rn() {
+        assert_eq!(
+            Some(Style::new().fg_color(Some(Magenta.into()))),
+            "rn=35".parse::<Capabilities>().unwrap().record_number
+        );
+    }
+
     #[test]
     fn parse_se() {
         assert_eq!(
@@ -190,4 +275,14 @@ mod test {
             "se=32".parse::<Capabilities>().unwrap().separator
         );
     }
+
+    #[test]
+    fn parse_sl_cx() {
+        let caps = "sl=44:cx=2".parse::<Capabilities>().unwrap();
+        assert_eq!(
+            Some(Style::new().bg_color(Some(AnsiColor::Blue.into()))),
+            caps.selected_line
+        );
+        assert_eq!(Some(Style::new().dimmed()), caps.context_line);
+    }
 }
@@ -0,0 +1,165 @@
+use std::io::Write;
+
+use anyhow::Result;
+use clap::ValueEnum;
+use flate2::write::GzEncoder;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+pub(crate) enum OutputCompression {
+    Gzip,
+    Zstd,
+}
+
+impl OutputCompression {
+    /// The compression implied by an output file's extension, if any.
+    pub(crate) fn for_filename(filename: &str) -> Option<OutputCompression> {
+        if filename.ends_with(".gz") {
+            Some(OutputCompression::Gzip)
+        } else if filename.ends_with(".zst") {
+            Some(OutputCompression::Zstd)
+        } else {
+            None
+        }
+    }
+
+    /// Wrap a writer in a compressing encoder. The compressed stream must be
+    /// ended with [Encoder::finish].
+    pub(crate) fn encoder(self, inner: Box<dyn Write>) -> Result<Encoder> {
+        Ok(match self {
+            OutputCompression::Gzip => {
+                Encoder::Gzip(GzEncoder::new(inner, flate2::Compression::default()))
+            }
+            OutputCompression::Zstd => {
+                Encoder::Zstd(zstd::Encoder::new(inner, zstd::DEFAULT_COMPRESSION_LEVEL)?)
+            }
+        })
+    }
+}
+
+/// An output, compressed or not.
+///
+/// Encoders flush by ending the current block, which would wreck compression
+/// if done after every record, as [LgrepWrite] does. So a compressed output
+/// doesn't flush until it's finished.
+///
+/// [LgrepWrite]: crate::write::LgrepWrite
+pub(crate) enum Encoder {
+    Plain(Box<dyn Write>),
+    Gzip(GzEncoder<Box<dyn Write>>),
+    Zstd(zstd::Encoder<'static, Box<dyn Write>>),
+}
+
+impl Encoder {
+    /// Write the end of a compressed stream, and flush it. Any error doing so
+    /// is returned, unlike if it were left to drop.
+    pub(crate) fn finish(&mut self) -> std::io::Result<()> {
+        match self {
+            Encoder::Plain(w) => w.flush(),
+            Encoder::Gzip(e) => e.try_finish().and_then(|_| e.get_mut().flush()),
+            Encoder::Zstd(e) => e.do_finish().and_then(|_| e.get_mut().flush()),
+        }
+    }
+}
+
+impl Write for Encoder {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Encoder::Plain(w) => w.write(buf),
+            Encoder::Gzip(e) => e.write(buf),
+            Encoder::Zstd(e) => e.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Encoder::Plain(w) => w.flush(),
+            Encoder::Gzip(_) | Encoder::Zstd(_) => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Read;
+    use std::sync::{Arc, Mutex};
+
+    use flate2::read::GzDecoder;
+
+    use super::*;
+
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn compress(c: OutputCompression, text: &str) -> Vec<u8> {
+        let buf = Shared::default();
+        let mut enc = c.encoder(Box::new(buf.clone())).unwrap();
+        enc.write_all(text.as_bytes()).unwrap();
+        enc.finish().unwrap();
+        drop(enc);
+        let bytes = buf.0.lock().unwrap().clone();
+        bytes
+    }
+
+    /// Fills up after a header's worth of bytes, like a full disk.
+    struct Full(usize);
+
+    impl Write for Full {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            if self.0 + buf.len() > 10 {
+                return Err(std::io::Error::other("No space left on device"));
+            }
+            self.0 += buf.len();
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn finish_reports_errors() {
+        for c in [OutputCompression::Gzip, OutputCompression::Zstd] {
+            let mut enc = c.encoder(Box::new(Full(0))).unwrap();
+            enc.write_all(b"one\n").unwrap();
+            assert!(enc.finish().is_err(), "{c:?}");
+        }
+    }
+
+    #[test]
+    fn for_filename() {
+        use OutputCompression::*;
+        assert_eq!(Some(Gzip), OutputCompression::for_filename("out.log.gz"));
+        assert_eq!(Some(Zstd), OutputCompression::for_filename("out.log.zst"));
+        assert_eq!(None, OutputCompression::for_filename("out.log"));
+    }
+
+    #[test]
+    fn gzip_round_trip() {
+        let bytes = compress(OutputCompression::Gzip, "one\ntwo\n");
+        let mut text = String::new();
+        GzDecoder::new(&bytes[..])
+            .read_to_string(&mut text)
+            .unwrap();
+        assert_eq!("one\ntwo\n", text);
+    }
+
+    #[test]
+    fn zstd_round_trip() {
+        let bytes = compress(OutputCompression::Zstd, "one\ntwo\n");
+        assert_eq!(
+            b"one\ntwo\n".to_vec(),
+            zstd::decode_all(&bytes[..]).unwrap()
+        );
+    }
+}
@@ -0,0 +1,295 @@
+use std::ops::Range;
+
+use serde::Serialize;
+
+use crate::read::records::Record;
+
+/// The version of the JSON lines' schema, which every line carries as its
+/// `schema`. It's bumped whenever a field is removed or changes meaning, but
+/// not for fields which are added.
+pub(crate) const SCHEMA_VERSION: u32 = 1;
+
+/// The JSON Schema document for the lines written with '--json'.
+pub(crate) const SCHEMA: &str = include_str!("schema.json");
+
+/// A line of JSON, stamped with the schema version it follows.
+#[derive(Debug, Serialize)]
+pub(crate) struct Versioned<'a, T> {
+    schema: u32,
+    #[serde(flatten)]
+    value: &'a T,
+}
+
+impl<'a, T> Versioned<'a, T> {
+    pub(crate) fn new(value: &'a T) -> Versioned<'a, T> {
+        Versioned {
+            schema: SCHEMA_VERSION,
+            value,
+        }
+    }
+}
+
+/// A selected record, as a line of JSON. The text is as it would be printed
+/// (prefixes and all, less color), with the styled parts described by spans
+/// instead of escape sequences.
+#[derive(Debug, Serialize)]
+pub(crate) struct JsonRecord<'a> {
+    file: &'a str,
+    record: usize,
+    line: usize,
+    /// Byte offset of the record in the file, after any decompression.
+    offset: u64,
+    text: String,
+    spans: Vec<Span>,
+    /// Labels of the patterns which matched, if they're labeled.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    patterns: Vec<&'a str>,
+}
+
+/// A file with selected records.
+#[derive(Debug, Serialize)]
+pub(crate) struct JsonFile<'a> {
+    pub(crate) file: &'a str,
+}
+
+/// A count of selected records in a file.
+#[derive(Debug, Serialize)]
+pub(crate) struct JsonCount<'a> {
+    pub(crate) file: &'a str,
+    pub(crate) count: usize,
+}
+
+/// A count of selected records with a '--count-by' value.
+#[derive(Debug, Serialize)]
+pub(crate) struct JsonTally<'a> {
+    pub(crate) value: &'a str,
+    pub(crate) count: usize,
+}
+
+/// How many distinct values a '--count-distinct' group took.
+#[derive(Debug, Serialize)]
+pub(crate) struct JsonDistinct {
+    pub(crate) distinct: usize,
+    /// The relative standard error of an approximate count.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) error: Option<f64>,
+}
+
+#[derive(Debug, Eq, PartialEq, Serialize)]
+struct Span {
+    /// Byte offset into the text.
+    offset: usize,
+    length: usize,
+    kind: SpanKind,
+}
+
+#[derive(Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum SpanKind {
+    Match,
+    Filename,
+    Record,
+    Line,
+}
+
+impl<'a> JsonRecord<'a> {
+    /// Render a record, whose matches are at the given byte ranges of its
+    /// text.
+    pub(crate) fn new(
+        filename: &'a str,
+        record: &Record,
+        matches: &[Range<usize>],
+        filenames: bool,
+        record_numbers: bool,
+        line_numbers: bool,
+        mut annotation: Option<&str>,
+    ) -> JsonRecord<'a> {
+        let mut text = String::with_capacity(record.text.len() + 20);
+        let mut spans = Vec::with_capacity(matches.len());
+        let push_span = |text: &mut String, spans: &mut Vec<Span>, s: &str, kind| {
+            spans.push(Span {
+                offset: text.len(),
+                length: s.len(),
+                kind,
+            });
+            text.push_str(s);
+        };
+        let mut separator = ':';
+        let mut line_start = 0;
+        for (line_num, l) in (record.first_line..).zip(record.text.split('\n')) {
+            if line_start > 0 {
+                text.push('\n');
+            }
+            if filenames {
+                push_span(&mut text, &mut spans, filename, SpanKind::Filename);
+                text.push(separator);
+            }
+            if record_numbers {
+                let record_num = record.record_num.to_string();
+                push_span(&mut text, &mut spans, &record_num, SpanKind::Record);
+                text.push(separator);
+            }
+            if line_numbers {
+                push_span(&mut text, &mut spans, &line_num.to_string(), SpanKind::Line);
+                text.push(separator);
+            }
+            if let Some(a) = annotation.take() {
+                text.push_str(a);
+                text.push(' ');
+            }
+            let line_end = line_start + l.len();
+            let base = text.len();
+            text.push_str(l);
+            for m in matches {
+                let start = m.start.max(line_start);
+                let end = m.end.min(line_end);
+                if start < end {
+                    spans.push(Span {
+                        offset: base + start - line_start,
+                        length: end - start,
+                        kind: SpanKind::Match,
+                    });
+                }
+            }
+            line_start = line_end + 1;
+            separator = '-';
+        }
+        JsonRecord {
+            file: filename,
+            record: record.record_num,
+            line: record.first_line,
+            offset: record.offset,
+            text,
+            spans,
+            patterns: Vec::new(),
+        }
+    }
+
+    pub(crate) fn with_patterns(self, patterns: Vec<&'a str>) -> Self {
+        JsonRecord { patterns, ..self }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::slice;
+
+    use super::*;
+
+    #[test]
+    fn plain() {
+        let r = Record::new("one two\nthree", 4, 7).at(83);
+        assert_eq!(
+            r#"{"file":"app.log","record":4,"line":7,"offset":83,"text":"one two\nthree","spans":[{"offset":4,"length":3,"kind":"match"}]}"#,
+            serde_json::to_string(&JsonRecord::new(
+                "app.log",
+                &r,
+                slice::from_ref(&(4..7)),
+                false,
+                false,
+                false,
+                None
+            ))
+            .unwrap()
+        );
+    }
+
+    #[test]
+    fn prefixed_multi_line_match() {
+        let r = Record::new("one two\nthree", 1, 7);
+        let j = JsonRecord::new(
+            "a.log",
+            &r,
+            slice::from_ref(&(4..10)),
+            true,
+            false,
+            true,
+            Some("[+0]"),
+        );
+        assert_eq!("a.log:7:[+0] one two\na.log-8-three", j.text);
+        assert_eq!(
+            vec![
+                Span {
+                    offset: 0,
+                    length: 5,
+                    kind: SpanKind::Filename
+                },
+                Span {
+                    offset: 6,
+                    length: 1,
+                    kind: SpanKind::Line
+                },
+                Span {
+                    offset: 17,
+                    length: 3,
+                    kind: SpanKind::Match
+                },
+                Span {
+                    offset: 21,
+                    length: 5,
+                    kind: SpanKind::Filename
+                },
+                Span {
+                    offset: 27,
+                    length: 1,
+                    kind: SpanKind::Line
+                },
+                Span {
+                    offset: 29,
+                    length: 2,
+                    kind: SpanKind::Match
+                },
+            ],
+            j.spans
+        );
+    }
+
+    #[test]
+    fn versioned() {
+        assert_eq!(
+            r#"{"schema":1,"file":"app.log","count":3}"#,
+            serde_json::to_string(&Versioned::new(&JsonCount {
+                file: "app.log",
+                count: 3
+            }))
+            .unwrap()
+        );
+        let schema: serde_json::Value = serde_json::from_str(SCHEMA).unwrap();
+        assert_eq!(
+            serde_json::json!(SCHEMA_VERSION),
+            schema["properties"]["schema"]["const"]
+        );
+    }
+
+    #[test]
+    fn record_numbers() {
+        let r = Record::new("one\ntwo", 12, 40);
+        let j = JsonRecord::new("a.log", &r, &[], false, true, true, None);
+        assert_eq!("12:40:one\n12-41-two", j.text);
+        assert_eq!(
+            vec![
+                Span {
+                    offset: 0,
+                    length: 2,
+                    kind: SpanKind::Record
+                },
+                Span {
+                    offset: 3,
+                    length: 2,
+                    kind: SpanKind::Line
+                },
+                Span {
+                    offset: 10,
+                    length: 2,
+                    kind: SpanKind::Record
+                },
+                Span {
+                    offset: 13,
+                    length: 2,
+                    kind: SpanKind::Line
+                },
+            ],
+            j.spans
+        );
+    }
+}
@@ -0,0 +1,99 @@
+/// How alike records must be for '--diff-similar': this share of their lines
+/// must be the same, once numbers are masked.
+const SIMILAR_LINES: f64 = 0.8;
+
+/// The last record written in full, for '--diff-similar', so later records
+/// which are much the same (e.g., a loop's errors, with their stack traces)
+/// can be written as just their differences from it.
+#[derive(Debug, Default)]
+pub(crate) struct Similar {
+    last_full: Option<(String, String)>,
+}
+
+impl Similar {
+    /// The last record written in full, if a file's record is similar to it.
+    /// Otherwise, the record will be written in full, so it becomes the one
+    /// later records are compared with.
+    pub(crate) fn base_for(&mut self, filename: &str, text: &str) -> Option<String> {
+        if let Some((f, base)) = &self.last_full {
+            if f == filename && is_similar(base, text) {
+                return Some(base.clone());
+            }
+        }
+        self.last_full = Some((filename.to_owned(), text.to_owned()));
+        None
+    }
+}
+
+/// Whether two records have the same number of lines, and most of them are
+/// the same, but for their numbers (timestamps, IDs, etc.).
+fn is_similar(a: &str, b: &str) -> bool {
+    let (a, b): (Vec<_>, Vec<_>) = (a.split('\n').collect(), b.split('\n').collect());
+    if a.len() != b.len() {
+        return false;
+    }
+    let alike = a
+        .iter()
+        .zip(&b)
+        .filter(|(x, y)| x == y || mask_numbers(x) == mask_numbers(y))
+        .count();
+    alike as f64 >= a.len() as f64 * SIMILAR_LINES
+}
+
+/// Replace each run of (hex) digits containing a decimal digit with a `#`.
+pub(crate) fn mask_numbers(line: &str) -> String {
+    let mut result = String::with_capacity(line.len());
+    let mut run = String::new();
+    let flush = |run: &mut String, result: &mut String| {
+        if run.chars().any(|c| c.is_ascii_digit()) {
+            result.push('#');
+        } else {
+            result.push_str(run);
+        }
+        run.clear();
+    };
+    for c in line.chars() {
+        if c.is_ascii_hexdigit() {
+            run.push(c);
+        } else {
+            flush(&mut run, &mut result);
+            result.push(c);
+        }
+    }
+    flush(&mut run, &mut result);
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn masking() {
+        assert_eq!(
+            "#-#-# #:#:# user=# req=# failed",
+            mask_numbers("2024-07-01 01:25:47 user=42 req=9f3a failed")
+        );
+        assert_eq!("deadbeef fade", mask_numbers("deadbeef fade"));
+    }
+
+    #[test]
+    fn bases() {
+        let mut similar = Similar::default();
+        let first = "12:00 failed for 1\n  at a\n  at b\n  at c\n  at d";
+        assert_eq!(None, similar.base_for("a.log", first));
+        // just numbers differ
+        let second = "12:01 failed for 2\n  at a\n  at b\n  at c\n  at e";
+        assert_eq!(Some(first.to_owned()), similar.base_for("a.log", second));
+        // still compared with the first
+        assert_eq!(Some(first.to_owned()), similar.base_for("a.log", first));
+        // not in another file
+        assert_eq!(None, similar.base_for("b.log", second));
+        // too different
+        assert_eq!(
+            None,
+            similar.base_for("b.log", "12:02 ok\n  x\n  y\n  at c\n  at e")
+        );
+        assert_eq!(None, similar.base_for("b.log", "12:02 shorter"));
+    }
+}
@@ -0,0 +1,127 @@
+use std::collections::{BTreeSet, HashMap};
+
+use clap::builder::styling::{AnsiColor, Style};
+use unicode_width::UnicodeWidthStr;
+
+use crate::read::rotation_base;
+use crate::stable_hash;
+
+/// Tag colors, like `docker compose`.
+const PALETTE: [AnsiColor; 10] = [
+    AnsiColor::Cyan,
+    AnsiColor::Yellow,
+    AnsiColor::Green,
+    AnsiColor::Magenta,
+    AnsiColor::Blue,
+    AnsiColor::BrightCyan,
+    AnsiColor::BrightYellow,
+    AnsiColor::BrightGreen,
+    AnsiColor::BrightMagenta,
+    AnsiColor::BrightBlue,
+];
+
+/// Short labels for files, each with its own color, so interleaved output
+/// from several sources can be told apart at a glance. A tag's color comes
+/// from its hash, so it's the same from run to run, unless another tag has
+/// already claimed it.
+#[derive(Debug)]
+pub(crate) struct Tags {
+    width: usize,
+    tags: HashMap<String, (String, Style)>,
+}
+
+impl Tags {
+    /// Tag each file with its name, less directories, extension, and any
+    /// rotation suffix. So all of a log's rotated files share a tag.
+    pub(crate) fn for_files<'a>(filenames: impl IntoIterator<Item = &'a str>) -> Tags {
+        let filenames: Vec<_> = filenames.into_iter().collect();
+        // claimed in name order, so the order of the files doesn't matter
+        let mut colors = HashMap::new();
+        for tag in filenames
+            .iter()
+            .map(|f| tag_for(f))
+            .collect::<BTreeSet<_>>()
+        {
+            let mut i = (stable_hash(&tag) % PALETTE.len() as u64) as usize;
+            while colors.len() < PALETTE.len() && colors.values().any(|&c| c == i) {
+                i = (i + 1) % PALETTE.len();
+            }
+            colors.insert(tag, i);
+        }
+        let mut tags = HashMap::new();
+        for f in filenames {
+            let tag = tag_for(f);
+            let color = PALETTE[colors[&tag]];
+            tags.insert(
+                f.to_owned(),
+                (tag, Style::new().fg_color(Some(color.into()))),
+            );
+        }
+        Tags {
+            width: tags.values().map(|(t, _)| t.width()).max().unwrap_or(0),
+            tags,
+        }
+    }
+
+    /// The tag and its style for a file, padded to the widest tag's display
+    /// width, so a wide (e.g., CJK) name lines up with the rest.
+    pub(crate) fn get(&self, filename: &str) -> (String, Option<Style>) {
+        let (tag, style) = match self.tags.get(filename) {
+            Some((tag, style)) => (tag.as_str(), Some(*style)),
+            None => (filename, None),
+        };
+        let pad = self.width.saturating_sub(tag.width());
+        (format!("{tag}{:pad$}", ""), style)
+    }
+}
+
+fn tag_for(filename: &str) -> String {
+    let base = rotation_base(filename);
+    let base = base.rsplit('/').next().unwrap_or(base);
+    let base = base.strip_suffix(".log").unwrap_or(base);
+    if base.is_empty() {
+        filename.to_owned()
+    } else {
+        base.to_owned()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn tags() {
+        assert_eq!("app", tag_for("app.log"));
+        assert_eq!("api", tag_for("/var/log/api.log.2.gz"));
+        assert_eq!("db.txt", tag_for("logs/db.txt"));
+        assert_eq!("(standard input)", tag_for("(standard input)"));
+    }
+
+    #[test]
+    fn padded_and_colored() {
+        let tags = Tags::for_files(["api.log.1", "api.log", "worker.log"]);
+        let (tag, api) = tags.get("api.log.1");
+        assert_eq!("api   ", tag);
+        assert_eq!(api, tags.get("api.log").1);
+        let (tag, worker) = tags.get("worker.log");
+        assert_eq!("worker", tag);
+        assert_ne!(api, worker);
+    }
+
+    #[test]
+    fn padded_by_width() {
+        let tags = Tags::for_files(["決済.log", "api.log"]);
+        assert_eq!("決済", tags.get("決済.log").0);
+        assert_eq!("api ", tags.get("api.log").0);
+    }
+
+    #[test]
+    fn stable_colors() {
+        let alone = Tags::for_files(["db.log"]);
+        let among = Tags::for_files(["web.log", "db.log.1", "app.log"]);
+        assert_eq!(alone.get("db.log").1, among.get("db.log.1").1);
+        let reordered = Tags::for_files(["app.log", "db.log.1", "web.log"]);
+        assert_eq!(among.get("web.log").1, reordered.get("web.log").1);
+    }
+}
@@ -0,0 +1,133 @@
+use std::borrow::Cow;
+
+use anyhow::{bail, Result};
+
+/// A '--format' template, like `{file}:{line}: {text}`: literal text, with
+/// fields in braces. A literal brace is doubled (`{{` or `}}`).
+#[derive(Debug, Eq, PartialEq)]
+pub(crate) struct Template<F> {
+    parts: Vec<Part<F>>,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+enum Part<F> {
+    Literal(String),
+    Field(F),
+}
+
+impl Template<String> {
+    /// Parse a template, with its fields as their names.
+    pub(crate) fn parse(s: &str) -> Result<Template<String>> {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut chars = s.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some('{') | None => bail!("Unclosed '{{' in template '{s}'"),
+                            Some(c) => name.push(c),
+                        }
+                    }
+                    if name.is_empty() {
+                        bail!("Empty field '{{}}' in template '{s}'");
+                    }
+                    if !literal.is_empty() {
+                        parts.push(Part::Literal(std::mem::take(&mut literal)));
+                    }
+                    parts.push(Part::Field(name));
+                }
+                '}' => bail!("Unmatched '}}' in template '{s}'; use '}}}}' for a literal one"),
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            parts.push(Part::Literal(literal));
+        }
+        Ok(Template { parts })
+    }
+}
+
+impl<F> Template<F> {
+    /// Resolve each field, e.g., from its name to what it refers to.
+    pub(crate) fn try_map<G>(self, mut f: impl FnMut(F) -> Result<G>) -> Result<Template<G>> {
+        let parts = self
+            .parts
+            .into_iter()
+            .map(|p| {
+                Ok(match p {
+                    Part::Literal(s) => Part::Literal(s),
+                    Part::Field(field) => Part::Field(f(field)?),
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Template { parts })
+    }
+
+    /// Fill in the template, with each field's value. A field without one is
+    /// left empty.
+    pub(crate) fn render<'v>(&self, value: impl Fn(&F) -> Option<Cow<'v, str>>) -> String {
+        let mut result = String::new();
+        for p in &self.parts {
+            match p {
+                Part::Literal(s) => result.push_str(s),
+                Part::Field(f) => {
+                    if let Some(v) = value(f) {
+                        result.push_str(&v)
+                    }
+                }
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_and_render() {
+        let t = Template::parse("{file}:{line}: {{{user}}} {0}").unwrap();
+        let rendered = t.render(|f| match f.as_str() {
+            "file" => Some("app.log".into()),
+            "line" => Some(12.to_string().into()),
+            "user" => Some("bob".into()),
+            _ => None,
+        });
+        assert_eq!("app.log:12: {bob} ", rendered);
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(Template::parse("{file").is_err());
+        assert!(Template::parse("{}").is_err());
+        assert!(Template::parse("a } b").is_err());
+        assert!(Template::parse("{a{b}").is_err());
+        assert!(Template::parse("plain").is_ok());
+    }
+
+    #[test]
+    fn mapped() {
+        let t = Template::parse("{a}-{b}")
+            .unwrap()
+            .try_map(|n| Ok(n.len()))
+            .unwrap();
+        assert_eq!("-", t.render(|_| None));
+        assert!(Template::parse("{a}")
+            .unwrap()
+            .try_map(|n| -> Result<()> { bail!("no {n}") })
+            .is_err());
+    }
+}